@@ -0,0 +1,1835 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::{HashMap, HashSet},
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    build_config::{
+        find_in_path, is_cxx_header, is_cxx_source, normalize_extension, BuildConfig,
+        CustomBuildRule, CustomBuildRuleType, DependencyRef, LibraryKind, PkgConfigDependency,
+        Profile, ResolvedSettings, SubProject, SubProjectType, Toolchain, MAX_PARALLEL_JOBS,
+    },
+    error::{json_string, Error, ErrorType},
+    logd, loge, logi, logv, logw,
+    package::DEPS_DIR,
+    util,
+};
+
+// Root directory for all build artifacts
+pub const BUILD_DIR: &str = "build";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildMode {
+    Debug,
+    Release,
+    // A profile from [profiles], by name
+    Profile(String),
+}
+
+impl BuildMode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BuildMode::Debug => "debug",
+            BuildMode::Release => "release",
+            BuildMode::Profile(name) => name,
+        }
+    }
+}
+
+// Counting semaphore bounding the number of compiler processes running at once
+struct JobSlots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl JobSlots {
+    fn new(jobs: usize) -> Self {
+        Self {
+            free: Mutex::new(jobs),
+            released: Condvar::new(),
+        }
+    }
+
+    fn run<T>(&self, job: impl FnOnce() -> T) -> T {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            free = self.released.wait(free).unwrap();
+        }
+        *free -= 1;
+        drop(free);
+
+        let _slot = TakenSlot(self);
+        job()
+    }
+}
+
+// Gives its slot back when dropped, even when the job panicked
+struct TakenSlot<'a>(&'a JobSlots);
+
+impl Drop for TakenSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+// Outcome of building a single subproject
+#[derive(Debug, Clone)]
+pub struct BuildReport {
+    pub name: String,
+    // Whether anything was compiled or linked, false when it was up to date
+    pub rebuilt: bool,
+    // Number of sources compiled
+    pub compiled: usize,
+    pub duration: Duration,
+}
+
+// Everything a build needs to know besides the config itself
+pub struct BuildContext<'a> {
+    pub config: &'a BuildConfig,
+    pub mode: BuildMode,
+    pub jobs: usize,
+    // Rebuild everything instead of only what changed
+    pub force: bool,
+    // Target triple to cross compile for, None builds for the host
+    pub target: Option<String>,
+    // Print the commands that would run instead of running them
+    pub dry_run: bool,
+    // Preset given on the command line, applied to every subproject
+    pub preset: Option<String>,
+    // Compile the C sources of subprojects that don't say otherwise as one unit
+    pub unity: bool,
+    // Build every subproject not depending on a failed one instead of stopping at the
+    // first failure
+    pub keep_going: bool,
+    // Flags reported by pkg-config, keyed by query and whether it links statically,
+    // so a changed query is asked again
+    pkg_config: Mutex<HashMap<(String, bool), PkgConfigFlags>>,
+    // ccache or the like, compiles run through it while links don't
+    launcher: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct PkgConfigFlags {
+    cflags: Vec<String>,
+    libs: Vec<String>,
+}
+
+impl<'a> BuildContext<'a> {
+    pub fn new(config: &'a BuildConfig, mode: BuildMode) -> Self {
+        Self {
+            config,
+            mode,
+            jobs: job_count(config, None),
+            force: false,
+            target: config.default_target().map(str::to_string),
+            dry_run: false,
+            preset: None,
+            unity: false,
+            keep_going: false,
+            pkg_config: Mutex::new(HashMap::new()),
+            launcher: config.build.compiler_launcher(),
+        }
+    }
+
+    // Compile and link flags of a pkg-config dependency, only running pkg-config the
+    // first time a query is seen
+    fn pkg_config_flags(&self, dep: &PkgConfigDependency) -> Result<PkgConfigFlags, Error> {
+        let query = &dep.pkg_config_query;
+        let is_static = dep.r#static.unwrap_or(false);
+        let key = (query.get_ref().clone(), is_static);
+        if let Some(flags) = self.pkg_config.lock().unwrap().get(&key) {
+            return Ok(flags.clone());
+        }
+        let run = |option: &str| {
+            let output = Command::new("pkg-config")
+                .args(is_static.then_some("--static"))
+                .arg(option)
+                .arg(query.get_ref())
+                .output();
+            match output {
+                Ok(output) if output.status.success() => {
+                    Ok(String::from_utf8_lossy(&output.stdout)
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect())
+                }
+                _ => Err(Error {
+                    error_type: ErrorType::InvalidPkgConfigQuery,
+                    message: format!("`pkg-config {} {}` failed", option, query.get_ref()),
+                    span: Some(query.span()),
+                    additional_info: None,
+                }),
+            }
+        };
+        let mut libs: Vec<String> = run("--libs")?;
+        // Prefer archives for the libraries of this dependency only
+        if is_static {
+            libs.insert(0, "-Wl,-Bstatic".to_string());
+            libs.push("-Wl,-Bdynamic".to_string());
+        }
+        let flags = PkgConfigFlags {
+            cflags: run("--cflags")?,
+            libs,
+        };
+        self.pkg_config.lock().unwrap().insert(key, flags.clone());
+        Ok(flags)
+    }
+
+    // Command invoking the compiler for the build target, clang is told the target
+    // with --target while other compilers are expected to be prefixed with the triple
+    fn compiler_command(&self, compiler: &str) -> Command {
+        let mut cmd = Command::new(target_compiler(compiler, self.target.as_deref()));
+        if let Some(target) = &self.target {
+            if is_clang(compiler) {
+                cmd.arg(format!("--target={}", target));
+            }
+        }
+        if let Some(sysroot) = self
+            .toolchain()
+            .and_then(|toolchain| toolchain.sysroot.as_ref())
+        {
+            cmd.arg(format!("--sysroot={}", sysroot.get_ref()));
+        }
+        cmd
+    }
+
+    // Command compiling with the compiler, through the launcher if there is one
+    fn compile_command(&self, compiler: &str) -> Command {
+        let cmd = self.compiler_command(compiler);
+        let Some(launcher) = &self.launcher else {
+            return cmd;
+        };
+        let mut launched = Command::new(launcher);
+        launched.arg(cmd.get_program()).args(cmd.get_args());
+        launched
+    }
+
+    // Leading arguments of a compile command that aren't the compiler's: the compiler
+    // itself when it runs through the launcher
+    fn launcher_args(&self) -> usize {
+        usize::from(self.launcher.is_some())
+    }
+
+    fn toolchain(&self) -> Option<&Toolchain> {
+        self.config.toolchain.as_ref()
+    }
+
+    // Archiver of the toolchain, or the one for the build target, falling back to the
+    // host ar
+    fn archiver(&self) -> String {
+        if let Some(ar) = self.toolchain().and_then(|toolchain| toolchain.ar.as_ref()) {
+            return ar.get_ref().clone();
+        }
+        self.target
+            .as_ref()
+            .map(|target| format!("{}-ar", target))
+            .filter(|ar| find_in_path(ar).is_some())
+            .unwrap_or_else(|| "ar".to_string())
+    }
+}
+
+fn is_clang(compiler: &str) -> bool {
+    Path::new(compiler)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("clang"))
+}
+
+// Name of the compiler to run when building for target, compilers already prefixed with
+// the triple are run as they are
+pub fn target_compiler(compiler: &str, target: Option<&str>) -> String {
+    let path = Path::new(compiler);
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    match target {
+        Some(target)
+            if !is_clang(compiler)
+                && !name.to_string_lossy().starts_with(&format!("{}-", target)) =>
+        {
+            path.with_file_name(format!("{}-{}", target, name.to_string_lossy()))
+                .to_string_lossy()
+                .to_string()
+        }
+        _ => compiler.to_string(),
+    }
+}
+
+// Number of parallel jobs, from the command line, then the config, then the CPU count
+pub fn job_count(config: &BuildConfig, requested: Option<u32>) -> usize {
+    requested
+        .or(config
+            .build
+            .parallel_jobs
+            .as_ref()
+            .map(|jobs| *jobs.get_ref()))
+        .map(|jobs| jobs.min(MAX_PARALLEL_JOBS) as usize)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .max(1)
+}
+
+// Directory holding the artifacts of a given build mode, cross builds get their own
+pub fn output_dir(target: Option<&str>, mode: &BuildMode) -> PathBuf {
+    match target {
+        Some(target) => Path::new(BUILD_DIR).join(target).join(mode.as_str()),
+        None => Path::new(BUILD_DIR).join(mode.as_str()),
+    }
+}
+
+// Path of the file produced by a subproject, header-only subprojects produce nothing.
+// Libraries built both ways produce their static archive here.
+pub fn artifact_path(
+    subproject: &SubProject,
+    target: Option<&str>,
+    mode: &BuildMode,
+) -> Option<PathBuf> {
+    let name = subproject.output_name();
+    let dir = output_dir(target, mode);
+    match subproject.r#type {
+        SubProjectType::Binary => Some(dir.join("bin").join(name)),
+        SubProjectType::Test => Some(dir.join("tests").join(name)),
+        SubProjectType::Library => match library_kind(subproject) {
+            LibraryKind::Static | LibraryKind::Both => {
+                Some(dir.join("lib").join(format!("lib{}.a", name)))
+            }
+            LibraryKind::Shared => shared_library_path(subproject, target, mode),
+        },
+        SubProjectType::HeaderOnly => None,
+    }
+}
+
+// Path of the shared library of a library subproject, if it builds one
+pub fn shared_library_path(
+    subproject: &SubProject,
+    target: Option<&str>,
+    mode: &BuildMode,
+) -> Option<PathBuf> {
+    if !builds_shared_library(subproject) {
+        return None;
+    }
+    let (prefix, suffix) = shared_library_affixes(target);
+    let file_name = format!("{}{}{}", prefix, subproject.output_name(), suffix);
+    Some(output_dir(target, mode).join("lib").join(file_name))
+}
+
+// Prefix and suffix of a shared library's file name on target, those of the host when
+// building for it
+fn shared_library_affixes(target: Option<&str>) -> (&'static str, &'static str) {
+    match target {
+        Some(target) if target.contains("windows") || target.contains("mingw") => ("", ".dll"),
+        Some(target) if target.contains("apple") || target.contains("darwin") => ("lib", ".dylib"),
+        Some(_) => ("lib", ".so"),
+        None => (DLL_PREFIX, DLL_SUFFIX),
+    }
+}
+
+fn library_kind(subproject: &SubProject) -> LibraryKind {
+    subproject
+        .library_type
+        .as_ref()
+        .map(|kind| *kind.get_ref())
+        .unwrap_or_default()
+}
+
+fn builds_shared_library(subproject: &SubProject) -> bool {
+    subproject.r#type == SubProjectType::Library
+        && matches!(
+            library_kind(subproject),
+            LibraryKind::Shared | LibraryKind::Both
+        )
+}
+
+// Jobs an override explicitly allows its subproject. The global parallel_jobs and
+// --parallel are already in the context's job count.
+fn override_jobs(config: &BuildConfig, name: &str) -> Option<usize> {
+    config
+        .overrides
+        .iter()
+        .flatten()
+        .find(|over| over.name.get_ref() == name)
+        .and_then(|over| over.parallel_jobs.as_ref())
+        .map(|jobs| (*jobs.get_ref()).min(MAX_PARALLEL_JOBS) as usize)
+}
+
+fn find_subproject<'a>(config: &'a BuildConfig, name: &str) -> Option<&'a SubProject> {
+    config
+        .subprojects
+        .iter()
+        .find(|subproject| subproject.name.get_ref() == name)
+}
+
+// All subprojects a subproject depends on, directly or transitively, in build order
+fn transitive_subprojects<'a>(
+    config: &'a BuildConfig,
+    subproject: &SubProject,
+) -> Vec<&'a SubProject> {
+    let mut needed = HashSet::new();
+    let mut pending = vec![subproject.name.get_ref().clone()];
+    while let Some(name) = pending.pop() {
+        if let Some(current) = find_subproject(config, &name) {
+            for dep in current.dependencies.iter().flatten() {
+                let dep_name = dep.get_ref().name().to_string();
+                if find_subproject(config, &dep_name).is_some() && needed.insert(dep_name.clone()) {
+                    pending.push(dep_name);
+                }
+            }
+        }
+    }
+    // The config's subproject list is already in build order
+    config
+        .subprojects
+        .iter()
+        .filter(|sub| needed.contains(sub.name.get_ref()))
+        .collect()
+}
+
+// External dependencies (remote, pkg-config, manual) named directly by a subproject, in
+// the order it lists them so their link flags are too
+fn external_dependencies<'a>(
+    config: &'a BuildConfig,
+    subproject: &SubProject,
+) -> Vec<DependencyRef<'a>> {
+    subproject
+        .dependencies
+        .iter()
+        .flatten()
+        .filter_map(|dep| {
+            let name = dep.get_ref().name();
+            config.dependencies.iter().find(|dep| dep.name() == name)
+        })
+        .collect()
+}
+
+// Split a flags string into arguments like a POSIX shell would, honoring single and
+// double quotes and backslash escapes. Fails with the byte offset of an unterminated quote.
+pub fn split_shell_words(text: &str) -> Result<Vec<String>, usize> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    word.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            '\'' | '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        None => return Err(index),
+                        Some((_, quote)) if quote == c => break,
+                        // Inside double quotes a backslash only escapes " and \
+                        Some((_, '\\')) if c == '"' => match chars.clone().next() {
+                            Some((_, next @ ('"' | '\\'))) => {
+                                chars.next();
+                                current.push(next);
+                            }
+                            _ => current.push('\\'),
+                        },
+                        Some((_, other)) => current.push(other),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// Flags split into arguments, an unterminated quote falls back to splitting on whitespace
+fn split_flags(flags: &Option<String>) -> Vec<String> {
+    let flags = flags.as_deref().unwrap_or_default();
+    split_shell_words(flags)
+        .unwrap_or_else(|_| flags.split_whitespace().map(str::to_string).collect())
+}
+
+fn is_up_to_date(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match (modified(input), modified(output)) {
+        (Some(input), Some(output)) => output >= input,
+        _ => false,
+    }
+}
+
+// Files listed in a make-style dependency file emitted by `-MMD`
+fn parse_depfile(content: &str) -> Vec<PathBuf> {
+    let joined = content.replace("\\\n", " ").replace("\\\r\n", " ");
+    let Some((_, deps)) = joined.split_once(": ") else {
+        return Vec::new();
+    };
+    // Escaped spaces belong to the file name, so protect them while splitting
+    deps.replace("\\ ", "\0")
+        .split_whitespace()
+        .map(|dep| PathBuf::from(dep.replace('\0', " ")))
+        .collect()
+}
+
+// An object needs recompiling when it, or its dependency file, is missing or older
+// than the source or any header the source included last time
+fn needs_compile(source: &Path, object: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(object.with_extension("d")) else {
+        return true;
+    };
+    let mut deps = parse_depfile(&content);
+    deps.push(source.to_path_buf());
+    deps.iter().any(|dep| !is_up_to_date(dep, object))
+}
+
+// Quote an argument for a POSIX shell, leaving plain words alone
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// A command as a line that can be pasted into a shell
+pub fn command_line(cmd: &Command) -> String {
+    let mut line = shell_quote(&cmd.get_program().to_string_lossy());
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&shell_quote(&arg.to_string_lossy()));
+    }
+    line
+}
+
+// Run a command with its output captured, then print that output in one piece so the
+// output of parallel jobs never interleaves mid-line. A dry run only prints the command.
+fn run_command(mut cmd: Command, error_type: ErrorType, dry_run: bool) -> Result<(), Error> {
+    let line = command_line(&cmd);
+    if dry_run {
+        println!("{}", line);
+        return Ok(());
+    }
+    logv!("{}", line);
+    match cmd.output() {
+        Ok(output) => {
+            if !output.stdout.is_empty() {
+                std::io::stdout().lock().write_all(&output.stdout).ok();
+            }
+            if !output.stderr.is_empty() {
+                std::io::stderr().lock().write_all(&output.stderr).ok();
+            }
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(Error {
+                    error_type,
+                    message: format!("Command `{}` failed with {}", line, output.status),
+                    span: None,
+                    additional_info: None,
+                })
+            }
+        }
+        Err(e) => Err(Error {
+            error_type,
+            message: format!("Failed to run `{}`: {}", line, e),
+            span: None,
+            additional_info: None,
+        }),
+    }
+}
+
+// Arguments longer than this, in bytes, are passed through a response file. The
+// limit can be lowered with CRYO_RESPONSE_FILE_THRESHOLD to exercise that path.
+const RESPONSE_FILE_THRESHOLD: usize = 32 * 1024;
+
+static RESPONSE_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn response_file_threshold() -> usize {
+    std::env::var("CRYO_RESPONSE_FILE_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(RESPONSE_FILE_THRESHOLD)
+}
+
+// Quote an argument the way gcc, clang and ar read response files
+fn quote_response_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Run a compiler, linker or archiver, moving its arguments to an `@file` when they
+// would exceed the command line length limit of the OS. The first `kept` arguments stay
+// on the command line, a launcher has to see the compiler it runs.
+fn run_tool(cmd: Command, kept: usize, error_type: ErrorType, dry_run: bool) -> Result<(), Error> {
+    run_tool_with_threshold(cmd, kept, error_type, dry_run, response_file_threshold())
+}
+
+fn run_tool_with_threshold(
+    cmd: Command,
+    kept: usize,
+    error_type: ErrorType,
+    dry_run: bool,
+    threshold: usize,
+) -> Result<(), Error> {
+    let length: usize = cmd.get_args().map(|arg| arg.len() + 1).sum();
+    if dry_run || length <= threshold {
+        return run_command(cmd, error_type, dry_run);
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "iceforge-{}-{}.rsp",
+        std::process::id(),
+        RESPONSE_FILE_COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let content: Vec<String> = cmd
+        .get_args()
+        .skip(kept)
+        .map(|arg| quote_response_arg(&arg.to_string_lossy()))
+        .collect();
+    fs::write(&path, content.join("\n")).map_err(|e| Error {
+        error_type: error_type.clone(),
+        message: format!("Failed to write response file {}: {}", path.display(), e),
+        span: None,
+        additional_info: None,
+    })?;
+    logd!("Arguments of {}", command_line(&cmd));
+
+    let mut response_cmd = Command::new(cmd.get_program());
+    response_cmd
+        .args(cmd.get_args().take(kept))
+        .arg(format!("@{}", path.display()));
+    let result = run_command(response_cmd, error_type, false);
+    fs::remove_file(&path).ok();
+    result
+}
+
+fn create_dir(dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).map_err(|e| Error {
+        error_type: ErrorType::CompilationFailed,
+        message: format!("Failed to create directory {}: {}", dir.display(), e),
+        span: None,
+        additional_info: None,
+    })
+}
+
+fn compile_flags(ctx: &BuildContext, subproject: &SubProject) -> Result<Vec<String>, Error> {
+    let config = ctx.config;
+    let settings = config.resolved_settings(subproject.name.get_ref(), ctx.preset.as_deref());
+    let mut flags = split_flags(&settings.cflags);
+    // Objects of shared libraries end up in position independent code
+    if builds_shared_library(subproject) {
+        flags.push("-fPIC".to_string());
+    }
+    // A profile starts from the flags of the mode it inherits, its own flags come after
+    let profile = config.profile(ctx.mode.as_str());
+    let release = match &ctx.mode {
+        BuildMode::Debug => false,
+        BuildMode::Release => true,
+        BuildMode::Profile(_) => profile.is_some_and(Profile::is_release),
+    };
+    flags.extend(if release {
+        split_flags(&settings.release_flags)
+    } else {
+        split_flags(&settings.debug_flags)
+    });
+    if let Some(profile) = profile {
+        let opt_level = profile.opt_level.as_ref();
+        flags.extend(opt_level.map(|level| format!("-O{}", level.get_ref())));
+        flags.extend(split_flags(&profile.cflags));
+    }
+    flags.extend(split_flags(&settings.preset_cflags));
+
+    // Global defines come first, then the subproject's, then override and preset defines.
+    // A later define replaces earlier ones of the same macro.
+    let define_name = |define: &str| define.split('=').next().unwrap_or_default().to_string();
+    let global = config.build.defines.iter().flatten();
+    let own = subproject.defines.iter().flatten();
+    let mut defines: Vec<&String> = Vec::new();
+    for define in global
+        .chain(own)
+        .map(|define| define.get_ref())
+        .chain(&settings.defines)
+    {
+        let name = define_name(define);
+        defines.retain(|earlier| define_name(earlier) != name);
+        defines.push(define);
+    }
+    flags.extend(defines.into_iter().map(|define| format!("-D{}", define)));
+
+    let deps = external_dependencies(config, subproject);
+    for dep in &deps {
+        match dep {
+            DependencyRef::Remote(_) => {}
+            DependencyRef::Manual(manual) => flags.extend(split_flags(&manual.get_ref().cflags)),
+            DependencyRef::PkgConfig(pkg_config) => {
+                flags.extend(ctx.pkg_config_flags(pkg_config.get_ref())?.cflags)
+            }
+        }
+    }
+    let order = transitive_subprojects(config, subproject);
+    flags.extend(
+        resolve_include_dirs(subproject, &deps, &order)
+            .into_iter()
+            .map(|dir| format!("-I{}", dir.display())),
+    );
+    Ok(flags)
+}
+
+// Include directories of a subproject, in the order they are searched: its own first,
+// then those of the subprojects it depends on in build order, then those of its remote
+// and manual dependencies. A directory listed twice is only searched where it first appears.
+pub fn resolve_include_dirs(
+    subproject: &SubProject,
+    deps: &[DependencyRef],
+    order: &[&SubProject],
+) -> Vec<PathBuf> {
+    let subproject_dirs = std::iter::once(subproject)
+        .chain(order.iter().copied())
+        .flat_map(|sub| sub.include_dirs.iter().flatten())
+        .map(|dir| PathBuf::from(dir.get_ref()));
+    let dependency_dirs = deps.iter().flat_map(|dep| match dep {
+        DependencyRef::Remote(remote) => {
+            let remote = remote.get_ref();
+            let root = Path::new(DEPS_DIR).join(remote.name.get_ref());
+            remote
+                .include_dirs
+                .iter()
+                .map(|dir| root.join(dir.get_ref()))
+                .collect()
+        }
+        DependencyRef::Manual(manual) => manual
+            .get_ref()
+            .include_dirs
+            .iter()
+            .flatten()
+            .map(|dir| PathBuf::from(dir.get_ref()))
+            .collect(),
+        DependencyRef::PkgConfig(_) => Vec::new(),
+    });
+
+    let mut include_dirs: Vec<PathBuf> = Vec::new();
+    for dir in subproject_dirs.chain(dependency_dirs) {
+        if !include_dirs.contains(&dir) {
+            include_dirs.push(dir);
+        }
+    }
+    include_dirs
+}
+
+fn link_flags(ctx: &BuildContext, subproject: &SubProject) -> Result<Vec<String>, Error> {
+    let config = ctx.config;
+    let mut flags = Vec::new();
+    // Dependents must come before their dependencies for static archives
+    let libraries: Vec<&SubProject> = transitive_subprojects(config, subproject)
+        .into_iter()
+        .rev()
+        .filter(|dep| dep.r#type == SubProjectType::Library)
+        .collect();
+    // Libraries built both ways are linked statically, shared-only ones by name
+    let lib_dir = output_dir(ctx.target.as_deref(), &ctx.mode).join("lib");
+    let mut shared = false;
+    for dep in &libraries {
+        if library_kind(dep) == LibraryKind::Shared {
+            if !shared {
+                flags.push(format!("-L{}", lib_dir.display()));
+                shared = true;
+            }
+            flags.push(format!("-l{}", dep.output_name()));
+        } else if let Some(artifact) = artifact_path(dep, ctx.target.as_deref(), &ctx.mode) {
+            flags.push(artifact.to_string_lossy().to_string());
+        }
+    }
+    // Shared libraries are found next to the binary's directory at runtime
+    if shared {
+        flags.push("-Wl,-rpath,$ORIGIN/../lib".to_string());
+    }
+    flags.extend(split_flags(&subproject.ldflags));
+    if let Some(profile) = config.profile(ctx.mode.as_str()) {
+        flags.extend(split_flags(&profile.ldflags));
+    }
+    let settings = config.resolved_settings(subproject.name.get_ref(), ctx.preset.as_deref());
+    flags.extend(split_flags(&settings.preset_ldflags));
+    for dep in external_dependencies(config, subproject) {
+        match dep {
+            DependencyRef::Manual(manual) => {
+                let manual = manual.get_ref();
+                let lib_dirs = manual.lib_dirs.iter().flatten();
+                flags.extend(lib_dirs.map(|dir| format!("-L{}", dir.get_ref())));
+                flags.extend(split_flags(&manual.ldflags));
+            }
+            DependencyRef::PkgConfig(pkg_config) => {
+                flags.extend(ctx.pkg_config_flags(pkg_config.get_ref())?.libs)
+            }
+            DependencyRef::Remote(_) => {}
+        }
+    }
+    Ok(flags)
+}
+
+// Object file of a source, mirroring its path below the subproject's object base
+fn object_path(obj_dir: &Path, object_base: &Path, source: &Path) -> PathBuf {
+    let relative = source.strip_prefix(object_base).unwrap_or(source);
+    obj_dir.join(relative).with_extension("o")
+}
+
+// Compiler and arguments compiling one source to its object. The language standard is
+// the only flag that differs between C and C++ sources.
+fn source_compile_args<'s>(
+    settings: &'s ResolvedSettings,
+    flags: &[String],
+    source: &Path,
+    object: &Path,
+    pch_include: Option<&Path>,
+) -> (&'s str, Vec<OsString>) {
+    let (compiler, standard) = if is_cxx_source(source) {
+        let standard = settings.cpp_standard.as_ref();
+        (
+            &settings.cxx_compiler,
+            standard.map(|standard| format!("-std={}", standard)),
+        )
+    } else {
+        (
+            &settings.compiler,
+            Some(format!("-std={}", settings.c_standard)),
+        )
+    };
+    let mut args: Vec<OsString> = standard.into_iter().map(OsString::from).collect();
+    args.extend(flags.iter().map(OsString::from));
+    if let Some(include) = pch_include {
+        args.push("-include".into());
+        args.push(include.into());
+    }
+    args.push("-MMD".into());
+    args.push("-MF".into());
+    args.push(object.with_extension("d").into());
+    args.push("-c".into());
+    args.push(source.into());
+    args.push("-o".into());
+    args.push(object.into());
+    (compiler, args)
+}
+
+// A source with the command compiling it, as compile_commands.json lists it
+pub struct CompileEntry {
+    pub source: PathBuf,
+    pub object: PathBuf,
+    pub arguments: Vec<String>,
+}
+
+// The compile command of every source of every subproject, as a build in the context's
+// mode would run it. Tools reading them want the compiler, so the launcher is left out,
+// and a precompiled header is included as the header itself.
+pub fn compile_entries(ctx: &BuildContext) -> Result<Vec<CompileEntry>, Error> {
+    let mut entries = Vec::new();
+    for subproject in &ctx.config.subprojects {
+        // Header-only subprojects only lend their include directories to dependents
+        if subproject.r#type == SubProjectType::HeaderOnly {
+            continue;
+        }
+        let name = subproject.name.get_ref();
+        let sources = subproject.source_files().map_err(|e| Error {
+            error_type: ErrorType::CompilationFailed,
+            message: format!("Failed to read sources of {}: {}", name, e),
+            span: None,
+            additional_info: None,
+        })?;
+        if sources.is_empty() {
+            continue;
+        }
+        let obj_dir = output_dir(ctx.target.as_deref(), &ctx.mode)
+            .join("obj")
+            .join(name);
+        let settings = ctx.config.resolved_settings(name, ctx.preset.as_deref());
+        let flags = compile_flags(ctx, subproject)?;
+        let pch = subproject.pch.as_ref().map(|pch| Path::new(pch.get_ref()));
+        for source in sources {
+            let object = object_path(&obj_dir, subproject.object_base(), &source);
+            let include = pch.filter(|pch| is_cxx_header(pch) == is_cxx_source(&source));
+            let (compiler, args) =
+                source_compile_args(&settings, &flags, &source, &object, include);
+            let mut cmd = ctx.compiler_command(compiler);
+            cmd.args(args);
+            let arguments = std::iter::once(cmd.get_program())
+                .chain(cmd.get_args())
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect();
+            entries.push(CompileEntry {
+                source,
+                object,
+                arguments,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn build_subproject(
+    ctx: &BuildContext,
+    slots: &JobSlots,
+    subproject: &SubProject,
+) -> Result<Option<BuildReport>, Error> {
+    let (config, mode, target) = (ctx.config, &ctx.mode, ctx.target.as_deref());
+    let name = subproject.name.get_ref();
+    let Some(artifact) = artifact_path(subproject, target, mode) else {
+        return Ok(None);
+    };
+    logi!("Building {} ({})", name, mode.as_str());
+    let start = Instant::now();
+    let report = |rebuilt, compiled| {
+        Some(BuildReport {
+            name: name.clone(),
+            rebuilt,
+            compiled,
+            duration: start.elapsed(),
+        })
+    };
+
+    let object_base = subproject.object_base();
+    let sources = subproject.source_files().map_err(|e| Error {
+        error_type: ErrorType::CompilationFailed,
+        message: format!("Failed to read sources of {}: {}", name, e),
+        span: None,
+        additional_info: None,
+    })?;
+
+    let obj_dir = output_dir(target, mode).join("obj").join(name);
+    let settings = config.resolved_settings(name, ctx.preset.as_deref());
+    let flags = compile_flags(ctx, subproject)?;
+    let objects: Vec<PathBuf> = sources
+        .iter()
+        .map(|source| object_path(&obj_dir, object_base, source))
+        .collect();
+
+    let pch = match &subproject.pch {
+        Some(header) => Some(compile_pch(
+            ctx,
+            slots,
+            Path::new(header.get_ref()),
+            &obj_dir,
+            &settings,
+            &flags,
+        )?),
+        None => None,
+    };
+    // Sources of the precompiled header's language include it, and are recompiled with it
+    let pch_for = |source: &Path| pch.as_ref().filter(|pch| pch.cxx == is_cxx_source(source));
+
+    let units: Vec<Unit> = sources
+        .iter()
+        .zip(&objects)
+        .map(|(source, object)| Unit::single(source, object))
+        .collect();
+    // Translation units are independent, so compile them with a pool of workers. A dry run
+    // prints them with a single one, in order.
+    let compile_units = |units: &[Unit]| -> Result<usize, Error> {
+        let workers = match ctx.dry_run {
+            true => 1,
+            false => override_jobs(config, name).unwrap_or(ctx.jobs),
+        }
+        .clamp(1, units.len().max(1));
+        let next = AtomicUsize::new(0);
+        let compiled = AtomicUsize::new(0);
+        let errors = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= units.len() || !errors.lock().unwrap().is_empty() {
+                        break;
+                    }
+                    let (source, object) = (&units[index].source, &units[index].object);
+                    let pch = pch_for(source);
+                    let pch_changed =
+                        pch.is_some_and(|pch| pch.rebuilt || !is_up_to_date(&pch.output, object));
+                    if !ctx.force && !pch_changed && !needs_compile(source, object) {
+                        continue;
+                    }
+                    compiled.fetch_add(units[index].sources, Ordering::SeqCst);
+                    let parent = object.parent().filter(|_| !ctx.dry_run);
+                    let result = parent.map_or(Ok(()), create_dir).and_then(|_| {
+                        let include = pch.map(|pch| pch.include.as_path());
+                        let (compiler, args) =
+                            source_compile_args(&settings, &flags, source, object, include);
+                        let mut cmd = ctx.compile_command(compiler);
+                        cmd.args(args);
+                        slots.run(|| {
+                            run_tool(
+                                cmd,
+                                ctx.launcher_args(),
+                                ErrorType::CompilationFailed,
+                                ctx.dry_run,
+                            )
+                        })
+                    });
+                    if let Err(e) = result {
+                        errors.lock().unwrap().push(e);
+                    }
+                });
+            }
+        });
+        match errors.into_inner().unwrap().into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(compiled.into_inner()),
+        }
+    };
+
+    let unity = subproject.unity.unwrap_or(ctx.unity)
+        && units
+            .iter()
+            .filter(|unit| !is_cxx_source(&unit.source))
+            .all(|unit| needs_compile(&unit.source, &unit.object));
+    let unity_units = if unity {
+        unity_units(ctx, name, &obj_dir, &units)?
+    } else {
+        None
+    };
+    let (objects, compiled) = match unity_units {
+        Some(unity_units) => match compile_units(&unity_units) {
+            Ok(compiled) => (
+                unity_units.into_iter().map(|unit| unit.object).collect(),
+                compiled,
+            ),
+            // A name defined in two sources, or a macro leaking from one into the next
+            Err(e) => {
+                logw!(
+                    "Unity build of {} failed, compiling its sources one by one: {}",
+                    name,
+                    e.message
+                );
+                (objects, compile_units(&units)?)
+            }
+        },
+        None => (objects, compile_units(&units)?),
+    };
+
+    // Relink only when an object was recompiled or an input is newer than an artifact
+    let libraries: Vec<PathBuf> = transitive_subprojects(config, subproject)
+        .into_iter()
+        .filter_map(|dep| artifact_path(dep, target, mode))
+        .collect();
+    let shared_library = shared_library_path(subproject, target, mode);
+    let outputs: Vec<&PathBuf> = std::iter::once(&artifact).chain(&shared_library).collect();
+    let compiled = compiled + usize::from(pch.as_ref().is_some_and(|pch| pch.rebuilt));
+    let relink = ctx.force
+        || compiled > 0
+        || objects
+            .iter()
+            .chain(&libraries)
+            .any(|input| outputs.iter().any(|output| !is_up_to_date(input, output)));
+    if !relink {
+        logv!("{} is up to date", name);
+        return Ok(report(false, compiled));
+    }
+
+    if let Some(parent) = artifact.parent().filter(|_| !ctx.dry_run) {
+        create_dir(parent)?;
+    }
+    let mut commands = Vec::new();
+    if subproject.r#type == SubProjectType::Library
+        && library_kind(subproject) != LibraryKind::Shared
+    {
+        let mut cmd = Command::new(ctx.archiver());
+        cmd.arg("rcs").arg(&artifact).args(&objects);
+        commands.push(cmd);
+    }
+    let linked = match subproject.r#type {
+        SubProjectType::Binary | SubProjectType::Test => Some(&artifact),
+        _ => shared_library.as_ref(),
+    };
+    if let Some(linked) = linked {
+        // C++ objects, here or in a linked library, need the C++ runtime
+        // Header-only subprojects compile nothing of their own
+        let has_cxx = |sub: &SubProject| {
+            sub.r#type != SubProjectType::HeaderOnly
+                && sub
+                    .source_files()
+                    .is_ok_and(|files| files.iter().any(|file| is_cxx_source(file)))
+        };
+        let linker = if sources.iter().any(|source| is_cxx_source(source))
+            || transitive_subprojects(config, subproject)
+                .into_iter()
+                .any(has_cxx)
+        {
+            &settings.cxx_compiler
+        } else {
+            &settings.compiler
+        };
+        let mut cmd = ctx.compiler_command(linker);
+        if builds_shared_library(subproject) {
+            let file_name = linked.file_name().unwrap_or_default().to_string_lossy();
+            cmd.arg("-shared").arg(format!("-Wl,-soname,{}", file_name));
+        }
+        cmd.args(&objects)
+            .arg("-o")
+            .arg(linked)
+            .args(link_flags(ctx, subproject)?);
+        commands.push(cmd);
+    }
+    for cmd in commands {
+        slots.run(|| run_tool(cmd, 0, ErrorType::LinkingFailed, ctx.dry_run))?;
+    }
+    Ok(report(true, compiled))
+}
+
+// A source compiled to an object, standing for several sources in a unity build
+struct Unit {
+    source: PathBuf,
+    object: PathBuf,
+    sources: usize,
+}
+
+impl Unit {
+    fn single(source: &Path, object: &Path) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            object: object.to_path_buf(),
+            sources: 1,
+        }
+    }
+}
+
+// The C sources of a subproject as one generated source including all of them, the C++
+// sources stay units of their own. None when there are fewer than two C sources.
+fn unity_units(
+    ctx: &BuildContext,
+    name: &str,
+    obj_dir: &Path,
+    units: &[Unit],
+) -> Result<Option<Vec<Unit>>, Error> {
+    let (c_units, cxx_units): (Vec<&Unit>, Vec<&Unit>) =
+        units.iter().partition(|unit| !is_cxx_source(&unit.source));
+    if c_units.len() < 2 {
+        return Ok(None);
+    }
+    let source = obj_dir.join(format!("{}_unity.c", name));
+    let mut content = String::from("/* Generated by iceforge, do not edit */\n");
+    for unit in &c_units {
+        let path = std::path::absolute(&unit.source).unwrap_or_else(|_| unit.source.clone());
+        content.push_str(&format!("#include \"{}\"\n", path.display()));
+    }
+    // Only written when it changes, so an unchanged file list keeps the object up to date
+    if !ctx.dry_run && fs::read_to_string(&source).ok().as_deref() != Some(content.as_str()) {
+        create_dir(obj_dir)?;
+        util::write_atomic(&source, content).map_err(|e| Error {
+            error_type: ErrorType::CompilationFailed,
+            message: format!("Failed to write {}: {}", source.display(), e),
+            span: None,
+            additional_info: None,
+        })?;
+    }
+    let unity = Unit {
+        object: source.with_extension("o"),
+        source,
+        sources: c_units.len(),
+    };
+    let cxx_units = cxx_units
+        .into_iter()
+        .map(|unit| Unit::single(&unit.source, &unit.object));
+    Ok(Some(std::iter::once(unity).chain(cxx_units).collect()))
+}
+
+// A precompiled header in the object directory of a subproject
+struct Pch {
+    // Passed to -include, the compiler picks the .gch next to it
+    include: PathBuf,
+    output: PathBuf,
+    cxx: bool,
+    rebuilt: bool,
+}
+
+// Precompile a header with the flags of the sources including it. The command is recorded
+// next to the output, so changed flags rebuild it like a changed header does.
+fn compile_pch(
+    ctx: &BuildContext,
+    slots: &JobSlots,
+    header: &Path,
+    obj_dir: &Path,
+    settings: &ResolvedSettings,
+    flags: &[String],
+) -> Result<Pch, Error> {
+    let cxx = is_cxx_header(header);
+    let include = obj_dir.join(header.file_name().unwrap_or_default());
+    let mut output = include.clone().into_os_string();
+    output.push(".gch");
+    let output = PathBuf::from(output);
+    let mut stamp = include.clone().into_os_string();
+    stamp.push(".cmd");
+    let stamp = PathBuf::from(stamp);
+
+    let (compiler, standard, language) = if cxx {
+        let standard = settings.cpp_standard.as_ref();
+        (
+            &settings.cxx_compiler,
+            standard.map(|std| format!("-std={}", std)),
+            "c++-header",
+        )
+    } else {
+        (
+            &settings.compiler,
+            Some(format!("-std={}", settings.c_standard)),
+            "c-header",
+        )
+    };
+    let mut cmd = ctx.compile_command(compiler);
+    cmd.args(standard)
+        .args(flags)
+        .arg("-x")
+        .arg(language)
+        .arg("-MMD")
+        .arg("-MF")
+        .arg(output.with_extension("d"))
+        .arg(header)
+        .arg("-o")
+        .arg(&output);
+    let line = command_line(&cmd);
+    let rebuilt = ctx.force
+        || needs_compile(header, &output)
+        || fs::read_to_string(&stamp).ok().as_deref() != Some(line.as_str());
+    if rebuilt {
+        if !ctx.dry_run {
+            create_dir(obj_dir)?;
+        }
+        slots.run(|| {
+            run_tool(
+                cmd,
+                ctx.launcher_args(),
+                ErrorType::CompilationFailed,
+                ctx.dry_run,
+            )
+        })?;
+        if !ctx.dry_run {
+            util::write_atomic(&stamp, &line).map_err(|e| Error {
+                error_type: ErrorType::CompilationFailed,
+                message: format!("Failed to write {}: {}", stamp.display(), e),
+                span: None,
+                additional_info: None,
+            })?;
+        }
+    }
+    Ok(Pch {
+        include,
+        output,
+        cxx,
+        rebuilt,
+    })
+}
+
+// Collect every file below a directory whose extension is one of the trigger extensions
+fn collect_triggered(
+    dir: &Path,
+    extensions: &[String],
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_triggered(&path, extensions, files)?;
+        } else if let Some(ext) = path.extension() {
+            let ext = ext.to_string_lossy();
+            if extensions
+                .iter()
+                .any(|trigger| normalize_extension(trigger) == ext)
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+// Source files of a custom build rule, each with the file it produces
+pub fn custom_rule_outputs(rule: &CustomBuildRule) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let src_dir = Path::new(rule.src_dir.get_ref());
+    let mut inputs = Vec::new();
+    collect_triggered(src_dir, &rule.trigger_extensions, &mut inputs)?;
+    Ok(inputs
+        .into_iter()
+        .map(|input| {
+            let relative = input.strip_prefix(src_dir).unwrap_or(&input);
+            let mut file_name = relative.as_os_str().to_os_string();
+            file_name.push(".");
+            file_name.push(normalize_extension(&rule.output_extension));
+            let output = Path::new(rule.output_dir.get_ref()).join(file_name);
+            (input, output)
+        })
+        .collect())
+}
+
+// Run a custom build rule over its source files. `OnTrigger` rules only run when
+// triggered, and triggering a rule runs it regardless of its rebuild rule.
+pub fn run_custom_build_rule(
+    config: &BuildConfig,
+    rule: &CustomBuildRule,
+    triggered: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    if rule.rebuild_rule == CustomBuildRuleType::OnTrigger && !triggered {
+        return Ok(());
+    }
+    let rule_error = |message: String| Error {
+        error_type: ErrorType::CustomBuildRuleFailed,
+        message,
+        span: None,
+        additional_info: None,
+    };
+
+    let outputs = custom_rule_outputs(rule).map_err(|e| {
+        rule_error(format!(
+            "Failed to read sources of custom build rule {} in {}: {}",
+            rule.name.get_ref(),
+            rule.src_dir.get_ref(),
+            e
+        ))
+    })?;
+
+    logi!("Running custom build rule {}", rule.name.get_ref());
+    for (input, output) in outputs {
+        let rebuild = triggered
+            || rule.rebuild_rule == CustomBuildRuleType::Always
+            || !is_up_to_date(&input, &output);
+        if !rebuild {
+            continue;
+        }
+        if let Some(parent) = output.parent().filter(|_| !dry_run) {
+            fs::create_dir_all(parent).map_err(|e| {
+                rule_error(format!(
+                    "Failed to create directory {}: {}",
+                    parent.display(),
+                    e
+                ))
+            })?;
+        }
+        let command = rule.expand(config.build.compiler.get_ref(), &input, &output)?;
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        run_command(cmd, ErrorType::CustomBuildRuleFailed, dry_run)?;
+    }
+    Ok(())
+}
+
+// Build the subprojects of a verified config, optionally restricted to a single
+// subproject and the subprojects it depends on. Subprojects whose dependencies are
+// all built are scheduled in parallel.
+pub fn build_project(ctx: &BuildContext, only: Option<&str>) -> Result<Vec<BuildReport>, Error> {
+    let config = ctx.config;
+    let selected: Vec<&SubProject> = match only {
+        Some(name) => {
+            let Some(subproject) = find_subproject(config, name) else {
+                return Err(Error {
+                    error_type: ErrorType::InvalidSubprojectDependency,
+                    message: format!("No subproject named {}", name),
+                    span: None,
+                    additional_info: None,
+                });
+            };
+            let mut selected = transitive_subprojects(config, subproject);
+            selected.push(subproject);
+            selected
+        }
+        // Tests are only built by `iceforge test`
+        None => config
+            .subprojects
+            .iter()
+            .filter(|subproject| subproject.r#type != SubProjectType::Test)
+            .collect(),
+    };
+    build_selected(ctx, selected)
+}
+
+// Build test subprojects and the subprojects they depend on
+pub fn build_tests(ctx: &BuildContext, tests: &[&SubProject]) -> Result<Vec<BuildReport>, Error> {
+    let mut selected: Vec<&SubProject> = Vec::new();
+    for test in tests {
+        for subproject in transitive_subprojects(ctx.config, test)
+            .into_iter()
+            .chain([*test])
+        {
+            if !selected
+                .iter()
+                .any(|other| other.name.get_ref() == subproject.name.get_ref())
+            {
+                selected.push(subproject);
+            }
+        }
+    }
+    build_selected(ctx, selected)
+}
+
+fn build_selected(
+    ctx: &BuildContext,
+    selected: Vec<&SubProject>,
+) -> Result<Vec<BuildReport>, Error> {
+    let config = ctx.config;
+
+    // The config only checks the host compiler, cross compilers are checked here
+    if let Some(target) = &ctx.target {
+        for subproject in &selected {
+            let compiler = config
+                .resolved_settings(subproject.name.get_ref(), ctx.preset.as_deref())
+                .compiler;
+            let cross_compiler = target_compiler(&compiler, Some(target));
+            if find_in_path(&cross_compiler).is_none() {
+                return Err(Error {
+                    error_type: ErrorType::IncorrectCompiler,
+                    message: format!(
+                        "Compiler {} for target {} not found in PATH",
+                        cross_compiler, target
+                    ),
+                    span: None,
+                    additional_info: None,
+                });
+            }
+        }
+    }
+
+    for rule in config.custom_build_rules.iter().flatten() {
+        run_custom_build_rule(config, rule, false, ctx.dry_run)?;
+    }
+
+    let slots = JobSlots::new(ctx.jobs);
+    let mut started = HashSet::new();
+    let mut finished = HashSet::new();
+    let mut failures: Vec<(String, Error)> = Vec::new();
+    let mut reports = Vec::new();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        let mut running = 0;
+        loop {
+            // Start every subproject whose subproject dependencies are all finished. A dry
+            // run starts one at a time, so its commands are printed in build order.
+            if failures.is_empty() || ctx.keep_going {
+                for subproject in &selected {
+                    if ctx.dry_run && running > 0 {
+                        break;
+                    }
+                    let name = subproject.name.get_ref();
+                    let ready = subproject.dependencies.iter().flatten().all(|dep| {
+                        let dep = dep.get_ref().name();
+                        find_subproject(config, dep).is_none() || finished.contains(dep)
+                    });
+                    if ready && started.insert(name.clone()) {
+                        running += 1;
+                        let sender = sender.clone();
+                        let slots = &slots;
+                        scope.spawn(move || {
+                            // Always report back, otherwise the scheduler waits forever
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                                build_subproject(ctx, slots, subproject)
+                            }))
+                            .unwrap_or_else(|_| {
+                                Err(Error {
+                                    error_type: ErrorType::CompilationFailed,
+                                    message: format!("Building {} panicked", name),
+                                    span: None,
+                                    additional_info: None,
+                                })
+                            });
+                            sender.send((name.clone(), result)).unwrap();
+                        });
+                    }
+                }
+            }
+            if running == 0 {
+                break;
+            }
+            let (name, result) = receiver.recv().unwrap();
+            running -= 1;
+            match result {
+                Ok(report) => {
+                    reports.extend(report);
+                    finished.insert(name);
+                }
+                Err(e) => {
+                    // Reported now, the build may go on for a while
+                    if ctx.keep_going {
+                        loge!("{} ({})", e.message, e.error_type.explain_hint());
+                    }
+                    failures.push((name, e));
+                }
+            }
+        }
+    });
+
+    if !ctx.keep_going {
+        return match failures.into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(reports),
+        };
+    }
+    // Whatever never started depends on a failed or skipped subproject
+    let skipped: Vec<&str> = selected
+        .iter()
+        .map(|subproject| subproject.name.get_ref().as_str())
+        .filter(|name| !started.contains(*name))
+        .collect();
+    for name in &skipped {
+        logw!("{}: skipped, a dependency failed", name);
+    }
+    let Some((_, first)) = failures.first() else {
+        return Ok(reports);
+    };
+    let failed: Vec<&str> = failures.iter().map(|(name, _)| name.as_str()).collect();
+    Err(Error {
+        error_type: first.error_type.clone(),
+        message: format!(
+            "{} of {} subprojects failed ({}), {} skipped",
+            failed.len(),
+            selected.len(),
+            failed.join(", "),
+            skipped.len()
+        ),
+        span: None,
+        additional_info: None,
+    })
+}
+
+// Log a line per built subproject and the total time the build took
+pub fn log_summary(reports: &[BuildReport], elapsed: Duration) {
+    for report in reports {
+        let status = if report.rebuilt {
+            "rebuilt"
+        } else {
+            "up to date"
+        };
+        logi!(
+            "{}: {} in {:.2}s",
+            report.name,
+            status,
+            report.duration.as_secs_f64()
+        );
+    }
+    logi!(
+        "Built {} subprojects in {:.2}s",
+        reports.len(),
+        elapsed.as_secs_f64()
+    );
+}
+
+// Machine readable version of the summary, a single JSON object
+pub fn timings_json(reports: &[BuildReport], elapsed: Duration) -> String {
+    let subprojects: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            format!(
+                "{{\"name\":{},\"rebuilt\":{},\"seconds\":{:.6}}}",
+                json_string(&report.name),
+                report.rebuilt,
+                report.duration.as_secs_f64()
+            )
+        })
+        .collect();
+    format!(
+        "{{\"subprojects\":[{}],\"total_seconds\":{:.6}}}",
+        subprojects.join(","),
+        elapsed.as_secs_f64()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn job_slots_are_given_back_when_a_job_panics() {
+        let slots = JobSlots::new(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| slots.run(|| panic!("job"))));
+        assert!(result.is_err());
+        // Would wait forever for the slot of the panicked job
+        assert_eq!(slots.run(|| 1), 1);
+    }
+
+    #[test]
+    fn job_count_falls_back_to_the_config_then_the_cpus() {
+        let source = "subprojects = []\n[build]\nversion = \"0.1.0\"\nc_standard = \"c11\"\ncompiler = \"cc\"\n";
+        let unset = BuildConfig::from_source(source).unwrap();
+        let set = BuildConfig::from_source(&format!("{}parallel_jobs = 3\n", source)).unwrap();
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        assert_eq!(job_count(&set, Some(5)), 5);
+        assert_eq!(job_count(&set, None), 3);
+        assert_eq!(job_count(&unset, None), cpus);
+        assert_eq!(
+            job_count(&unset, Some(u32::MAX)),
+            MAX_PARALLEL_JOBS as usize
+        );
+    }
+
+    #[test]
+    fn shared_libraries_are_named_for_the_target() {
+        let config = BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[[subprojects]]
+name = "core"
+type = "library"
+library_type = "shared"
+src_dir = "src/core"
+"#,
+        )
+        .unwrap();
+        let core = &config.subprojects[0];
+        let file_name = |target| {
+            shared_library_path(core, target, &BuildMode::Release)
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(file_name(Some("x86_64-pc-windows-msvc")), "core.dll");
+        assert_eq!(file_name(Some("x86_64-w64-mingw32")), "core.dll");
+        assert_eq!(file_name(Some("aarch64-apple-darwin")), "libcore.dylib");
+        assert_eq!(file_name(Some("aarch64-linux-gnu")), "libcore.so");
+        assert_eq!(file_name(None), format!("{}core{}", DLL_PREFIX, DLL_SUFFIX));
+    }
+
+    #[test]
+    fn only_overrides_set_the_workers_of_a_subproject() {
+        let config = BuildConfig::from_source(
+            r#"
+subprojects = []
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+parallel_jobs = 8
+
+[[overrides]]
+name = "slow"
+parallel_jobs = 2
+"#,
+        )
+        .unwrap();
+        assert_eq!(override_jobs(&config, "slow"), Some(2));
+        assert_eq!(override_jobs(&config, "other"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn long_commands_pass_their_arguments_in_a_response_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let seen = dir.path().join("seen");
+        let tool = dir.path().join("tool");
+        // Records the argument it got and the response file it points at
+        fs::write(
+            &tool,
+            format!(
+                "#!/bin/sh\necho \"$1\" > {0}\ncat \"${{1#@}}\" >> {0}\n",
+                seen.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut cmd = Command::new(&tool);
+        cmd.args(["-c", "main.c", "-DNAME=\"a b\""]);
+        run_tool_with_threshold(cmd, 0, ErrorType::CompilationFailed, false, 8).unwrap();
+
+        let seen = fs::read_to_string(seen).unwrap();
+        let (argument, content) = seen.split_once('\n').unwrap();
+        let response_file = argument.strip_prefix('@').unwrap();
+        assert!(response_file.ends_with(".rsp"), "{}", argument);
+        assert_eq!(content, "\"-c\"\n\"main.c\"\n\"-DNAME=\\\"a b\\\"\"");
+        // Removed once the tool is done with it
+        assert!(!Path::new(response_file).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launched_compilers_stay_on_the_command_line_of_a_response_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let seen = dir.path().join("seen");
+        let launcher = dir.path().join("launcher");
+        // Records the compiler it was asked to run and the response file it got
+        fs::write(
+            &launcher,
+            format!(
+                "#!/bin/sh
+echo \"$1 $2\" > {0}\ncat \"${{2#@}}\" >> {0}\n",
+                seen.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&launcher, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = BuildConfig::from_source(
+            "subprojects = []\n[build]\nversion = \"0.1.0\"\nc_standard = \"c11\"\ncompiler = \"cc\"\n",
+        )
+        .unwrap();
+        let mut ctx = BuildContext::new(&config, BuildMode::Debug);
+        ctx.target = None;
+        ctx.launcher = Some(launcher.display().to_string());
+        let mut cmd = ctx.compile_command("cc");
+        cmd.args(["-c", "main.c"]);
+        let kept = ctx.launcher_args();
+        run_tool_with_threshold(cmd, kept, ErrorType::CompilationFailed, false, 8).unwrap();
+
+        let seen = fs::read_to_string(seen).unwrap();
+        let (arguments, content) = seen.split_once('\n').unwrap();
+        let (compiler, response_file) = arguments.split_once(' ').unwrap();
+        assert_eq!(compiler, "cc");
+        assert!(response_file.starts_with('@'), "{}", arguments);
+        assert_eq!(content, "\"-c\"\n\"main.c\"");
+    }
+
+    #[test]
+    fn header_only_dependencies_add_their_include_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().display();
+        fs::create_dir_all(dir.path().join("app")).unwrap();
+        fs::create_dir_all(dir.path().join("vec/include")).unwrap();
+        fs::write(
+            dir.path().join("app/main.c"),
+            "int main(void) { return 0; }\n",
+        )
+        .unwrap();
+        // The dependent comes first, verification puts it after the header-only library
+        let mut config = BuildConfig::from_source(&format!(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "{root}/app"
+dependencies = ["vec"]
+
+[[subprojects]]
+name = "vec"
+type = "header-only"
+include_dirs = ["{root}/vec/include"]
+"#
+        ))
+        .unwrap();
+        // Only the subprojects are verified, the whole config would probe the compiler
+        config.subprojects =
+            SubProject::verify_subprojects(config.subprojects, &config.dependencies).unwrap();
+        let names: Vec<&str> = config
+            .subprojects
+            .iter()
+            .map(|sub| sub.name.get_ref().as_str())
+            .collect();
+        assert_eq!(names, ["vec", "app"]);
+
+        let entries = compile_entries(&BuildContext::new(&config, BuildMode::Debug)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].source, dir.path().join("app/main.c"));
+        let include = format!("-I{}/vec/include", root);
+        assert!(entries[0].arguments.contains(&include));
+    }
+
+    #[test]
+    fn defines_reach_the_compile_command() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        let config = BuildConfig::from_source(&format!(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+defines = ["LEVEL=1", "GLOBAL"]
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "{}"
+defines = ["FOO", "LEVEL=2"]
+"#,
+            dir.path().display()
+        ))
+        .unwrap();
+        let entries = compile_entries(&BuildContext::new(&config, BuildMode::Debug)).unwrap();
+        let defines: Vec<&str> = entries[0]
+            .arguments
+            .iter()
+            .filter(|arg| arg.starts_with("-D"))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(defines, ["-DGLOBAL", "-DFOO", "-DLEVEL=2"]);
+    }
+
+    #[test]
+    fn subproject_ldflags_come_before_those_of_its_dependencies() {
+        let config = BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[dependencies]
+manual = [{ name = "m", ldflags = "-lm" }, { name = "dl", ldflags = "-ldl" }]
+
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+ldflags = "-pthread -rdynamic"
+dependencies = ["core", { name = "dl" }, { name = "m" }]
+"#,
+        )
+        .unwrap();
+        let ctx = BuildContext::new(&config, BuildMode::Debug);
+        let app = find_subproject(&config, "app").unwrap();
+        let flags = link_flags(&ctx, app).unwrap();
+        let core = artifact_path(find_subproject(&config, "core").unwrap(), None, &ctx.mode);
+        // Dependency flags follow in the order the subproject lists its dependencies, not
+        // the order of the [dependencies] table
+        assert_eq!(
+            flags,
+            [
+                core.unwrap().to_string_lossy().to_string(),
+                "-pthread".to_string(),
+                "-rdynamic".to_string(),
+                "-ldl".to_string(),
+                "-lm".to_string(),
+            ]
+        );
+    }
+}
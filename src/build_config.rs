@@ -18,38 +18,89 @@
 */
 
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use toml::de::Error as TomlError; // For handling deserialization errors
+use toml::Spanned;
 
 mod build_settings;
 mod custom_build_rule;
 mod dependencies;
 mod r#override;
+mod preset;
+mod profile;
 mod subproject;
+mod toolchain;
 
-use crate::error::{Error, ErrorType};
-use build_settings::BuildSettings;
-use custom_build_rule::CustomBuildRule;
-use dependencies::Dependencies;
-use r#override::Override;
-use subproject::SubProject;
+use crate::{
+    build::BuildMode,
+    error::{AdditionalInfo, Error, ErrorType, Warning},
+};
+use build_settings::{check_parallel_jobs, check_partial_compiler, parallel_jobs_warning};
+pub use build_settings::{find_in_path, set_probe_cache_enabled, BuildSettings, MAX_PARALLEL_JOBS};
+use custom_build_rule::{expand_path, expansion_span, ExpansionError};
+pub use custom_build_rule::{normalize_extension, CustomBuildRule, CustomBuildRuleType};
+pub use dependencies::{
+    Dependencies, DependencyRef, ManualDependency, PkgConfigDependency, RemoteDependency,
+};
+pub use preset::Preset;
+pub use profile::{Profile, BUILT_IN_PROFILES};
+pub use r#override::{Override, ResolvedSettings};
+use subproject::check_define;
+pub use subproject::{
+    is_cxx_header, is_cxx_source, LibraryKind, SubProject, SubProjectDependency, SubProjectType,
+};
+pub use toolchain::Toolchain;
+
+// Name of the config file at the root of every iceforge project
+pub const CONFIG_FILE: &str = "iceforge.toml";
+
+static UNUSED_WARNINGS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_unused_warnings_enabled(enabled: bool) {
+    UNUSED_WARNINGS_ENABLED.store(enabled, Ordering::Relaxed);
+}
 
 // Main struct representing the entire configuration
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BuildConfig {
     pub build: BuildSettings,
+    pub toolchain: Option<Toolchain>,
+    #[serde(default)]
     pub dependencies: Dependencies,
     pub subprojects: Vec<SubProject>,
     pub custom_build_rules: Option<Vec<CustomBuildRule>>,
     pub overrides: Option<Vec<Override>>,
+    pub presets: Option<BTreeMap<String, Preset>>,
+    pub profiles: Option<BTreeMap<Spanned<String>, Profile>>,
+    // Text the config was parsed from, which spans in errors refer to
+    #[serde(skip)]
+    pub source: String,
+    // Dependencies limited to other platforms than the current one, left out of
+    // dependencies when the config is loaded
+    #[serde(skip)]
+    pub other_platform_dependencies: Dependencies,
+    // Found while loading and verifying the config, for the caller to report
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
 }
 
 impl BuildConfig {
-    pub fn load_config(file_path: &str) -> Result<Self, Error> {
-        // Read the TOML file
-        let content = fs::read_to_string(file_path).expect("Failed to read the config file");
+    pub fn read_source(file_path: &str) -> Result<String, Error> {
+        fs::read_to_string(file_path).map_err(|e| Error {
+            error_type: ErrorType::ConfigReadError,
+            message: format!("Failed to read the config file: {}", e),
+            span: None,
+            additional_info: None,
+        })
+    }
+
+    pub fn from_source(content: &str) -> Result<Self, Error> {
         // Parse the TOML content into the BuildConfig struct
-        let config: Result<Self, TomlError> = toml::from_str(&content);
+        let config: Result<Self, TomlError> = toml::from_str(content);
         match config {
             Err(e) => Err(Error {
                 error_type: ErrorType::TomlParseError,
@@ -57,23 +108,560 @@ impl BuildConfig {
                 span: e.span(),
                 additional_info: None,
             }),
-            Ok(config) => Ok(config),
+            Ok(mut config) => {
+                config.source = content.to_string();
+                config.dependencies.check_platforms()?;
+                config.other_platform_dependencies =
+                    config.dependencies.split_off_other_platforms();
+                config.interpolate_paths()?;
+                if let Some(toolchain) = &config.toolchain {
+                    toolchain.apply(&mut config.build);
+                }
+                Ok(config)
+            }
         }
     }
 
-    pub fn verify_config(&mut self) -> Result<(), Error> {
-        self.build.check_compiler_details()?;
-        self.dependencies.check_dependencies()?;
-        let new_subprojects =
-            SubProject::verify_subprojects(self.subprojects.clone(), &self.dependencies.clone())?;
-        self.subprojects = new_subprojects;
+    // Subprojects are built without the dependencies of other platforms, with a warning as
+    // they may need them
+    fn drop_other_platform_references(&mut self) {
+        let current = self.dependencies.name_index();
+        let subproject_names: HashSet<String> = self
+            .subprojects
+            .iter()
+            .map(|subproject| subproject.name.get_ref().clone())
+            .collect();
+        for subproject in &mut self.subprojects {
+            let Some(deps) = &mut subproject.dependencies else {
+                continue;
+            };
+            deps.retain(|dep| {
+                let name = dep.get_ref().name();
+                let Some(other) = self
+                    .other_platform_dependencies
+                    .iter()
+                    .find(|other| other.name() == name)
+                else {
+                    return true;
+                };
+                // The name may also be in use on this platform
+                if current.contains(name) || subproject_names.contains(name) {
+                    return true;
+                }
+                let platforms: Vec<&str> = other
+                    .platforms()
+                    .into_iter()
+                    .flatten()
+                    .map(|platform| platform.get_ref().as_str())
+                    .collect();
+                self.warnings.push(Warning(Error {
+                    error_type: ErrorType::OtherPlatformDependency,
+                    message: format!(
+                        "{} depends on {}, which is only used on {}",
+                        subproject.name.get_ref(),
+                        name,
+                        platforms.join(", ")
+                    ),
+                    span: Some(dep.span()),
+                    additional_info: Some(AdditionalInfo {
+                        span: other.name_span(),
+                        message: format!("{} defined here", name),
+                    }),
+                }));
+                false
+            });
+        }
+    }
+
+    // Expand `${VAR}` and `$VAR` references in path-like fields against the environment
+    // and the PROJECT_ROOT built-in. Custom build rule commands are expanded when they run.
+    fn interpolate_paths(&mut self) -> Result<(), Error> {
+        let project_root = std::env::current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let lookup = |name: &str| match name {
+            "PROJECT_ROOT" => Some(project_root.clone()),
+            _ => std::env::var(name).ok(),
+        };
+        let undefined = |e: ExpansionError, span| Error {
+            error_type: ErrorType::UndefinedVariable,
+            message: e.message,
+            span: Some(span),
+            additional_info: None,
+        };
+        let interpolate = |field: &mut Spanned<String>| -> Result<(), Error> {
+            let expanded = expand_path(field.get_ref(), &lookup).map_err(|e| {
+                let span = expansion_span(field, &e);
+                undefined(e, span)
+            })?;
+            *field.get_mut() = expanded;
+            Ok(())
+        };
+        // Entries of a list have no span of their own, errors point at the list
+        let interpolate_list = |field: &mut Spanned<Vec<String>>| -> Result<(), Error> {
+            let span = field.span();
+            for entry in field.get_mut() {
+                *entry = expand_path(entry, &lookup).map_err(|e| undefined(e, span.clone()))?;
+            }
+            Ok(())
+        };
+
+        for field in std::iter::once(&mut self.build.compiler)
+            .chain(&mut self.build.cxx_compiler)
+            .chain(&mut self.build.compiler_launcher)
+        {
+            interpolate(field)?;
+        }
+        for subproject in &mut self.subprojects {
+            for field in subproject
+                .src_dir
+                .iter_mut()
+                .chain(subproject.src_dirs.iter_mut().flatten())
+                .chain(subproject.include_dirs.iter_mut().flatten())
+                .chain(&mut subproject.pch)
+                .chain(&mut subproject.compiler)
+            {
+                interpolate(field)?;
+            }
+            for field in subproject.sources.iter_mut().chain(&mut subproject.exclude) {
+                interpolate_list(field)?;
+            }
+        }
+        for over in self.overrides.iter_mut().flatten() {
+            if let Some(compiler) = &mut over.compiler {
+                interpolate(compiler)?;
+            }
+        }
+        for remote in &mut self.dependencies.remote {
+            let remote = remote.get_mut();
+            for field in std::iter::once(&mut remote.source)
+                .chain(&mut remote.include_dirs)
+                .chain(&mut remote.build_output)
+            {
+                interpolate(field)?;
+            }
+        }
+        for manual in &mut self.dependencies.manual {
+            let manual = manual.get_mut();
+            for field in manual
+                .include_dirs
+                .iter_mut()
+                .chain(manual.lib_dirs.iter_mut())
+                .flatten()
+            {
+                interpolate(field)?;
+            }
+        }
+        if let Some(toolchain) = &mut self.toolchain {
+            for field in toolchain
+                .cc
+                .iter_mut()
+                .chain(&mut toolchain.cxx)
+                .chain(&mut toolchain.ar)
+                .chain(&mut toolchain.sysroot)
+            {
+                interpolate(field)?;
+            }
+        }
+        for rule in self.custom_build_rules.iter_mut().flatten() {
+            interpolate(&mut rule.src_dir)?;
+            interpolate(&mut rule.output_dir)?;
+        }
+        Ok(())
+    }
+
+    // Target triple to build for when none is given on the command line
+    pub fn default_target(&self) -> Option<&str> {
+        self.toolchain
+            .as_ref()
+            .and_then(|toolchain| toolchain.target.as_ref())
+            .map(|target| target.get_ref().as_str())
+    }
 
+    // Global build settings with the subproject's override, if any, layered on top, then
+    // the compiler and C standard set on the subproject itself. The flags of the preset
+    // given on the command line come next, and those of the subproject's preset last.
+    pub fn resolved_settings(
+        &self,
+        subproject_name: &str,
+        preset: Option<&str>,
+    ) -> ResolvedSettings {
+        let mut resolved = self.override_settings(subproject_name);
+        let subproject = self
+            .subprojects
+            .iter()
+            .find(|subproject| subproject.name.get_ref() == subproject_name);
+        if let Some(subproject) = subproject {
+            if let Some(compiler) = &subproject.compiler {
+                resolved.compiler = compiler.get_ref().clone();
+            }
+            if let Some(c_standard) = &subproject.c_standard {
+                resolved.c_standard = c_standard.get_ref().clone();
+            }
+        }
+        let subproject_preset = subproject
+            .and_then(|subproject| subproject.preset.as_ref())
+            .map(|preset| preset.get_ref().as_str());
+        for name in preset.into_iter().chain(subproject_preset) {
+            if let Some(preset) = self.presets.as_ref().and_then(|presets| presets.get(name)) {
+                preset.apply(&mut resolved);
+            }
+        }
+        resolved
+    }
+
+    // The preset called name, for --preset
+    pub fn preset(&self, name: &str) -> Result<&Preset, Error> {
+        let presets = self.presets.as_ref();
+        presets
+            .and_then(|presets| presets.get(name))
+            .ok_or_else(|| Preset::unknown(presets, name, None))
+    }
+
+    // The [profiles] table of a profile, built-in ones only have one when it adds to them
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+    }
+
+    // The build mode of the profile called name, for --profile
+    pub fn build_mode(&self, name: &str) -> Result<BuildMode, Error> {
+        match name {
+            "debug" => Ok(BuildMode::Debug),
+            "release" => Ok(BuildMode::Release),
+            _ if self.profile(name).is_some() => Ok(BuildMode::Profile(name.to_string())),
+            _ => Err(Profile::unknown(self.profiles.as_ref(), name, None)),
+        }
+    }
+
+    fn override_settings(&self, subproject_name: &str) -> ResolvedSettings {
+        Override::resolve_for(
+            self.overrides.as_deref().unwrap_or_default(),
+            &self.build,
+            subproject_name,
+        )
+    }
+
+    // A field the subproject doesn't set is checked with the value of its override, or
+    // the global one
+    fn check_subproject_compilers(&self) -> Vec<Error> {
+        self.subprojects
+            .iter()
+            .filter_map(|subproject| {
+                let inherited = self.override_settings(subproject.name.get_ref());
+                check_partial_compiler(
+                    subproject.compiler.as_ref(),
+                    subproject.c_standard.as_ref(),
+                    &inherited.compiler,
+                    &inherited.c_standard,
+                )
+                .err()
+            })
+            .collect()
+    }
+
+    // A subproject named like a dependency would make subproject dependencies ambiguous
+    fn check_name_shadowing(&self) -> Vec<Error> {
+        self.subprojects
+            .iter()
+            .filter_map(|subproject| {
+                let dep = self
+                    .dependencies
+                    .iter()
+                    .find(|dep| dep.name() == subproject.name.get_ref())?;
+                Some(Error {
+                    error_type: ErrorType::NameShadowing,
+                    message: format!(
+                        "Subproject {} has the same name as a dependency",
+                        subproject.name.get_ref()
+                    ),
+                    span: Some(subproject.name.span()),
+                    additional_info: Some(AdditionalInfo {
+                        span: dep.name_span(),
+                        message: "Dependency defined here".to_string(),
+                    }),
+                })
+            })
+            .collect()
+    }
+
+    // Declared dependencies no subproject depends on
+    pub fn unused_dependencies(&self) -> Vec<DependencyRef<'_>> {
+        let used: Vec<&str> = self
+            .subprojects
+            .iter()
+            .flat_map(|subproject| subproject.dependencies.iter().flatten())
+            .map(|dep| dep.get_ref().name())
+            .collect();
+        let mut unused: Vec<DependencyRef> = self
+            .dependencies
+            .iter()
+            .filter(|dep| !used.contains(&dep.name()))
+            .collect();
+        unused.sort_by_key(|dep| dep.name_span().start);
+        unused
+    }
+
+    pub fn unused_dependency_errors(&self) -> Vec<Error> {
+        self.unused_dependencies()
+            .iter()
+            .map(|dep| Error {
+                error_type: ErrorType::UnusedDependency,
+                message: format!("Dependency {} is never used by any subproject", dep.name()),
+                span: Some(dep.name_span()),
+                additional_info: None,
+            })
+            .collect()
+    }
+
+    // The dependencies on their own and against the subproject names
+    pub fn check_dependencies(&self) -> Result<(), Vec<Error>> {
+        let mut errors = self
+            .dependencies
+            .check_dependencies()
+            .err()
+            .unwrap_or_default();
+        errors.extend(self.check_name_shadowing());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Every independent problem in the config is reported, not just the first one
+    pub fn verify_config(&mut self) -> Result<(), Vec<Error>> {
+        self.drop_other_platform_references();
+        let mut errors = Vec::new();
+        if let Err(e) = self.build.check_compiler_details() {
+            errors.push(e);
+        }
+        if let Some(Err(e)) = self.build.parallel_jobs.as_ref().map(check_parallel_jobs) {
+            errors.push(e);
+        }
+        errors.extend(
+            self.build
+                .defines
+                .iter()
+                .flatten()
+                .filter_map(|define| check_define(define).err()),
+        );
+        if let Some(toolchain) = &self.toolchain {
+            errors.extend(toolchain.verify(&self.build));
+        }
+        if let Err(e) = self.check_dependencies() {
+            errors.extend(e);
+        }
+        match SubProject::verify_subprojects(self.subprojects.clone(), &self.dependencies) {
+            Ok(new_subprojects) => self.subprojects = new_subprojects,
+            Err(e) => errors.extend(e),
+        }
+
+        if let Some(overrides) = &self.overrides {
+            if let Err(e) = Override::verify_overrides(overrides, &self.subprojects, &self.build) {
+                errors.extend(e);
+            }
+        }
+        errors.extend(self.check_subproject_compilers());
+        errors.extend(Preset::verify_presets(
+            self.presets.as_ref(),
+            &self.subprojects,
+        ));
+        errors.extend(Profile::verify_profiles(self.profiles.as_ref()));
+        if let Some(custom_build_rules) = &self.custom_build_rules {
+            if let Err(e) = CustomBuildRule::verify_custom_build_rules(
+                custom_build_rules,
+                self.build.compiler.get_ref(),
+            ) {
+                errors.extend(e);
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        self.warnings.extend(
+            self.build
+                .parallel_jobs
+                .as_ref()
+                .and_then(parallel_jobs_warning),
+        );
+        self.warnings.extend(self.dependencies.warnings());
+        self.warnings
+            .extend(SubProject::warnings(&self.subprojects));
         if let Some(overrides) = &self.overrides {
-            Override::verify_overrides(overrides, &self.subprojects)?;
+            self.warnings.extend(Override::warnings(overrides));
+        }
+        // Unused dependencies are still fetched and built, and are usually stale config
+        if UNUSED_WARNINGS_ENABLED.load(Ordering::Relaxed) {
+            self.warnings
+                .extend(self.unused_dependency_errors().into_iter().map(Warning));
         }
+        // Overlapping rules are legitimate when their commands don't clash, `check --strict`
+        // treats them as errors
         if let Some(custom_build_rules) = &self.custom_build_rules {
-            CustomBuildRule::verify_custom_build_rules(custom_build_rules)?;
+            self.warnings.extend(
+                CustomBuildRule::conflicting_rules(custom_build_rules)
+                    .into_iter()
+                    .map(Warning),
+            );
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subprojects_named_like_a_dependency_are_reported() {
+        let config = BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "gcc"
+
+[[dependencies.manual]]
+name = "glfw"
+ldflags = "-lglfw"
+
+[[subprojects]]
+name = "glfw"
+type = "library"
+src_dir = "src/glfw"
+include_dirs = ["src/glfw/include"]
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+include_dirs = []
+"#,
+        )
+        .unwrap();
+        let errors = config.check_name_shadowing();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].error_type, ErrorType::NameShadowing));
+        assert_eq!(
+            errors[0].message,
+            "Subproject glfw has the same name as a dependency"
+        );
+        let dependency = errors[0].additional_info.as_ref().unwrap();
+        assert_eq!(dependency.message, "Dependency defined here");
+        assert_ne!(Some(dependency.span.clone()), errors[0].span);
+    }
+
+    // Subprojects setting one of compiler and c_standard, with an override setting both
+    fn config_with_override() -> BuildConfig {
+        BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+compiler = "gcc"
+
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+c_standard = "c17x"
+
+[[overrides]]
+name = "app"
+compiler = "clang"
+c_standard = "c99x"
+cflags = "-O1"
+
+[[overrides]]
+name = "core"
+compiler = "tcc"
+c_standard = "c99"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn subproject_compiler_settings_win_over_its_override() {
+        let config = config_with_override();
+        let app = config.resolved_settings("app", None);
+        assert_eq!(app.compiler, "gcc");
+        assert_eq!(app.c_standard, "c99x");
+        assert_eq!(app.cflags.as_deref(), Some("-O1"));
+        let core = config.resolved_settings("core", None);
+        assert_eq!(core.compiler, "tcc");
+        assert_eq!(core.c_standard, "c17x");
+    }
+
+    #[test]
+    fn subproject_compilers_are_checked_with_the_fields_of_their_override() {
+        // Unknown standards are rejected before any compiler runs
+        let messages: Vec<String> = config_with_override()
+            .check_subproject_compilers()
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("Unknown C standard c99x"));
+        assert!(messages[1].starts_with("Unknown C standard c17x"));
+    }
+
+    #[test]
+    fn source_patterns_expand_environment_variables() {
+        std::env::set_var("ICEFORGE_TEST_SDK", "/opt/sdk");
+        let config = BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[[subprojects]]
+name = "app"
+type = "binary"
+sources = ["$ICEFORGE_TEST_SDK/src/*.c", "${ICEFORGE_TEST_SDK}/gen/**/*.c"]
+exclude = ["$ICEFORGE_TEST_SDK/src/test_*.c"]
+pch = "$ICEFORGE_TEST_SDK/include/pch.h"
+"#,
+        )
+        .unwrap();
+        let app = &config.subprojects[0];
+        assert_eq!(
+            app.sources.as_ref().unwrap().get_ref(),
+            &["/opt/sdk/src/*.c", "/opt/sdk/gen/**/*.c"]
+        );
+        assert_eq!(
+            app.exclude.as_ref().unwrap().get_ref(),
+            &["/opt/sdk/src/test_*.c"]
+        );
+        assert_eq!(
+            app.pch.as_ref().unwrap().get_ref(),
+            "/opt/sdk/include/pch.h"
+        );
+
+        let source = r#"
+subprojects = [{ name = "app", type = "binary", sources = ["$ICEFORGE_TEST_UNSET/*.c"] }]
+
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+"#;
+        let error = BuildConfig::from_source(source).unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::UndefinedVariable));
+        assert_eq!(error.message, "Undefined variable $ICEFORGE_TEST_UNSET");
+        assert_eq!(
+            &source[error.span.unwrap()],
+            r#"["$ICEFORGE_TEST_UNSET/*.c"]"#
+        );
+    }
+}
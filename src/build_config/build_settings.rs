@@ -17,78 +17,417 @@
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicBool, Ordering},
+    time::UNIX_EPOCH,
+};
 use toml::Spanned;
 
-use super::{Error, ErrorType};
+use super::{Error, ErrorType, Warning};
+use crate::build::{split_shell_words, BUILD_DIR};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BuildSettings {
     pub version: String,
     pub c_standard: Spanned<String>,
     pub compiler: Spanned<String>,
-    pub global_cflags: Option<String>,
-    pub debug_flags: Option<String>,
-    pub release_flags: Option<String>,
-    pub parallel_jobs: Option<u32>,
+    // Standard and compiler for C++ sources, the compiler defaults to the C++
+    // driver matching the C compiler
+    pub cpp_standard: Option<Spanned<String>>,
+    pub cxx_compiler: Option<Spanned<String>>,
+    pub global_cflags: Option<Spanned<String>>,
+    pub debug_flags: Option<Spanned<String>>,
+    pub release_flags: Option<Spanned<String>>,
+    pub parallel_jobs: Option<Spanned<u32>>,
+    // Preprocessor definitions for every subproject, which its own defines win over
+    pub defines: Option<Vec<Spanned<String>>>,
+    // Program every compile runs through, such as ccache or sccache
+    pub compiler_launcher: Option<Spanned<String>>,
+}
+
+// Launcher used instead of compiler_launcher when set, for caching without editing the config
+pub const COMPILER_LAUNCHER_VAR: &str = "CRYO_COMPILER_LAUNCHER";
+
+// More jobs than this only cost memory and threads, larger values are clamped
+pub const MAX_PARALLEL_JOBS: u32 = 1024;
+
+// A job count of 0 would never start a compile
+pub fn check_parallel_jobs(jobs: &Spanned<u32>) -> Result<(), Error> {
+    if *jobs.get_ref() == 0 {
+        return Err(Error {
+            error_type: ErrorType::InvalidParallelJobs,
+            message: "parallel_jobs must be at least 1".to_string(),
+            span: Some(jobs.span()),
+            additional_info: None,
+        });
+    }
+    Ok(())
+}
+
+pub fn parallel_jobs_warning(jobs: &Spanned<u32>) -> Option<Warning> {
+    (*jobs.get_ref() > MAX_PARALLEL_JOBS).then(|| {
+        Warning(Error {
+            error_type: ErrorType::InvalidParallelJobs,
+            message: format!(
+                "parallel_jobs of {} is more than {}, {} jobs are used",
+                jobs.get_ref(),
+                MAX_PARALLEL_JOBS,
+                MAX_PARALLEL_JOBS
+            ),
+            span: Some(jobs.span()),
+            additional_info: None,
+        })
+    })
+}
+
+// Values of -std for C that gcc and clang accept, checked before probing the compiler
+const KNOWN_C_STANDARDS: [&str; 22] = [
+    "c89",
+    "c90",
+    "c99",
+    "c11",
+    "c17",
+    "c18",
+    "c23",
+    "c2x",
+    "gnu89",
+    "gnu90",
+    "gnu99",
+    "gnu11",
+    "gnu17",
+    "gnu18",
+    "gnu23",
+    "gnu2x",
+    "iso9899:1990",
+    "iso9899:199409",
+    "iso9899:1999",
+    "iso9899:2011",
+    "iso9899:2017",
+    "iso9899:2018",
+];
+
+// Number of single character insertions, deletions and substitutions turning a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// The known C standard closest to an unknown one, if any is close enough to be a typo
+fn suggest_c_standard(standard: &str) -> Option<&'static str> {
+    KNOWN_C_STANDARDS
+        .iter()
+        .map(|known| (edit_distance(standard, known), *known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+// Successful compiler probes, one per line, so later runs can skip them
+const PROBE_CACHE_FILE: &str = "compiler-probes";
+
+static PROBE_CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_probe_cache_enabled(enabled: bool) {
+    PROBE_CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// Whether the compiler accepts an empty source in language with the given standard
+fn probe_compiler(compiler: &Path, language: &str, standard: &str) -> bool {
+    let output = Command::new(compiler)
+        .arg(format!("-std={}", standard))
+        .arg("-o") // Dummy output
+        .arg("/dev/null") // Just discard any output file
+        .arg("-x") // Specify the language
+        .arg(language)
+        .arg("-c") // Compile only, don't link
+        .arg("-") // Read from stdin
+        .output();
+    output.is_ok_and(|output| output.status.success())
+}
+
+// Same as probe_compiler, but remembers successes under the build directory. The
+// cache key includes the compiler's resolved path and modification time, so
+// replacing or upgrading the compiler probes it again.
+fn probe_compiler_cached(compiler: &Path, language: &str, standard: &str) -> bool {
+    let resolved = fs::canonicalize(compiler).unwrap_or_else(|_| compiler.to_path_buf());
+    let modified = fs::metadata(&resolved)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    let (Some(modified), true) = (modified, PROBE_CACHE_ENABLED.load(Ordering::Relaxed)) else {
+        return probe_compiler(compiler, language, standard);
+    };
+    let key = format!(
+        "{}\t{}\t{}\t{}",
+        language,
+        resolved.display(),
+        modified.as_nanos(),
+        standard
+    );
+    let cache = Path::new(BUILD_DIR).join(PROBE_CACHE_FILE);
+    if fs::read_to_string(&cache).is_ok_and(|content| content.lines().any(|line| line == key)) {
+        return true;
+    }
+    if !probe_compiler(compiler, language, standard) {
+        return false;
+    }
+    // Failing to cache only costs a probe next time
+    let _ = fs::create_dir_all(BUILD_DIR).and_then(|_| {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&cache)
+            .and_then(|mut file| writeln!(file, "{}", key))
+    });
+    true
+}
+
+// Locate a program the way the shell would, accepting explicit paths as is
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(program).is_file().then(|| PathBuf::from(program));
+    }
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+// The C++ driver belonging to a C compiler, e.g. g++ for gcc
+pub fn default_cxx_compiler(compiler: &str) -> String {
+    if let Some(prefix) = compiler.strip_suffix("gcc") {
+        format!("{}g++", prefix)
+    } else if compiler.ends_with("clang") {
+        format!("{}++", compiler)
+    } else if let Some(prefix) = compiler.strip_suffix("cc") {
+        format!("{}c++", prefix)
+    } else {
+        "c++".to_string()
+    }
 }
 
 impl BuildSettings {
-    pub fn check_compiler_details(&self) -> Result<(), Error> {
-        // NOTE: Compiler details
-        // Check if the compiler is in the path
-        // Check if the standard is supported
-        let compiler = self.compiler.clone();
-        let compiler_span = compiler.span();
-        let compiler_name = compiler.into_inner();
-
-        // Check if the compiler is in the path
-        let compiler_path = Command::new("sh")
-            .arg("-c")
-            .arg(format!("which {}", compiler_name))
-            .output();
-        let compiler_path = if let Ok(compiler_path) = compiler_path {
-            let output = String::from_utf8(compiler_path.stdout).unwrap();
-            let output = output.split_whitespace().next();
-            if let Some(output) = output {
-                output.to_string()
-            } else {
-                return Err(Error {
-                    error_type: ErrorType::IncorrectCompiler,
-                    message: "Compiler not in path".to_string(),
-                    span: Some(compiler_span),
-                    additional_info: None,
-                });
+    pub fn cxx_compiler(&self) -> String {
+        self.cxx_compiler.as_ref().map_or_else(
+            || default_cxx_compiler(self.compiler.get_ref()),
+            |cxx| cxx.get_ref().clone(),
+        )
+    }
+
+    // The launcher of the environment wins over the one of the config
+    pub fn compiler_launcher(&self) -> Option<String> {
+        std::env::var(COMPILER_LAUNCHER_VAR)
+            .ok()
+            .filter(|launcher| !launcher.is_empty())
+            .or_else(|| {
+                self.compiler_launcher
+                    .as_ref()
+                    .map(|launcher| launcher.get_ref().clone())
+            })
+    }
+
+    fn check_compiler_launcher(&self) -> Result<(), Error> {
+        let Some(launcher) = self.compiler_launcher() else {
+            return Ok(());
+        };
+        if find_in_path(&launcher).is_some() {
+            return Ok(());
+        }
+        // Only a launcher from the config has a place in it to point at
+        let configured = self
+            .compiler_launcher
+            .as_ref()
+            .filter(|configured| *configured.get_ref() == launcher);
+        Err(Error {
+            error_type: ErrorType::IncorrectCompiler,
+            message: match configured {
+                Some(_) => format!("Compiler launcher {} not in path", launcher),
+                None => format!(
+                    "Compiler launcher {} from {} not in path",
+                    launcher, COMPILER_LAUNCHER_VAR
+                ),
+            },
+            span: configured.map(Spanned::span),
+            additional_info: None,
+        })
+    }
+
+    // Only checked when C++ is configured, C-only projects needn't have a C++ compiler
+    fn check_cxx_details(&self) -> Result<(), Error> {
+        let Some(cpp_standard) = &self.cpp_standard else {
+            if let Some(cxx_compiler) = &self.cxx_compiler {
+                if find_in_path(cxx_compiler.get_ref()).is_none() {
+                    return Err(Error {
+                        error_type: ErrorType::IncorrectCompiler,
+                        message: "C++ compiler not in path".to_string(),
+                        span: Some(cxx_compiler.span()),
+                        additional_info: None,
+                    });
+                }
             }
-        } else {
+            return Ok(());
+        };
+        let cxx_compiler = self.cxx_compiler();
+        let Some(cxx_path) = find_in_path(&cxx_compiler) else {
             return Err(Error {
                 error_type: ErrorType::IncorrectCompiler,
-                message: "Compiler not in path".to_string(),
-                span: Some(compiler_span),
+                message: format!("C++ compiler {} not in path", cxx_compiler),
+                span: Some(
+                    self.cxx_compiler
+                        .as_ref()
+                        .map_or(cpp_standard.span(), |cxx| cxx.span()),
+                ),
                 additional_info: None,
             });
         };
-        let c_standard = self.c_standard.clone();
-        let c_standard_span = c_standard.span();
-        let c_standard = c_standard.into_inner();
-        let output = Command::new(compiler_path)
-            .arg(format!("-std={}", c_standard))
-            .arg("-o") // Dummy output
-            .arg("/dev/null") // Just discard any output file
-            .arg("-x") // Specify language C
-            .arg("c") // Use C language
-            .arg("-c") // Compile only, don't link
-            .arg("-") // Read from stdin
-            .output();
-
-        if output.is_err() || output.unwrap().status.code() != Some(0) {
+        if !probe_compiler_cached(&cxx_path, "c++", cpp_standard.get_ref()) {
             return Err(Error {
                 error_type: ErrorType::UnsupportedCStandard,
-                message: "Unsupported C standard".to_string(),
-                span: Some(c_standard_span),
+                message: "Unsupported C++ standard".to_string(),
+                span: Some(cpp_standard.span()),
+                additional_info: None,
+            });
+        }
+        Ok(())
+    }
+
+    // Compile an empty translation unit with each of the global flag fields to catch flags
+    // the compiler doesn't know, such as -Walll
+    pub fn check_flags(&self) -> Result<(), Error> {
+        let fields = [&self.global_cflags, &self.debug_flags, &self.release_flags];
+        for flags in fields.into_iter().flatten() {
+            let args = split_shell_words(flags.get_ref()).map_err(|offset| Error {
+                error_type: ErrorType::InvalidCompilerFlag,
+                message: format!(
+                    "Unterminated quote in flags: {}",
+                    &flags.get_ref()[offset..]
+                ),
+                span: Some(flags.span()),
+                additional_info: None,
+            })?;
+            let output = Command::new(self.compiler.get_ref())
+                .args(["-x", "c", "-fsyntax-only"])
+                .args(&args)
+                .arg("-")
+                .stdin(std::process::Stdio::null())
+                .output();
+            let output = match output {
+                Ok(output) if !output.status.success() => output,
+                _ => continue,
+            };
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let complaint = stderr
+                .lines()
+                .find(|line| line.contains("error"))
+                .unwrap_or(stderr.trim());
+            return Err(Error {
+                error_type: ErrorType::InvalidCompilerFlag,
+                message: format!(
+                    "{} rejects these flags: {}",
+                    self.compiler.get_ref(),
+                    complaint.trim()
+                ),
+                span: Some(flags.span()),
                 additional_info: None,
             });
         }
         Ok(())
     }
+
+    pub fn check_compiler_details(&self) -> Result<(), Error> {
+        check_c_compiler(&self.compiler, &self.c_standard)?;
+        self.check_cxx_details()?;
+        self.check_compiler_launcher()
+    }
+}
+
+// The compiler and C standard set by an override or a subproject are probed like the
+// global ones. A field left unset takes the value it would otherwise have, with errors
+// pointing at the field that is set.
+pub fn check_partial_compiler(
+    compiler: Option<&Spanned<String>>,
+    c_standard: Option<&Spanned<String>>,
+    default_compiler: &str,
+    default_c_standard: &str,
+) -> Result<(), Error> {
+    let (compiler, c_standard) = match (compiler, c_standard) {
+        (None, None) => return Ok(()),
+        (Some(compiler), Some(c_standard)) => (compiler.clone(), c_standard.clone()),
+        (Some(compiler), None) => (
+            compiler.clone(),
+            Spanned::new(compiler.span(), default_c_standard.to_string()),
+        ),
+        (None, Some(c_standard)) => (
+            Spanned::new(c_standard.span(), default_compiler.to_string()),
+            c_standard.clone(),
+        ),
+    };
+    check_c_compiler(&compiler, &c_standard)
+}
+
+// Check that a C compiler is in the path and supports a C standard, errors point at the
+// span of the offending value
+pub fn check_c_compiler(
+    compiler: &Spanned<String>,
+    c_standard: &Spanned<String>,
+) -> Result<(), Error> {
+    // NOTE: Compiler details
+    // Check if the compiler is in the path
+    // Check if the standard is supported
+    let compiler_span = compiler.span();
+    let compiler_name = compiler.get_ref();
+
+    // Reject typos without running the compiler
+    if !KNOWN_C_STANDARDS.contains(&c_standard.get_ref().as_str()) {
+        let message = match suggest_c_standard(c_standard.get_ref()) {
+            Some(suggestion) => format!(
+                "Unknown C standard {}, did you mean {}?",
+                c_standard.get_ref(),
+                suggestion
+            ),
+            None => format!(
+                "Unknown C standard {}, expected one of {}",
+                c_standard.get_ref(),
+                KNOWN_C_STANDARDS.join(", ")
+            ),
+        };
+        return Err(Error {
+            error_type: ErrorType::UnsupportedCStandard,
+            message,
+            span: Some(c_standard.span()),
+            additional_info: None,
+        });
+    }
+
+    // Check if the compiler is in the path
+    let Some(compiler_path) = find_in_path(compiler_name) else {
+        return Err(Error {
+            error_type: ErrorType::IncorrectCompiler,
+            message: format!("Compiler {} not in path", compiler_name),
+            span: Some(compiler_span),
+            additional_info: None,
+        });
+    };
+    if !probe_compiler_cached(&compiler_path, "c", c_standard.get_ref()) {
+        return Err(Error {
+            error_type: ErrorType::UnsupportedCStandard,
+            message: "Unsupported C standard".to_string(),
+            span: Some(c_standard.span()),
+            additional_info: None,
+        });
+    }
+    Ok(())
 }
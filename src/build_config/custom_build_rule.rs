@@ -17,11 +17,15 @@
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
 use serde::{Deserialize, Serialize};
+use std::{
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
 use toml::Spanned;
 
 use crate::error::{AdditionalInfo, Error, ErrorType};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum CustomBuildRuleType {
     IfChanged,
@@ -34,23 +38,299 @@ pub enum CustomBuildRuleType {
 pub struct CustomBuildRule {
     pub name: Spanned<String>,
     pub description: Option<String>,
-    pub src_dir: String,
-    pub output_dir: String,
+    pub src_dir: Spanned<String>,
+    pub output_dir: Spanned<String>,
     pub trigger_extensions: Vec<String>,
     pub output_extension: String,
-    pub command: String,
+    pub command: Spanned<String>,
     pub rebuild_rule: CustomBuildRuleType,
 }
 
+// Placeholders substituted with the input file in a rule's command
+const INPUT_PLACEHOLDERS: [&str; 2] = ["${in}", "$in"];
+
+// Extensions may be written with or without their leading dot
+fn is_valid_extension(ext: &str) -> bool {
+    let ext = normalize_extension(ext);
+    !ext.is_empty()
+        && !ext
+            .chars()
+            .any(|c| c == '.' || c == '/' || c == '\\' || c.is_whitespace())
+}
+
+pub fn normalize_extension(ext: &str) -> &str {
+    ext.strip_prefix('.').unwrap_or(ext)
+}
+
+// Byte index of the brace closing the one text starts with
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// A reference that couldn't be expanded, located by the byte range it covers
+#[derive(Debug)]
+pub struct ExpansionError {
+    pub message: String,
+    pub offset: usize,
+    pub length: usize,
+}
+
+// Expand `${NAME}` references in a command using lookup. References may nest, as in
+// `${CC_${ARCH}}`, and `$$` stands for a literal `$`.
+pub fn expand_command(
+    command: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, ExpansionError> {
+    expand_references(command, lookup, false)
+}
+
+// Expand a path like a command, and bare `$NAME` references too. Commands leave those to
+// the shell.
+pub fn expand_path(
+    path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, ExpansionError> {
+    expand_references(path, lookup, true)
+}
+
+// Length of the bare `$NAME` reference text starts with, if it is one
+fn bare_reference(text: &str) -> Option<usize> {
+    let name = text.strip_prefix('$')?;
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let length = name
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(name.len());
+    Some(1 + length)
+}
+
+fn expand_references(
+    command: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    bare: bool,
+) -> Result<String, ExpansionError> {
+    let mut expanded = String::new();
+    let mut rest = command;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let offset = command.len() - rest.len() + index;
+        let reference = &rest[index..];
+        if let Some(end) = bare.then(|| bare_reference(reference)).flatten() {
+            let name = &reference[1..end];
+            let Some(value) = lookup(name) else {
+                return Err(ExpansionError {
+                    message: format!("Undefined variable ${}", name),
+                    offset,
+                    length: end,
+                });
+            };
+            expanded.push_str(&value);
+            rest = &reference[end..];
+            continue;
+        }
+        if reference.starts_with("$$") || !reference.starts_with("${") {
+            expanded.push('$');
+            rest = &reference[if reference.starts_with("$$") { 2 } else { 1 }..];
+            continue;
+        }
+        let Some(end) = closing_brace(&reference[1..]).map(|end| 1 + end) else {
+            let reference = reference.split_whitespace().next().unwrap_or(reference);
+            return Err(ExpansionError {
+                message: format!("Unterminated variable reference {}", reference),
+                offset,
+                length: reference.len(),
+            });
+        };
+        let name =
+            expand_references(&reference[2..end], lookup, bare).map_err(|e| ExpansionError {
+                offset: offset + 2 + e.offset,
+                ..e
+            })?;
+        match lookup(&name) {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                return Err(ExpansionError {
+                    message: format!("Undefined variable ${{{}}}", name),
+                    offset,
+                    length: end + 1,
+                })
+            }
+        }
+        rest = &reference[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// Span of a failed reference within a string value, past its opening quote. Escapes
+// in the string can shift it, so it is kept inside the value's span.
+pub fn expansion_span(value: &Spanned<String>, error: &ExpansionError) -> Range<usize> {
+    let span = value.span();
+    let start = (span.start + 1 + error.offset).min(span.end);
+    start..(start + error.length).min(span.end)
+}
+
 impl CustomBuildRule {
-    pub fn verify_custom_build_rules(selfs: &[Self]) -> Result<(), Error> {
+    // The command to run for one input file, with built-in, placeholder and
+    // environment variables expanded
+    pub fn expand(&self, compiler: &str, input: &Path, output: &Path) -> Result<String, Error> {
+        let project_root = std::env::current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let lookup = |name: &str| match name {
+            "in" => Some(input.to_string_lossy().to_string()),
+            "out" => Some(output.to_string_lossy().to_string()),
+            "PROJECT_ROOT" => Some(project_root.clone()),
+            "SRC_DIR" => Some(self.src_dir.get_ref().clone()),
+            "OUTPUT_DIR" => Some(self.output_dir.get_ref().clone()),
+            "COMPILER" => Some(compiler.to_string()),
+            _ => std::env::var(name).ok(),
+        };
+        let command = expand_command(self.command.get_ref(), &lookup).map_err(|e| Error {
+            error_type: ErrorType::UndefinedVariable,
+            message: format!("{} in custom build rule {}", e.message, self.name.get_ref()),
+            span: Some(expansion_span(&self.command, &e)),
+            additional_info: None,
+        })?;
+        Ok(command
+            .replace("$in", &input.to_string_lossy())
+            .replace("$out", &output.to_string_lossy()))
+    }
+
+    // Pairs of rules that would both process the same files, because one's source directory
+    // contains the other's and they share a trigger extension
+    pub fn conflicting_rules(selfs: &[Self]) -> Vec<Error> {
+        let normalize = |dir: &str| -> PathBuf {
+            Path::new(dir)
+                .components()
+                .filter(|component| *component != Component::CurDir)
+                .collect()
+        };
+        let mut conflicts = Vec::new();
+        for (index, first) in selfs.iter().enumerate() {
+            for second in &selfs[index + 1..] {
+                let first_dir = normalize(first.src_dir.get_ref());
+                let second_dir = normalize(second.src_dir.get_ref());
+                if !first_dir.starts_with(&second_dir) && !second_dir.starts_with(&first_dir) {
+                    continue;
+                }
+                let shared = first.trigger_extensions.iter().find(|ext| {
+                    second
+                        .trigger_extensions
+                        .iter()
+                        .any(|other| normalize_extension(ext) == normalize_extension(other))
+                });
+                if let Some(ext) = shared {
+                    // Files are processed twice below the deeper of the two directories
+                    let overlap = if first_dir.starts_with(&second_dir) {
+                        &first_dir
+                    } else {
+                        &second_dir
+                    };
+                    let overlap = if overlap.as_os_str().is_empty() {
+                        ".".to_string()
+                    } else {
+                        overlap.display().to_string()
+                    };
+                    conflicts.push(Error {
+                        error_type: ErrorType::ConflictingBuildRules,
+                        message: format!(
+                            "Custom build rules {} and {} both process .{} files in {}",
+                            first.name.get_ref(),
+                            second.name.get_ref(),
+                            normalize_extension(ext),
+                            overlap
+                        ),
+                        span: Some(second.name.span()),
+                        additional_info: Some(AdditionalInfo {
+                            span: first.name.span(),
+                            message: format!("{} defined here", first.name.get_ref()),
+                        }),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    // Verify that the rule can actually match and produce files
+    fn verify(&self, compiler: &str) -> Result<(), Error> {
+        let invalid = |message: String| Error {
+            error_type: ErrorType::InvalidCustomBuildRule,
+            message,
+            span: Some(self.name.span()),
+            additional_info: None,
+        };
+        let name = self.name.get_ref();
+        if self.trigger_extensions.is_empty() {
+            return Err(invalid(format!(
+                "Custom build rule {} has no trigger extensions",
+                name
+            )));
+        }
+        for ext in &self.trigger_extensions {
+            if !is_valid_extension(ext) {
+                return Err(invalid(format!(
+                    "Custom build rule {} has an invalid trigger extension \"{}\"",
+                    name, ext
+                )));
+            }
+        }
+        if !is_valid_extension(&self.output_extension) {
+            return Err(invalid(format!(
+                "Custom build rule {} has an invalid output extension \"{}\"",
+                name, self.output_extension
+            )));
+        }
+        if !INPUT_PLACEHOLDERS
+            .iter()
+            .any(|placeholder| self.command.get_ref().contains(placeholder))
+        {
+            return Err(invalid(format!(
+                "Custom build rule {} command never references its input ($in or ${{in}})",
+                name
+            )));
+        }
+        // Catch undefined variables now rather than when the rule runs
+        self.expand(compiler, Path::new("in"), Path::new("out"))?;
+        // output_dir is created at build time, but it can't be shadowed by a file
+        if !Path::new(self.src_dir.get_ref()).is_dir() {
+            return Err(invalid(format!(
+                "Custom build rule {} source directory {} does not exist",
+                name,
+                self.src_dir.get_ref()
+            )));
+        }
+        let output_dir = Path::new(self.output_dir.get_ref());
+        if output_dir.exists() && !output_dir.is_dir() {
+            return Err(invalid(format!(
+                "Custom build rule {} output directory {} is not a directory",
+                name,
+                self.output_dir.get_ref()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn verify_custom_build_rules(selfs: &[Self], compiler: &str) -> Result<(), Vec<Error>> {
         // NOTE: Custom build rules
         // Verify duplicate custom build rule names are not present
         let mut name_set = std::collections::HashSet::new();
+        let mut errors = Vec::new();
 
         for cbr in selfs {
             if !name_set.insert(cbr.name.clone()) {
-                return Err(Error {
+                errors.push(Error {
                     error_type: ErrorType::DuplicateCustomBuildRuleName,
                     message: format!(
                         "Duplicate custom build rule name {}",
@@ -64,8 +344,101 @@ impl CustomBuildRule {
                 });
             }
         }
-        //  TODO: Verify that src_dir and output_dir exist
 
-        Ok(())
+        for cbr in selfs {
+            if let Err(e) = cbr.verify(compiler) {
+                errors.push(e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ARCH" => Some("arm".to_string()),
+            "CC_arm" => Some("arm-gcc".to_string()),
+            "in" => Some("main.c".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn nested_references_are_expanded_inside_out() {
+        let command = expand_command("${CC_${ARCH}} -c ${in}", &lookup).unwrap();
+        assert_eq!(command, "arm-gcc -c main.c");
+    }
+
+    #[test]
+    fn escaped_references_are_kept_literally() {
+        let command = expand_command("echo $${in} costs $$5 in ${in}", &lookup).unwrap();
+        assert_eq!(command, "echo ${in} costs $5 in main.c");
+    }
+
+    #[test]
+    fn failed_references_are_located_in_the_command() {
+        let error = expand_command("cc ${CC_${OS}}", &lookup).unwrap_err();
+        assert_eq!(error.message, "Undefined variable ${OS}");
+        assert_eq!((error.offset, error.length), (8, 5));
+
+        let error = expand_command("cc ${in", &lookup).unwrap_err();
+        assert_eq!(error.message, "Unterminated variable reference ${in");
+        assert_eq!((error.offset, error.length), (3, 4));
+    }
+
+    #[test]
+    fn paths_expand_bare_references_too() {
+        let path = expand_path("$ARCH/${CC_$ARCH}/$$in-$ARCH.c", &lookup).unwrap();
+        assert_eq!(path, "arm/arm-gcc/$in-arm.c");
+        assert_eq!(expand_command("$ARCH", &lookup).unwrap(), "$ARCH");
+
+        let error = expand_path("src/$OS/main.c", &lookup).unwrap_err();
+        assert_eq!(error.message, "Undefined variable $OS");
+        assert_eq!((error.offset, error.length), (4, 3));
+    }
+
+    fn rule_in(src_dir: &Path, output_dir: &Path) -> CustomBuildRule {
+        toml::from_str(&format!(
+            r#"
+name = "shaders"
+src_dir = "{}"
+output_dir = "{}"
+trigger_extensions = ["glsl"]
+output_extension = "spv"
+command = "glslc ${{in}} -o ${{out}}"
+rebuild_rule = "if-changed"
+"#,
+            src_dir.display(),
+            output_dir.display()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn rules_need_a_source_directory_but_not_an_output_one_yet() {
+        let dir = tempfile::tempdir().unwrap();
+        let shaders = dir.path().join("shaders");
+        std::fs::create_dir(&shaders).unwrap();
+        let output = dir.path().join("out");
+        assert!(rule_in(&shaders, &output).verify("cc").is_ok());
+
+        let error = rule_in(&dir.path().join("missing"), &output)
+            .verify("cc")
+            .unwrap_err();
+        assert!(error.message.contains("source directory"));
+        assert!(error.message.contains("does not exist"));
+
+        std::fs::write(&output, "").unwrap();
+        let error = rule_in(&shaders, &output).verify("cc").unwrap_err();
+        assert!(error.message.contains("is not a directory"));
     }
 }
@@ -16,26 +16,241 @@
 * You should have received a copy of the GNU General Public License
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
-use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, process::Command};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+    ops::Range,
+    path::Path,
+    process::Command,
+};
 use toml::Spanned;
 
-use crate::error::{AdditionalInfo, Error, ErrorType};
+use super::CONFIG_FILE;
+use crate::{
+    error::{AdditionalInfo, Error, ErrorType, Warning},
+    version::{is_requirement_like, parse_requirement},
+};
 
 // External dependencies (remote packages with versioning)
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Dependencies {
+    #[serde(default, deserialize_with = "named_entries")]
     pub remote: Vec<Spanned<RemoteDependency>>,
+    #[serde(default, deserialize_with = "named_entries")]
     pub pkg_config: Vec<Spanned<PkgConfigDependency>>,
+    #[serde(default, deserialize_with = "named_entries")]
     pub manual: Vec<Spanned<ManualDependency>>,
 }
 
-#[allow(clippy::large_enum_variant)]
-#[derive(Clone)]
-pub enum Dependency {
-    Remote(Spanned<RemoteDependency>),
-    PkgConfig(Spanned<PkgConfigDependency>),
-    Manual(Spanned<ManualDependency>),
+// Dependency kinds whose name can come from the key of a `[dependencies.<kind>.<name>]` table
+trait Named {
+    fn name_mut(&mut self) -> &mut Spanned<String>;
+}
+
+impl Named for RemoteDependency {
+    fn name_mut(&mut self) -> &mut Spanned<String> {
+        &mut self.name
+    }
+}
+
+impl Named for PkgConfigDependency {
+    fn name_mut(&mut self) -> &mut Spanned<String> {
+        &mut self.name
+    }
+}
+
+impl Named for ManualDependency {
+    fn name_mut(&mut self) -> &mut Spanned<String> {
+        &mut self.name
+    }
+}
+
+// Placeholder for a name left out of a dependency, filled from its table key
+fn unnamed() -> Spanned<String> {
+    Spanned::new(0..0, String::new())
+}
+
+// Accept a dependency kind either as an array of tables, each with a name, or as a table
+// of tables keyed by name
+fn named_entries<'de, D, T>(deserializer: D) -> Result<Vec<Spanned<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Named,
+{
+    struct EntriesVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de> + Named> Visitor<'de> for EntriesVisitor<T> {
+        type Value = Vec<Spanned<T>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an array of dependencies or a table of dependencies keyed by name")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut entries = Vec::new();
+            while let Some(mut entry) = seq.next_element::<Spanned<T>>()? {
+                if entry.get_mut().name_mut().get_ref().is_empty() {
+                    return Err(de::Error::missing_field("name"));
+                }
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut entries = Vec::new();
+            while let Some(key) = map.next_key::<Spanned<String>>()? {
+                let mut entry = map.next_value::<Spanned<T>>()?;
+                let name = entry.get_mut().name_mut();
+                if !name.get_ref().is_empty() {
+                    return Err(de::Error::custom(format!(
+                        "dependency {} is named by its table key and can't set name",
+                        key.get_ref()
+                    )));
+                }
+                *name = key;
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_any(EntriesVisitor(PhantomData))
+}
+
+// Operating systems a dependency can be limited to, named as in std::env::consts::OS
+pub const PLATFORMS: [&str; 11] = [
+    "linux",
+    "macos",
+    "windows",
+    "freebsd",
+    "netbsd",
+    "openbsd",
+    "dragonfly",
+    "android",
+    "ios",
+    "solaris",
+    "illumos",
+];
+
+// Whether a dependency limited to platforms is used on the current one
+fn on_current_platform(platforms: &Option<Vec<Spanned<String>>>) -> bool {
+    platforms.as_ref().is_none_or(|platforms| {
+        platforms
+            .iter()
+            .any(|platform| platform.get_ref() == std::env::consts::OS)
+    })
+}
+
+// Move the dependencies that aren't used on the current platform out of deps
+fn split_off_other_platforms<T>(
+    deps: &mut Vec<Spanned<T>>,
+    platforms: impl Fn(&T) -> &Option<Vec<Spanned<String>>>,
+) -> Vec<Spanned<T>> {
+    let (current, other) = std::mem::take(deps)
+        .into_iter()
+        .partition(|dep| on_current_platform(platforms(dep.get_ref())));
+    *deps = current;
+    other
+}
+
+// URL schemes git can fetch from
+const SOURCE_SCHEMES: [&str; 5] = ["https", "http", "ssh", "git", "file"];
+
+// A source is a URL, an scp-like `user@host:path` or a local path. The path of local
+// sources, file:// URLs included, is returned so it can be checked on disk.
+fn parse_source(source: &str) -> Result<Option<&str>, String> {
+    if source.is_empty() {
+        return Err("the source is empty".to_string());
+    }
+    if source.chars().any(char::is_whitespace) {
+        return Err("the source contains whitespace".to_string());
+    }
+    if let Some((scheme, rest)) = source.split_once("://") {
+        if !SOURCE_SCHEMES.contains(&scheme) {
+            return Err(format!(
+                "unsupported URL scheme {}, expected one of {}",
+                scheme,
+                SOURCE_SCHEMES.join(", ")
+            ));
+        }
+        if scheme == "file" {
+            return match rest {
+                "" => Err("the file URL has no path".to_string()),
+                path => Ok(Some(path)),
+            };
+        }
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = authority.rsplit('@').next().unwrap_or_default();
+        if host.split(':').next().unwrap_or_default().is_empty() {
+            return Err("the URL has no host".to_string());
+        }
+        if path.is_empty() {
+            return Err("the URL has no repository path".to_string());
+        }
+        return Ok(None);
+    }
+    // Like git, a colon before any slash means an scp-like address
+    if let Some((host, path)) = source.split_once(':') {
+        if !host.contains('/') {
+            if host.rsplit('@').next().unwrap_or_default().is_empty() || path.is_empty() {
+                return Err("the scp-like address needs a host and a path".to_string());
+            }
+            return Ok(None);
+        }
+    }
+    Ok(Some(source))
+}
+
+// Typos in a source would otherwise only show up as a git failure during refresh
+fn check_source(remote: &RemoteDependency) -> Result<(), Error> {
+    let source = &remote.source;
+    let invalid = |message: String| Error {
+        error_type: ErrorType::InvalidDependencySource,
+        message,
+        span: Some(source.span()),
+        additional_info: None,
+    };
+    let path = match parse_source(source.get_ref()) {
+        Ok(Some(path)) => Path::new(path),
+        Ok(None) => return Ok(()),
+        Err(reason) => {
+            return Err(invalid(format!(
+                "Invalid dependency source {}: {}",
+                source.get_ref(),
+                reason
+            )))
+        }
+    };
+    if !path.exists() {
+        return Err(invalid(format!(
+            "Dependency source {} is neither a URL nor an existing path",
+            source.get_ref()
+        )));
+    }
+    // Remote URLs can't be checked without fetching them
+    if remote.build_method == Some(RemoteBuildMethod::Iceforge) && !path.join(CONFIG_FILE).is_file()
+    {
+        return Err(invalid(format!(
+            "Dependency source {} has no {}, which build_method iceforge needs",
+            source.get_ref(),
+            CONFIG_FILE
+        )));
+    }
+    Ok(())
+}
+
+// A dependency of any kind, borrowed from the config
+#[derive(Clone, Copy)]
+pub enum DependencyRef<'a> {
+    Remote(&'a Spanned<RemoteDependency>),
+    PkgConfig(&'a Spanned<PkgConfigDependency>),
+    Manual(&'a Spanned<ManualDependency>),
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
@@ -50,74 +265,159 @@ pub enum RemoteBuildMethod {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RemoteDependency {
+    #[serde(default = "unnamed")]
     pub name: Spanned<String>,
     pub version: Option<Spanned<String>>,
     pub source: Spanned<String>,
     pub include_name: Option<Spanned<String>>,
-    pub include_dirs: Vec<String>,
+    pub include_dirs: Vec<Spanned<String>>,
     pub build_method: Option<RemoteBuildMethod>,
     pub build_command: Option<Spanned<String>>,
     pub build_output: Option<Spanned<String>>,
     pub imports: Option<Vec<String>>,
+    pub platforms: Option<Vec<Spanned<String>>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PkgConfigDependency {
+    #[serde(default = "unnamed")]
     pub name: Spanned<String>,
     pub pkg_config_query: Spanned<String>,
+    // Link statically, using `pkg-config --static --libs`
+    pub r#static: Option<bool>,
+    pub platforms: Option<Vec<Spanned<String>>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ManualDependency {
+    #[serde(default = "unnamed")]
     pub name: Spanned<String>,
     pub cflags: Option<String>,
     pub ldflags: Option<String>,
+    // Directories added with -I when compiling and -L when linking dependents
+    pub include_dirs: Option<Vec<Spanned<String>>>,
+    pub lib_dirs: Option<Vec<Spanned<String>>>,
+    pub platforms: Option<Vec<Spanned<String>>>,
 }
 
-impl Iterator for Dependencies {
-    type Item = Dependency;
+impl<'a> DependencyRef<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            DependencyRef::Remote(dep) => dep.get_ref().name.get_ref(),
+            DependencyRef::PkgConfig(dep) => dep.get_ref().name.get_ref(),
+            DependencyRef::Manual(dep) => dep.get_ref().name.get_ref(),
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // First check remote dependencies
-        if let Some(remote) = self.remote.pop() {
-            return Some(Dependency::Remote(remote));
+    pub fn name_span(&self) -> Range<usize> {
+        match self {
+            DependencyRef::Remote(dep) => dep.get_ref().name.span(),
+            DependencyRef::PkgConfig(dep) => dep.get_ref().name.span(),
+            DependencyRef::Manual(dep) => dep.get_ref().name.span(),
         }
-        // Then check pkg-config dependencies
-        if let Some(pkg_config) = self.pkg_config.pop() {
-            return Some(Dependency::PkgConfig(pkg_config));
+    }
+
+    pub fn platforms(&self) -> Option<&'a Vec<Spanned<String>>> {
+        match self {
+            DependencyRef::Remote(dep) => dep.get_ref().platforms.as_ref(),
+            DependencyRef::PkgConfig(dep) => dep.get_ref().platforms.as_ref(),
+            DependencyRef::Manual(dep) => dep.get_ref().platforms.as_ref(),
         }
-        // Finally check manual dependencies
-        if let Some(manual) = self.manual.pop() {
-            return Some(Dependency::Manual(manual));
+    }
+
+    // Span of the whole dependency entry in the config source
+    pub fn source_span(&self) -> Range<usize> {
+        match self {
+            DependencyRef::Remote(dep) => dep.span(),
+            DependencyRef::PkgConfig(dep) => dep.span(),
+            DependencyRef::Manual(dep) => dep.span(),
         }
-        None
     }
 }
 
 impl Dependencies {
+    // Every dependency, remote ones first, then pkg-config and manual ones, each kind in
+    // the order it is declared in
+    pub fn iter(&self) -> impl Iterator<Item = DependencyRef<'_>> {
+        let remote = self.remote.iter().map(DependencyRef::Remote);
+        let pkg_config = self.pkg_config.iter().map(DependencyRef::PkgConfig);
+        let manual = self.manual.iter().map(DependencyRef::Manual);
+        remote.chain(pkg_config).chain(manual)
+    }
+
+    pub fn len(&self) -> usize {
+        self.remote.len() + self.pkg_config.len() + self.manual.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Names of all dependencies, for checking many names without a scan each
+    pub fn name_index(&self) -> HashSet<&str> {
+        self.iter().map(|dep| dep.name()).collect()
+    }
+
     pub fn has_dependency(&self, name: &str) -> bool {
-        for dep in self.clone() {
-            match dep {
-                Dependency::Remote(dep) => {
-                    if dep.into_inner().name.into_inner() == name {
-                        return true;
-                    }
-                }
-                Dependency::PkgConfig(dep) => {
-                    if dep.into_inner().name.into_inner() == name {
-                        return true;
-                    }
-                }
-                Dependency::Manual(dep) => {
-                    if dep.into_inner().name.into_inner() == name {
-                        return true;
-                    }
-                }
-            }
+        self.iter().any(|dep| dep.name() == name)
+    }
+
+    // Every platform named in a platforms list has to be known, or a typo would leave the
+    // dependency out everywhere
+    pub fn check_platforms(&self) -> Result<(), Error> {
+        let unknown = self
+            .iter()
+            .flat_map(|dep| dep.platforms().into_iter().flatten())
+            .find(|platform| !PLATFORMS.contains(&platform.get_ref().as_str()));
+        match unknown {
+            Some(platform) => Err(Error {
+                error_type: ErrorType::InvalidPlatform,
+                message: format!(
+                    "Unknown platform {}, expected one of {}",
+                    platform.get_ref(),
+                    PLATFORMS.join(", ")
+                ),
+                span: Some(platform.span()),
+                additional_info: None,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Move the dependencies whose platforms leave out the current one into a set of their
+    // own, so they are neither checked, fetched nor linked
+    pub fn split_off_other_platforms(&mut self) -> Dependencies {
+        Dependencies {
+            remote: split_off_other_platforms(&mut self.remote, |dep| &dep.platforms),
+            pkg_config: split_off_other_platforms(&mut self.pkg_config, |dep| &dep.platforms),
+            manual: split_off_other_platforms(&mut self.manual, |dep| &dep.platforms),
         }
-        false
     }
-    pub fn check_dependencies(&self) -> Result<(), Error> {
+
+    // Dependencies with an empty include_dirs, whose headers can't be found
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.iter()
+            .filter(|dep| match dep {
+                DependencyRef::Remote(remote) => remote.get_ref().include_dirs.is_empty(),
+                DependencyRef::Manual(manual) => manual
+                    .get_ref()
+                    .include_dirs
+                    .as_ref()
+                    .is_some_and(Vec::is_empty),
+                DependencyRef::PkgConfig(_) => false,
+            })
+            .map(|dep| {
+                Warning(Error {
+                    error_type: ErrorType::EmptyIncludeDirs,
+                    message: format!("include_dirs of dependency {} is empty", dep.name()),
+                    span: Some(dep.name_span()),
+                    additional_info: None,
+                })
+            })
+            .collect()
+    }
+
+    pub fn check_dependencies(&self) -> Result<(), Vec<Error>> {
         // NOTE: Dependencies
         // Verify duplicate dependencies are not present
         // Verify no two dependencies share the same name or include_name
@@ -131,131 +431,158 @@ impl Dependencies {
         }
 
         let mut url_set: HashSet<RemoteInfo> = HashSet::new();
-        let mut name_set: HashSet<Spanned<String>> = HashSet::new();
-        let mut include_name_set: HashSet<Spanned<String>> = HashSet::new();
-        for dep in self.clone() {
-            match dep {
-                Dependency::Remote(remote) => {
-                    let remote_info = RemoteInfo {
-                        url: remote.clone().into_inner().source.clone(),
-                        version: remote.clone().into_inner().version,
-                    };
-                    if !url_set.insert(remote_info.clone()) {
-                        return Err(Error {
-                            error_type: ErrorType::DuplicateDependencySource,
-                            message: "Duplicate dependency url with same versions".to_string(),
-                            span: Some(remote.into_inner().source.clone().span()),
-                            additional_info: Some(AdditionalInfo {
-                                message: "Previously defined here".to_string(),
-                                span: url_set.get(&remote_info).unwrap().url.span(),
-                            }),
-                        });
-                    }
-                    if !name_set.insert(remote.clone().into_inner().name.clone()) {
-                        return Err(Error {
-                            error_type: ErrorType::DuplicateDependencyName,
-                            message: "Duplicate dependency name".to_string(),
-                            span: Some(remote.clone().into_inner().name.clone().span()),
-                            additional_info: Some(AdditionalInfo {
-                                message: "Previously defined here".to_string(),
-                                span: name_set.get(&remote.into_inner().name).unwrap().span(),
-                            }),
-                        });
-                    }
-                    if let Some(include_name) = remote.clone().into_inner().include_name {
-                        if !include_name_set.insert(include_name.clone()) {
+        let mut name_spans: HashMap<&str, Range<usize>> = HashMap::new();
+        let mut include_name_set: HashSet<&Spanned<String>> = HashSet::new();
+        // Entries are checked independently so all their errors are reported at once,
+        // an entry stops at its first error
+        let mut errors = Vec::new();
+        for dep in self.iter() {
+            let mut check = || -> Result<(), Error> {
+                if let Some(previous) = name_spans.get(dep.name()) {
+                    return Err(Error {
+                        error_type: ErrorType::DuplicateDependencyName,
+                        message: "Duplicate dependency name".to_string(),
+                        span: Some(dep.name_span()),
+                        additional_info: Some(AdditionalInfo {
+                            message: "Previously defined here".to_string(),
+                            span: previous.clone(),
+                        }),
+                    });
+                }
+                name_spans.insert(dep.name(), dep.name_span());
+
+                match dep {
+                    DependencyRef::Remote(remote) => {
+                        let remote = remote.get_ref();
+                        let remote_info = RemoteInfo {
+                            url: remote.source.clone(),
+                            version: remote.version.clone(),
+                        };
+                        if let Some(previous) = url_set.get(&remote_info) {
                             return Err(Error {
-                                error_type: ErrorType::DuplicateDependencyIncludeName,
-                                message: "Duplicate dependency include name".to_string(),
-                                span: Some(include_name.clone().span()),
+                                error_type: ErrorType::DuplicateDependencySource,
+                                message: "Duplicate dependency url with same versions".to_string(),
+                                span: Some(remote.source.span()),
                                 additional_info: Some(AdditionalInfo {
                                     message: "Previously defined here".to_string(),
-                                    span: include_name_set.get(&include_name).unwrap().span(),
+                                    span: previous.url.span(),
                                 }),
                             });
                         }
-                    }
+                        url_set.insert(remote_info);
+                        check_source(remote)?;
 
-                    if let Some(build_method) = remote.clone().into_inner().build_method {
-                        if build_method == RemoteBuildMethod::Custom {
-                            if remote.clone().into_inner().build_command.is_none() {
+                        // Anything else that isn't a requirement is checked out as a git ref
+                        if let Some(version) = &remote.version {
+                            if is_requirement_like(version.get_ref())
+                                && parse_requirement(version.get_ref()).is_none()
+                            {
                                 return Err(Error {
-                                    error_type: ErrorType::CustomBuildMissing,
-                                    message: "Custom build method missing build_command"
-                                        .to_string(),
-                                    span: Some(remote.span()),
+                                    error_type: ErrorType::InvalidVersionConstraint,
+                                    message: format!(
+                                        "Invalid version constraint {}",
+                                        version.get_ref()
+                                    ),
+                                    span: Some(version.span()),
                                     additional_info: None,
                                 });
                             }
-                        } else {
-                            if let Some(build_output) = remote.clone().into_inner().build_output {
+                        }
+
+                        if let Some(include_name) = &remote.include_name {
+                            if let Some(previous) = include_name_set.get(include_name) {
                                 return Err(Error {
-                                    error_type: ErrorType::ExtraFieldNonCustomBuild,
-                                    message: "Non-Custom build method has build_output".to_string(),
-                                    span: Some(build_output.span()),
-                                    additional_info: None,
+                                    error_type: ErrorType::DuplicateDependencyIncludeName,
+                                    message: "Duplicate dependency include name".to_string(),
+                                    span: Some(include_name.span()),
+                                    additional_info: Some(AdditionalInfo {
+                                        message: "Previously defined here".to_string(),
+                                        span: previous.span(),
+                                    }),
                                 });
                             }
-                            if let Some(build_command) = remote.clone().into_inner().build_command {
-                                return Err(Error {
-                                    error_type: ErrorType::ExtraFieldNonCustomBuild,
-                                    message: "non-Custom build method has build_command"
-                                        .to_string(),
-                                    span: Some(build_command.span()),
-                                    additional_info: None,
-                                });
+                            include_name_set.insert(include_name);
+                        }
+
+                        if let Some(build_method) = &remote.build_method {
+                            if *build_method == RemoteBuildMethod::Custom {
+                                if remote.build_command.is_none() {
+                                    return Err(Error {
+                                        error_type: ErrorType::CustomBuildMissing,
+                                        message: "Custom build method missing build_command"
+                                            .to_string(),
+                                        span: Some(dep.source_span()),
+                                        additional_info: None,
+                                    });
+                                }
+                            } else {
+                                if let Some(build_output) = &remote.build_output {
+                                    return Err(Error {
+                                        error_type: ErrorType::ExtraFieldNonCustomBuild,
+                                        message: "Non-Custom build method has build_output"
+                                            .to_string(),
+                                        span: Some(build_output.span()),
+                                        additional_info: None,
+                                    });
+                                }
+                                if let Some(build_command) = &remote.build_command {
+                                    return Err(Error {
+                                        error_type: ErrorType::ExtraFieldNonCustomBuild,
+                                        message: "non-Custom build method has build_command"
+                                            .to_string(),
+                                        span: Some(build_command.span()),
+                                        additional_info: None,
+                                    });
+                                }
                             }
                         }
                     }
-                }
-                Dependency::PkgConfig(pkg_config) => {
-                    if !name_set.insert(pkg_config.clone().into_inner().name.clone()) {
-                        return Err(Error {
-                            error_type: ErrorType::DuplicateDependencyName,
-                            message: "Duplicate dependency name".to_string(),
-                            span: Some(pkg_config.clone().into_inner().name.clone().span()),
-                            additional_info: Some(AdditionalInfo {
-                                message: "Previously defined here".to_string(),
-                                span: name_set.get(&pkg_config.into_inner().name).unwrap().span(),
-                            }),
-                        });
-                    }
-
-                    // Check if pkg-config dependency exists
-                    let status = Command::new("pkg-config")
-                        .arg("--exists")
-                        .arg(
-                            pkg_config
-                                .clone()
-                                .into_inner()
-                                .pkg_config_query
-                                .into_inner(),
-                        )
-                        .status();
-                    if status.is_err() || status.unwrap().code() != Some(0) {
-                        return Err(Error {
-                            error_type: ErrorType::InvalidPkgConfigQuery,
-                            message: "Pkg-config dependency not found".to_string(),
-                            span: Some(pkg_config.into_inner().pkg_config_query.clone().span()),
-                            additional_info: None,
-                        });
+                    DependencyRef::PkgConfig(pkg_config) => {
+                        // Check if pkg-config dependency exists
+                        let query = &pkg_config.get_ref().pkg_config_query;
+                        let status = Command::new("pkg-config")
+                            .arg("--exists")
+                            .arg(query.get_ref())
+                            .status();
+                        if status.is_err() || status.unwrap().code() != Some(0) {
+                            return Err(Error {
+                                error_type: ErrorType::InvalidPkgConfigQuery,
+                                message: "Pkg-config dependency not found".to_string(),
+                                span: Some(query.span()),
+                                additional_info: None,
+                            });
+                        }
                     }
-                }
-                Dependency::Manual(manual) => {
-                    if !name_set.insert(manual.clone().into_inner().name.clone()) {
-                        return Err(Error {
-                            error_type: ErrorType::DuplicateDependencyName,
-                            message: "Duplicate dependency name".to_string(),
-                            span: Some(manual.clone().into_inner().name.span()),
-                            additional_info: Some(AdditionalInfo {
-                                message: "Previously defined here".to_string(),
-                                span: name_set.get(&manual.into_inner().name).unwrap().span(),
-                            }),
-                        });
+                    DependencyRef::Manual(manual) => {
+                        let manual = manual.get_ref();
+                        let dirs = manual.include_dirs.iter().chain(&manual.lib_dirs);
+                        if let Some(dir) = dirs
+                            .flatten()
+                            .find(|dir| !Path::new(dir.get_ref()).is_dir())
+                        {
+                            return Err(Error {
+                                error_type: ErrorType::MissingManualPath,
+                                message: format!(
+                                    "Directory {} of manual dependency {} does not exist",
+                                    dir.get_ref(),
+                                    manual.name.get_ref()
+                                ),
+                                span: Some(dir.span()),
+                                additional_info: None,
+                            });
+                        }
                     }
                 }
+                Ok(())
+            };
+            if let Err(e) = check() {
+                errors.push(e);
             }
         }
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 }
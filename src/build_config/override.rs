@@ -20,30 +20,151 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use toml::Spanned;
 
-use super::subproject::SubProject;
-use crate::error::{AdditionalInfo, Error, ErrorType};
+use super::{
+    build_settings::{
+        check_parallel_jobs, check_partial_compiler, parallel_jobs_warning, BuildSettings,
+        MAX_PARALLEL_JOBS,
+    },
+    subproject::{check_define, SubProject},
+};
+use crate::error::{AdditionalInfo, Error, ErrorType, Warning};
 // Overrides
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Override {
     pub name: Spanned<String>,
-    pub c_standard: Option<String>,
-    pub compiler: Option<String>,
+    pub c_standard: Option<Spanned<String>>,
+    pub compiler: Option<Spanned<String>>,
+    pub cflags: Option<String>,
+    pub debug_flags: Option<String>,
+    pub release_flags: Option<String>,
+    pub parallel_jobs: Option<Spanned<u32>>,
+    // Added to the subproject's defines, winning over entries with the same name
+    pub defines: Option<Vec<Spanned<String>>>,
+}
+
+// Effective build settings of a subproject once its override is applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedSettings {
+    pub c_standard: String,
+    pub compiler: String,
+    pub cpp_standard: Option<String>,
+    pub cxx_compiler: String,
     pub cflags: Option<String>,
     pub debug_flags: Option<String>,
     pub release_flags: Option<String>,
     pub parallel_jobs: Option<u32>,
+    pub defines: Vec<String>,
+    // From the selected presets, passed after the mode flags and the subproject's ldflags
+    pub preset_cflags: Option<String>,
+    pub preset_ldflags: Option<String>,
 }
 
 impl Override {
-    pub fn verify_overrides(selfs: &[Self], sub_projects: &[SubProject]) -> Result<(), Error> {
+    pub fn resolve_for(
+        selfs: &[Self],
+        settings: &BuildSettings,
+        subproject_name: &str,
+    ) -> ResolvedSettings {
+        let mut resolved = ResolvedSettings {
+            c_standard: settings.c_standard.get_ref().clone(),
+            compiler: settings.compiler.get_ref().clone(),
+            cpp_standard: settings
+                .cpp_standard
+                .as_ref()
+                .map(|standard| standard.get_ref().clone()),
+            cxx_compiler: settings.cxx_compiler(),
+            cflags: settings
+                .global_cflags
+                .as_ref()
+                .map(|flags| flags.get_ref().clone()),
+            debug_flags: settings
+                .debug_flags
+                .as_ref()
+                .map(|flags| flags.get_ref().clone()),
+            release_flags: settings
+                .release_flags
+                .as_ref()
+                .map(|flags| flags.get_ref().clone()),
+            parallel_jobs: settings.parallel_jobs.as_ref().map(|jobs| *jobs.get_ref()),
+            defines: Vec::new(),
+            preset_cflags: None,
+            preset_ldflags: None,
+        };
+        // Override values win over the global ones, cflags replace the global cflags entirely
+        if let Some(over) = selfs
+            .iter()
+            .find(|over| over.name.get_ref() == subproject_name)
+        {
+            if let Some(c_standard) = &over.c_standard {
+                resolved.c_standard = c_standard.get_ref().clone();
+            }
+            if let Some(compiler) = &over.compiler {
+                resolved.compiler = compiler.get_ref().clone();
+            }
+            if over.cflags.is_some() {
+                resolved.cflags = over.cflags.clone();
+            }
+            if over.debug_flags.is_some() {
+                resolved.debug_flags = over.debug_flags.clone();
+            }
+            if over.release_flags.is_some() {
+                resolved.release_flags = over.release_flags.clone();
+            }
+            if let Some(jobs) = &over.parallel_jobs {
+                resolved.parallel_jobs = Some(*jobs.get_ref());
+            }
+            for define in over.defines.iter().flatten() {
+                resolved.defines.push(define.get_ref().clone());
+            }
+        }
+        resolved.parallel_jobs = resolved
+            .parallel_jobs
+            .map(|jobs| jobs.min(MAX_PARALLEL_JOBS));
+        resolved
+    }
+
+    // Overrides that set nothing besides their name, and job counts that get clamped
+    pub fn warnings(selfs: &[Self]) -> Vec<Warning> {
+        let jobs = selfs
+            .iter()
+            .filter_map(|over| over.parallel_jobs.as_ref().and_then(parallel_jobs_warning));
+        selfs
+            .iter()
+            .filter(|over| {
+                over.c_standard.is_none()
+                    && over.compiler.is_none()
+                    && over.cflags.is_none()
+                    && over.debug_flags.is_none()
+                    && over.release_flags.is_none()
+                    && over.parallel_jobs.is_none()
+                    && over.defines.as_ref().is_none_or(Vec::is_empty)
+            })
+            .map(|over| {
+                Warning(Error {
+                    error_type: ErrorType::UnusedOverride,
+                    message: format!("Override {} changes nothing", over.name.get_ref()),
+                    span: Some(over.name.span()),
+                    additional_info: None,
+                })
+            })
+            .chain(jobs)
+            .collect()
+    }
+
+    pub fn verify_overrides(
+        selfs: &[Self],
+        sub_projects: &[SubProject],
+        settings: &BuildSettings,
+    ) -> Result<(), Vec<Error>> {
         // NOTE: Overrrides
-        // Verify duplicate override names are not present
-        // TODO: Verify that override names match subproject names
+        // Verify duplicate override names are not present and that every override names a
+        // subproject
         let mut name_set = HashSet::new();
+        let mut errors = Vec::new();
 
         for over in selfs {
             if !name_set.insert(over.name.clone()) {
-                return Err(Error {
+                errors.push(Error {
                     error_type: ErrorType::OverrideNameConflict,
                     message: format!(
                         "Override name {} is already in use",
@@ -58,12 +179,36 @@ impl Override {
             }
         }
 
-        for name in name_set {
+        for define in selfs.iter().flat_map(|over| over.defines.iter().flatten()) {
+            if let Err(e) = check_define(define) {
+                errors.push(e);
+            }
+        }
+
+        for over in selfs {
+            let checked = check_partial_compiler(
+                over.compiler.as_ref(),
+                over.c_standard.as_ref(),
+                settings.compiler.get_ref(),
+                settings.c_standard.get_ref(),
+            );
+            if let Err(e) = checked {
+                errors.push(e);
+            }
+            if let Some(Err(e)) = over.parallel_jobs.as_ref().map(check_parallel_jobs) {
+                errors.push(e);
+            }
+        }
+
+        // In the order they were written, for a stable report
+        let mut names: Vec<_> = name_set.into_iter().collect();
+        names.sort_by_key(|name| name.span().start);
+        for name in names {
             if !sub_projects
                 .iter()
                 .any(|sub| sub.name.clone().into_inner() == name.clone().into_inner())
             {
-                return Err(Error {
+                errors.push(Error {
                     error_type: ErrorType::OverrideNameConflict,
                     message: format!(
                         "Override name {} is not defined in any subproject",
@@ -74,6 +219,60 @@ impl Override {
                 });
             }
         }
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_config::BuildConfig;
+
+    fn resolve(overrides: &str, name: &str) -> ResolvedSettings {
+        let source = format!(
+            "subprojects = []\n[build]\nversion = \"0.1.0\"\nc_standard = \"c11\"\ncompiler = \"gcc\"\nglobal_cflags = \"-Wall\"\nparallel_jobs = 4\n{}",
+            overrides
+        );
+        let config = BuildConfig::from_source(&source).unwrap();
+        Override::resolve_for(
+            config.overrides.as_deref().unwrap_or_default(),
+            &config.build,
+            name,
+        )
+    }
+
+    #[test]
+    fn settings_without_override_are_the_global_ones() {
+        let resolved = resolve("", "app");
+        assert_eq!(resolved.c_standard, "c11");
+        assert_eq!(resolved.compiler, "gcc");
+        assert_eq!(resolved.cflags.as_deref(), Some("-Wall"));
+        assert_eq!(resolved.parallel_jobs, Some(4));
+        assert!(resolved.defines.is_empty());
+    }
+
+    #[test]
+    fn override_values_win_over_the_global_ones() {
+        let overrides = r#"
+[[overrides]]
+name = "app"
+c_standard = "c99"
+cflags = "-O0"
+parallel_jobs = 2
+defines = ["FAST=1"]
+"#;
+        let resolved = resolve(overrides, "app");
+        assert_eq!(resolved.c_standard, "c99");
+        assert_eq!(resolved.compiler, "gcc");
+        assert_eq!(resolved.cflags.as_deref(), Some("-O0"));
+        assert_eq!(resolved.parallel_jobs, Some(2));
+        assert_eq!(resolved.defines, ["FAST=1"]);
+
+        // Other subprojects keep the global settings
+        assert_eq!(resolve(overrides, "lib"), resolve("", "lib"));
     }
 }
@@ -0,0 +1,119 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Range};
+use toml::Spanned;
+
+use super::{
+    r#override::ResolvedSettings,
+    subproject::{check_define, SubProject},
+};
+use crate::error::{Error, ErrorType};
+
+// Named flags under [presets.<name>], selected with --preset or by a subproject and
+// added on top of the global settings
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Preset {
+    pub cflags: Option<String>,
+    pub ldflags: Option<String>,
+    pub defines: Option<Vec<Spanned<String>>>,
+}
+
+impl Preset {
+    // Names are listed in order, the map is sorted
+    pub fn unknown(
+        presets: Option<&BTreeMap<String, Preset>>,
+        name: &str,
+        span: Option<Range<usize>>,
+    ) -> Error {
+        let available: Vec<&str> = presets
+            .into_iter()
+            .flat_map(BTreeMap::keys)
+            .map(String::as_str)
+            .collect();
+        let message = if available.is_empty() {
+            format!("No preset named {}, no presets are defined", name)
+        } else {
+            format!(
+                "No preset named {}, available presets: {}",
+                name,
+                available.join(", ")
+            )
+        };
+        Error {
+            error_type: ErrorType::UnknownPreset,
+            message,
+            span,
+            additional_info: None,
+        }
+    }
+
+    // A subproject may name a preset when there are no presets at all
+    pub fn verify_presets(
+        presets: Option<&BTreeMap<String, Preset>>,
+        sub_projects: &[SubProject],
+    ) -> Vec<Error> {
+        let mut errors: Vec<Error> = presets
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .flat_map(|preset| preset.defines.iter().flatten())
+            .filter_map(|define| check_define(define).err())
+            .collect();
+        for name in sub_projects
+            .iter()
+            .filter_map(|subproject| subproject.preset.as_ref())
+        {
+            if !presets.is_some_and(|presets| presets.contains_key(name.get_ref())) {
+                errors.push(Self::unknown(presets, name.get_ref(), Some(name.span())));
+            }
+        }
+        errors
+    }
+
+    // Merged into the resolved settings after everything else, so the preset wins
+    pub fn apply(&self, resolved: &mut ResolvedSettings) {
+        let append = |flags: &mut Option<String>, extra: &Option<String>| {
+            if let Some(extra) = extra {
+                *flags = Some(match flags.take() {
+                    Some(flags) => format!("{} {}", flags, extra),
+                    None => extra.clone(),
+                });
+            }
+        };
+        append(&mut resolved.preset_cflags, &self.cflags);
+        append(&mut resolved.preset_ldflags, &self.ldflags);
+        // A define of the preset replaces an earlier one of the same macro
+        let define_name = |define: &str| define.split('=').next().unwrap_or_default().to_string();
+        let replaced: Vec<String> = self
+            .defines
+            .iter()
+            .flatten()
+            .map(|define| define_name(define.get_ref()))
+            .collect();
+        resolved
+            .defines
+            .retain(|define| !replaced.contains(&define_name(define)));
+        resolved.defines.extend(
+            self.defines
+                .iter()
+                .flatten()
+                .map(|define| define.get_ref().clone()),
+        );
+    }
+}
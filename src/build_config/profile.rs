@@ -0,0 +1,136 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Range};
+use toml::Spanned;
+
+use crate::error::{Error, ErrorType};
+
+// Profiles every project has, a [profiles.debug] or [profiles.release] table adds to them
+pub const BUILT_IN_PROFILES: [&str; 2] = ["debug", "release"];
+
+// Optimization levels gcc and clang both take as -O<level>
+const OPT_LEVELS: [&str; 8] = ["0", "1", "2", "3", "s", "z", "g", "fast"];
+
+// Named build profile under [profiles.<name>], selected with --profile. It starts from
+// the debug or release flags and builds to build/<name>.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Profile {
+    // debug or release, debug when not given
+    pub inherits: Option<Spanned<String>>,
+    // Passed as -O<level> after the flags of the inherited mode
+    pub opt_level: Option<Spanned<String>>,
+    pub cflags: Option<String>,
+    pub ldflags: Option<String>,
+}
+
+impl Profile {
+    // Built-in profiles are listed first, the others in order as the map is sorted
+    pub fn unknown(
+        profiles: Option<&BTreeMap<Spanned<String>, Profile>>,
+        name: &str,
+        span: Option<Range<usize>>,
+    ) -> Error {
+        let custom = profiles
+            .into_iter()
+            .flat_map(BTreeMap::keys)
+            .map(|name| name.get_ref().as_str())
+            .filter(|name| !BUILT_IN_PROFILES.contains(name));
+        let available: Vec<&str> = BUILT_IN_PROFILES.into_iter().chain(custom).collect();
+        Error {
+            error_type: ErrorType::UnknownProfile,
+            message: format!(
+                "No profile named {}, available profiles: {}",
+                name,
+                available.join(", ")
+            ),
+            span,
+            additional_info: None,
+        }
+    }
+
+    // Whether the profile starts from the release flags
+    pub fn is_release(&self) -> bool {
+        self.inherits
+            .as_ref()
+            .is_some_and(|inherits| inherits.get_ref() == "release")
+    }
+
+    pub fn verify_profiles(profiles: Option<&BTreeMap<Spanned<String>, Profile>>) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for (name, profile) in profiles.into_iter().flatten() {
+            // The name is a directory under build/, next to those of cross targets
+            let valid_name = !name.get_ref().is_empty()
+                && name
+                    .get_ref()
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                errors.push(Error {
+                    error_type: ErrorType::UnknownProfile,
+                    message: format!(
+                        "Invalid profile name \"{}\", expected letters, digits, - and _",
+                        name.get_ref()
+                    ),
+                    span: Some(name.span()),
+                    additional_info: None,
+                });
+            }
+            if let Some(inherits) = &profile.inherits {
+                if BUILT_IN_PROFILES.contains(&name.get_ref().as_str()) {
+                    errors.push(Error {
+                        error_type: ErrorType::UnknownProfile,
+                        message: format!(
+                            "The built-in profile {} can't inherit from another profile",
+                            name.get_ref()
+                        ),
+                        span: Some(inherits.span()),
+                        additional_info: None,
+                    });
+                } else if !BUILT_IN_PROFILES.contains(&inherits.get_ref().as_str()) {
+                    errors.push(Error {
+                        error_type: ErrorType::UnknownProfile,
+                        message: format!(
+                            "Profile {} inherits from {}, expected debug or release",
+                            name.get_ref(),
+                            inherits.get_ref()
+                        ),
+                        span: Some(inherits.span()),
+                        additional_info: None,
+                    });
+                }
+            }
+            if let Some(level) = &profile.opt_level {
+                if !OPT_LEVELS.contains(&level.get_ref().as_str()) {
+                    errors.push(Error {
+                        error_type: ErrorType::InvalidCompilerFlag,
+                        message: format!(
+                            "Invalid opt_level \"{}\", expected one of {}",
+                            level.get_ref(),
+                            OPT_LEVELS.join(", ")
+                        ),
+                        span: Some(level.span()),
+                        additional_info: None,
+                    });
+                }
+            }
+        }
+        errors
+    }
+}
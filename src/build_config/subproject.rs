@@ -18,18 +18,38 @@
 */
 use super::{dependencies::Dependencies, Error};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
 use toml::Spanned;
 
-use crate::error::{AdditionalInfo, ErrorType};
+use crate::{
+    error::{AdditionalInfo, ErrorType, Warning},
+    glob,
+};
 
 // Enum for subproject type
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
-#[serde(rename_all = "kebab-case")] // Matches the TOML string "binary", "library", "header-only"
+#[serde(rename_all = "kebab-case")] // Matches the TOML string "binary", "library", "header-only", "test"
 pub enum SubProjectType {
     Binary,
     Library,
     HeaderOnly,
+    // A binary run by `iceforge test` and never installed
+    Test,
+}
+
+// Kind of artifact a library subproject produces
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LibraryKind {
+    #[default]
+    Static,
+    Shared,
+    // A static archive and a shared library from the same objects
+    Both,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -47,18 +67,99 @@ pub enum SubProjectDependency {
 pub struct SubProject {
     pub name: Spanned<String>,
     pub r#type: SubProjectType,
-    pub src_dir: Option<String>,
-    pub include_dirs: Option<Vec<String>>,
+    // Only meaningful for libraries, which are static unless stated otherwise
+    #[serde(alias = "library_kind")]
+    pub library_type: Option<Spanned<LibraryKind>>,
+    pub src_dir: Option<Spanned<String>>,
+    // More source directories, walked after src_dir
+    pub src_dirs: Option<Vec<Spanned<String>>>,
+    // Glob patterns selecting source files, used instead of walking src_dir
+    pub sources: Option<Spanned<Vec<String>>>,
+    // Glob patterns relative to src_dir dropping files from the sources
+    pub exclude: Option<Spanned<Vec<String>>>,
+    pub include_dirs: Option<Vec<Spanned<String>>>,
     pub dependencies: Option<Vec<Spanned<SubProjectDependency>>>,
+    // Preprocessor definitions like `FOO` or `FOO=1`, passed as -D flags
+    pub defines: Option<Vec<Spanned<String>>>,
+    // Flags added to this subproject's link command, before those of its dependencies
+    pub ldflags: Option<String>,
+    // File name of the binary or library, the subproject name unless given
+    pub output_name: Option<Spanned<String>>,
+    // Win over the global settings and the subproject's override
+    pub compiler: Option<Spanned<String>>,
+    pub c_standard: Option<Spanned<String>>,
+    // Name of a preset whose flags this subproject is always built with
+    pub preset: Option<Spanned<String>>,
+    // Header precompiled once and included in every source of its language
+    pub pch: Option<Spanned<String>>,
+    // Compile the C sources as one translation unit, or never, regardless of --unity
+    pub unity: Option<bool>,
+}
+
+// Sources with these extensions are compiled as C++
+const CXX_EXTENSIONS: [&str; 3] = ["cpp", "cc", "cxx"];
+
+pub fn is_cxx_source(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| CXX_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+}
+
+// Headers with these extensions are precompiled as C++
+const CXX_HEADER_EXTENSIONS: [&str; 3] = ["hpp", "hh", "hxx"];
+
+pub fn is_cxx_header(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| CXX_HEADER_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+}
+
+// A define must be a macro name optionally followed by `=value`, without whitespace
+pub(super) fn check_define(define: &Spanned<String>) -> Result<(), Error> {
+    let entry = define.get_ref();
+    let name = entry
+        .split_once('=')
+        .map_or(entry.as_str(), |(name, _)| name);
+    let valid_name = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name || entry.chars().any(char::is_whitespace) {
+        return Err(Error {
+            error_type: ErrorType::InvalidDefine,
+            message: format!(
+                "Invalid define \"{}\", expected NAME or NAME=value without spaces",
+                entry
+            ),
+            span: Some(define.span()),
+            additional_info: None,
+        });
+    }
+    Ok(())
+}
+
+impl SubProjectDependency {
+    pub fn name(&self) -> &str {
+        match self {
+            SubProjectDependency::Named(name) => name,
+            SubProjectDependency::Detailed { name, .. } => name,
+        }
+    }
 }
 
 impl SubProject {
-    fn check_duplicate_names(selfs: Vec<Self>) -> Result<HashSet<String>, Error> {
+    // Name of the produced binary, or of the library without its lib prefix and suffix
+    pub fn output_name(&self) -> &str {
+        self.output_name
+            .as_ref()
+            .map_or(self.name.get_ref(), |name| name.get_ref())
+    }
+
+    fn check_duplicate_names(selfs: &[Self], errors: &mut Vec<Error>) -> HashSet<String> {
         let mut name_set = HashSet::new();
         let mut lib_set = HashSet::new();
-        for subproject in selfs.clone() {
+        for subproject in selfs {
             if !name_set.insert(subproject.name.clone()) {
-                return Err(Error {
+                errors.push(Error {
                     error_type: ErrorType::DuplicateSubprojectName,
                     message: format!(
                         "Duplicate subproject name: {}",
@@ -83,25 +184,42 @@ impl SubProject {
                 lib_set.insert(subproject.name.clone().into_inner());
             }
         }
-        Ok(lib_set)
+        lib_set
     }
 
     fn check_subproject_dependencies(
         selfs: &[Self],
         dependencies: &Dependencies,
+        dependency_names: &HashSet<&str>,
         lib_set: &HashSet<String>,
-    ) -> Result<(), Error> {
+        errors: &mut Vec<Error>,
+    ) {
         for subproject in selfs {
             if let Some(deps) = &subproject.dependencies {
                 for dep in deps {
                     let dep_span = dep.span();
+                    // Caught by the cycle check too, but only with a one-node cycle
+                    if dep.get_ref().name() == subproject.name.get_ref() {
+                        errors.push(Error {
+                            error_type: ErrorType::CircularDependency,
+                            message: format!(
+                                "Subproject {} depends on itself",
+                                subproject.name.get_ref()
+                            ),
+                            span: Some(dep_span),
+                            additional_info: Some(AdditionalInfo {
+                                span: subproject.name.span(),
+                                message: format!("{} defined here", subproject.name.get_ref()),
+                            }),
+                        });
+                        continue;
+                    }
                     let dep = dep.clone().into_inner();
                     match dep {
                         SubProjectDependency::Named(name) => {
-                            if !dependencies.has_dependency(&name.clone())
-                                && !lib_set.contains(&name.clone())
+                            if !dependency_names.contains(name.as_str()) && !lib_set.contains(&name)
                             {
-                                return Err(Error {
+                                errors.push(Error {
                                     error_type: ErrorType::InvalidSubprojectDependency,
                                     message: format!("Invalid dependency: {}", name.clone()),
                                     span: Some(dep_span),
@@ -109,26 +227,62 @@ impl SubProject {
                                 });
                             }
                         }
-                        SubProjectDependency::Detailed { name, .. } => {
-                            if dependencies.has_dependency(&name.clone()) {
-                                // TODO: Grab individual imports from remote dependencies
-                                return Ok(());
-                            } else if !lib_set.contains(&name.clone()) {
-                                return Err(Error {
+                        SubProjectDependency::Detailed { name, imports } => {
+                            if !dependency_names.contains(name.as_str()) && !lib_set.contains(&name)
+                            {
+                                errors.push(Error {
                                     error_type: ErrorType::InvalidSubprojectDependency,
-                                    message: format!("Invalid dependency: {}", name.clone()),
+                                    message: format!("Invalid dependency: {}", name),
                                     span: Some(dep_span),
                                     additional_info: None,
                                 });
-                            } else {
-                                unreachable!("How did we get here?");
+                                continue;
+                            }
+                            let Some(imports) = imports else {
+                                continue;
+                            };
+                            // Requested imports must be exported by the remote dependency,
+                            // other kinds of dependencies export nothing to pick from
+                            let remote = dependencies
+                                .remote
+                                .iter()
+                                .find(|remote| *remote.get_ref().name.get_ref() == name);
+                            let Some(remote) = remote else {
+                                errors.push(Error {
+                                    error_type: ErrorType::InvalidImport,
+                                    message: format!(
+                                        "{} is not a remote dependency and has no imports",
+                                        name
+                                    ),
+                                    span: Some(dep_span),
+                                    additional_info: None,
+                                });
+                                continue;
+                            };
+                            let available = remote.get_ref().imports.clone().unwrap_or_default();
+                            if let Some(import) =
+                                imports.iter().find(|import| !available.contains(import))
+                            {
+                                errors.push(Error {
+                                    error_type: ErrorType::InvalidImport,
+                                    message: format!(
+                                        "{} does not export {}, available imports: [{}]",
+                                        name,
+                                        import,
+                                        available.join(", ")
+                                    ),
+                                    span: Some(dep_span),
+                                    additional_info: Some(AdditionalInfo {
+                                        span: remote.get_ref().name.span(),
+                                        message: format!("{} defined here", name),
+                                    }),
+                                });
                             }
                         }
                     }
                 }
             }
         }
-        Ok(())
     }
 
     fn dfs_cycle_detection(
@@ -137,11 +291,14 @@ impl SubProject {
         visited: &mut HashSet<String>,
         stack: &mut HashSet<String>,
         path: &mut Vec<String>, // Add this to track the path
-    ) -> Result<(), String> {
+    ) -> Result<(), Vec<String>> {
         if stack.contains(project) {
-            // Circular dependency detected
-            path.push(project.clone()); // Push the project to the path
-            return Err(path.join(" -> ")); // Return the circular path as error message
+            // Circular dependency detected, the path may lead into the cycle from a
+            // subproject outside it, so it is trimmed to start at the repeated project
+            let start = path.iter().position(|name| name == project).unwrap_or(0);
+            let mut cycle = path[start..].to_vec();
+            cycle.push(project.clone());
+            return Err(cycle); // Return the circular path as error
         }
 
         if !visited.contains(project) {
@@ -187,12 +344,9 @@ impl SubProject {
         }
     }
 
-    // Function to check for circular dependencies and return a valid build order
-    fn check_circular_dependencies_and_get_build_order(
-        selfs: &[SubProject],
-    ) -> Result<Vec<SubProject>, Error> {
-        // Step 1: Construct the dependency graph
-        let dependency_map: HashMap<String, Vec<String>> = selfs
+    // Names each subproject depends on, subprojects and external dependencies alike
+    pub fn dependency_map(selfs: &[SubProject]) -> HashMap<String, Vec<String>> {
+        selfs
             .iter()
             .map(|subproject| {
                 let deps = if let Some(dep_list) = &subproject.dependencies {
@@ -208,13 +362,15 @@ impl SubProject {
                 };
                 (subproject.name.clone().into_inner(), deps)
             })
-            .collect();
+            .collect()
+    }
 
-        // Step 2: Prepare sets to track visited nodes and the recursion stack
+    // The first dependency cycle found, with the subproject it starts and ends at and the
+    // path of names around it
+    pub fn find_dependency_cycle(selfs: &[SubProject]) -> Option<(&SubProject, Vec<String>)> {
+        let dependency_map = Self::dependency_map(selfs);
         let mut visited = HashSet::new();
         let mut stack = HashSet::new();
-
-        // Step 3: Run DFS for each subproject to detect cycles
         for subproject in selfs {
             let project_name = subproject.name.clone().into_inner();
             let mut path = Vec::new(); // Track the cycle path here
@@ -227,27 +383,57 @@ impl SubProject {
                     &mut stack,
                     &mut path,
                 ) {
-                    return Err(Error {
-                        error_type: ErrorType::CircularDependency,
-                        message: format!(
-                            "Circular dependency detected in subproject: {}",
-                            project_name
-                        ),
-                        span: Some(subproject.name.span()),
-                        additional_info: Some(AdditionalInfo {
-                            span: subproject.name.span(),
-                            message: format!("Dependency cycle: {}", cycle_path), // Add the cycle path here
-                        }),
-                    });
+                    let start = selfs
+                        .iter()
+                        .find(|sub| Some(sub.name.get_ref()) == cycle_path.first())
+                        .unwrap_or(subproject);
+                    return Some((start, cycle_path));
                 }
             }
         }
+        None
+    }
 
-        // Step 4: Now that we know there's no circular dependency, generate the build order
+    // Function to check for circular dependencies and return a valid build order
+    fn check_circular_dependencies_and_get_build_order(
+        selfs: &[SubProject],
+    ) -> Result<Vec<SubProject>, Error> {
+        // Step 1: Construct the dependency graph
+        let dependency_map = Self::dependency_map(selfs);
+
+        // Step 2: Run DFS for each subproject to detect cycles
+        if let Some((subproject, cycle_path)) = Self::find_dependency_cycle(selfs) {
+            // Point at the dependency closing the cycle, in the last subproject of the path
+            let closing = cycle_path
+                .len()
+                .checked_sub(2)
+                .and_then(|index| {
+                    selfs
+                        .iter()
+                        .find(|sub| sub.name.get_ref() == &cycle_path[index])
+                })
+                .and_then(|last| {
+                    last.dependencies
+                        .iter()
+                        .flatten()
+                        .find(|dep| dep.get_ref().name() == subproject.name.get_ref())
+                });
+            return Err(Error {
+                error_type: ErrorType::CircularDependency,
+                message: format!("Dependency cycle: {}", cycle_path.join(" -> ")),
+                span: Some(closing.map_or(subproject.name.span(), |dep| dep.span())),
+                additional_info: Some(AdditionalInfo {
+                    span: subproject.name.span(),
+                    message: format!("{} defined here", subproject.name.get_ref()),
+                }),
+            });
+        }
+
+        // Step 3: Now that we know there's no circular dependency, generate the build order
         let mut topological_order = Vec::new();
         let mut visited = HashSet::new();
 
-        // Run DFS again for topological sorting
+        // Run DFS again for topological sorting, post-order puts dependencies before dependents
         for subproject in selfs {
             let project_name = subproject.name.clone().into_inner();
             if !visited.contains(&project_name) {
@@ -260,10 +446,7 @@ impl SubProject {
             }
         }
 
-        // Step 5: Reverse the topological order (DFS will give us the reverse order)
-        topological_order.reverse();
-
-        // Step 6: Map the topological order back to the corresponding subprojects
+        // Step 4: Map the topological order back to the corresponding subprojects
         let build_order = topological_order
             .into_iter()
             .filter_map(|name| {
@@ -277,18 +460,573 @@ impl SubProject {
         Ok(build_order)
     }
 
+    // Collect every C and C++ source file below a directory
+    fn collect_sources(dir: &Path, sources: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::collect_sources(&path, sources)?;
+            } else if path.extension().is_some_and(|ext| ext == "c") || is_cxx_source(&path) {
+                sources.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    // src_dir followed by src_dirs
+    pub fn source_dirs(&self) -> Vec<&Spanned<String>> {
+        self.src_dir
+            .iter()
+            .chain(self.src_dirs.iter().flatten())
+            .collect()
+    }
+
+    fn missing_source_dirs(&self) -> impl Iterator<Item = &Spanned<String>> {
+        self.source_dirs()
+            .into_iter()
+            .filter(|dir| !Path::new(dir.get_ref()).is_dir())
+    }
+
+    // Directory object files are placed relative to. Only a sole source directory is
+    // left out, files of several could share a path relative to their directories.
+    pub fn object_base(&self) -> &Path {
+        match self.source_dirs()[..] {
+            [dir] => Path::new(dir.get_ref()),
+            _ => Path::new(""),
+        }
+    }
+
+    // Source files to compile, sorted for deterministic builds. The sources patterns
+    // win over the source directories when both are given, without either there are
+    // none. Exclude patterns are matched against paths relative to the source directory
+    // holding the file, or to the project root for files outside of them.
+    pub fn source_files(&self) -> io::Result<Vec<PathBuf>> {
+        let src_dirs = self.source_dirs();
+        let mut sources = match &self.sources {
+            Some(patterns) => glob::expand(patterns.get_ref())?,
+            None => {
+                let mut sources = Vec::new();
+                for dir in &src_dirs {
+                    Self::collect_sources(Path::new(dir.get_ref()), &mut sources)?;
+                }
+                sources.sort();
+                sources.dedup();
+                sources
+            }
+        };
+        if let Some(exclude) = &self.exclude {
+            sources.retain(|source| {
+                let relative = src_dirs
+                    .iter()
+                    .find_map(|dir| source.strip_prefix(dir.get_ref()).ok())
+                    .unwrap_or(source);
+                !exclude
+                    .get_ref()
+                    .iter()
+                    .any(|pattern| glob::matches(pattern, relative))
+            });
+        }
+        Ok(sources)
+    }
+
+    // Output names end up as file names, and two artifacts of the same kind can't share one
+    fn check_output_names(selfs: &[Self], errors: &mut Vec<Error>) {
+        for subproject in selfs {
+            let Some(output_name) = &subproject.output_name else {
+                continue;
+            };
+            let name = output_name.get_ref();
+            let valid = !name.starts_with(['.', '-'])
+                && !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+            if !valid {
+                errors.push(Error {
+                    error_type: ErrorType::InvalidOutputName,
+                    message: format!(
+                        "Invalid output name \"{}\", expected letters, digits, '.', '_' and '-', not starting with '.' or '-'",
+                        name
+                    ),
+                    span: Some(output_name.span()),
+                    additional_info: None,
+                });
+                continue;
+            }
+            let clash = selfs.iter().find(|other| {
+                !std::ptr::eq(*other, subproject)
+                    && other.output_name() == name
+                    && other.r#type == subproject.r#type
+                    && other.r#type != SubProjectType::HeaderOnly
+            });
+            if let Some(other) = clash {
+                errors.push(Error {
+                    error_type: ErrorType::InvalidOutputName,
+                    message: format!(
+                        "Output name {} of {} is also the output name of {}",
+                        name,
+                        subproject.name.get_ref(),
+                        other.name.get_ref()
+                    ),
+                    span: Some(output_name.span()),
+                    additional_info: Some(AdditionalInfo {
+                        span: other
+                            .output_name
+                            .as_ref()
+                            .map_or(other.name.span(), |name| name.span()),
+                        message: format!("{} defined here", other.name.get_ref()),
+                    }),
+                });
+            }
+        }
+    }
+
+    fn check_library_kinds(selfs: &[Self], errors: &mut Vec<Error>) {
+        for subproject in selfs {
+            if let Some(kind) = &subproject.library_type {
+                if subproject.r#type != SubProjectType::Library {
+                    errors.push(Error {
+                        error_type: ErrorType::InvalidLibraryKind,
+                        message: format!(
+                            "Subproject {} sets library_type but is not a library",
+                            subproject.name.get_ref()
+                        ),
+                        span: Some(kind.span()),
+                        additional_info: None,
+                    });
+                }
+            }
+        }
+    }
+
+    fn check_defines(selfs: &[Self], errors: &mut Vec<Error>) {
+        errors.extend(
+            selfs
+                .iter()
+                .flat_map(|subproject| subproject.defines.iter().flatten())
+                .filter_map(|define| check_define(define).err()),
+        );
+    }
+
+    fn check_pch(selfs: &[Self], errors: &mut Vec<Error>) {
+        for pch in selfs
+            .iter()
+            .filter_map(|subproject| subproject.pch.as_ref())
+        {
+            if !Path::new(pch.get_ref()).is_file() {
+                errors.push(Error {
+                    error_type: ErrorType::InvalidPrecompiledHeader,
+                    message: format!("Precompiled header {} does not exist", pch.get_ref()),
+                    span: Some(pch.span()),
+                    additional_info: None,
+                });
+            }
+        }
+    }
+
+    // Header-only subprojects compile nothing, their source directories aren't read
+    fn check_source_dirs(selfs: &[Self], errors: &mut Vec<Error>) {
+        for subproject in selfs {
+            if subproject.r#type == SubProjectType::HeaderOnly {
+                continue;
+            }
+            for dir in subproject.missing_source_dirs() {
+                errors.push(Error {
+                    error_type: ErrorType::NoSourcesMatched,
+                    message: format!(
+                        "Source directory {} of {} does not exist",
+                        dir.get_ref(),
+                        subproject.name.get_ref()
+                    ),
+                    span: Some(dir.span()),
+                    additional_info: None,
+                });
+            }
+        }
+    }
+
+    fn check_sources(selfs: &[Self], errors: &mut Vec<Error>) {
+        for subproject in selfs {
+            // Already reported by check_source_dirs
+            if subproject.missing_source_dirs().next().is_some() {
+                continue;
+            }
+            // Sources from src_dir can only run out through exclude
+            let Some(patterns) = subproject.sources.as_ref().or(subproject.exclude.as_ref()) else {
+                continue;
+            };
+            let message = match subproject.source_files() {
+                Ok(files) if !files.is_empty() => continue,
+                Ok(_) if subproject.sources.is_none() => format!(
+                    "Every source file of {} is excluded by [{}]",
+                    subproject.name.get_ref(),
+                    patterns.get_ref().join(", ")
+                ),
+                Ok(_) => format!(
+                    "No source files of {} match [{}]",
+                    subproject.name.get_ref(),
+                    patterns.get_ref().join(", ")
+                ),
+                Err(e) => format!(
+                    "Failed to read sources of {}: {}",
+                    subproject.name.get_ref(),
+                    e
+                ),
+            };
+            errors.push(Error {
+                error_type: ErrorType::NoSourcesMatched,
+                message,
+                span: Some(patterns.span()),
+                additional_info: Some(AdditionalInfo {
+                    span: subproject.name.span(),
+                    message: format!("{} defined here", subproject.name.get_ref()),
+                }),
+            });
+        }
+    }
+
+    // Whether any of the files looks like it defines main(), unreadable files are skipped
+    fn defines_main(files: &[PathBuf]) -> bool {
+        files.iter().any(|path| {
+            let Ok(content) = fs::read_to_string(path) else {
+                return false;
+            };
+            content.match_indices("main").any(|(index, _)| {
+                let before = content[..index].chars().next_back();
+                let after = content[index + 4..].trim_start().chars().next();
+                !before.is_some_and(|c| c.is_alphanumeric() || c == '_') && after == Some('(')
+            })
+        })
+    }
+
+    // Findings that don't stop the build: binaries without main(), libraries with one and
+    // empty include_dirs
+    pub fn warnings(selfs: &[Self]) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        for subproject in selfs {
+            let name = subproject.name.get_ref();
+            let warning = |error_type, message| {
+                Warning(Error {
+                    error_type,
+                    message,
+                    span: Some(subproject.name.span()),
+                    additional_info: None,
+                })
+            };
+            if subproject
+                .include_dirs
+                .as_ref()
+                .is_some_and(|dirs| dirs.is_empty())
+            {
+                warnings.push(warning(
+                    ErrorType::EmptyIncludeDirs,
+                    format!("include_dirs of {} is empty", name),
+                ));
+            }
+            if subproject.source_dirs().is_empty() && subproject.sources.is_none() {
+                continue;
+            }
+            let files = subproject.source_files().unwrap_or_default();
+            match subproject.r#type {
+                SubProjectType::Binary | SubProjectType::Test if !Self::defines_main(&files) => {
+                    warnings.push(warning(
+                        ErrorType::EntryPointMismatch,
+                        format!(
+                            "{} subproject {} has no main() in its sources, it may fail to link",
+                            if subproject.r#type == SubProjectType::Test {
+                                "Test"
+                            } else {
+                                "Binary"
+                            },
+                            name
+                        ),
+                    ));
+                }
+                SubProjectType::Library if Self::defines_main(&files) => {
+                    warnings.push(warning(
+                        ErrorType::EntryPointMismatch,
+                        format!(
+                            "Library subproject {} defines main() in its sources, which is unexpected for a library",
+                            name
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        warnings
+    }
+
     pub fn verify_subprojects(
         selfs: Vec<Self>,
         dependencies: &Dependencies,
-    ) -> Result<Vec<Self>, Error> {
+    ) -> Result<Vec<Self>, Vec<Error>> {
         // NOTE: Subprojects
         // Verify duplicate subproject names are not present
         // Verify that subproject dependencies exist
         // Verify that there are no circular dependencies
-        let name_set = Self::check_duplicate_names(selfs.clone())?;
-        // TODO: Verify that src_dir and include_dirs exist (except in header_only)
-        // TODO: Grab all remote dependencies as they are needed to verify subproject dependencies
-        Self::check_subproject_dependencies(&selfs, dependencies, &name_set)?;
-        Self::check_circular_dependencies_and_get_build_order(&selfs)
+        let mut errors = Vec::new();
+        let name_set = Self::check_duplicate_names(&selfs, &mut errors);
+        // TODO: Verify that include_dirs exist (except in header_only)
+        Self::check_subproject_dependencies(
+            &selfs,
+            dependencies,
+            &dependencies.name_index(),
+            &name_set,
+            &mut errors,
+        );
+        Self::check_output_names(&selfs, &mut errors);
+        Self::check_library_kinds(&selfs, &mut errors);
+        Self::check_source_dirs(&selfs, &mut errors);
+        Self::check_sources(&selfs, &mut errors);
+        Self::check_pch(&selfs, &mut errors);
+        Self::check_defines(&selfs, &mut errors);
+        // The cycle check needs unique names and dependencies that exist
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Self::check_circular_dependencies_and_get_build_order(&selfs).map_err(|e| vec![e])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_config::BuildConfig;
+
+    fn config(body: &str) -> BuildConfig {
+        let source = format!(
+            "[build]\nversion = \"0.1.0\"\nc_standard = \"c11\"\ncompiler = \"gcc\"\n{}",
+            body
+        );
+        BuildConfig::from_source(&source).unwrap()
+    }
+
+    fn dependency_errors(config: &BuildConfig) -> Vec<String> {
+        let mut errors = Vec::new();
+        let names = SubProject::check_duplicate_names(&config.subprojects, &mut errors);
+        SubProject::check_subproject_dependencies(
+            &config.subprojects,
+            &config.dependencies,
+            &config.dependencies.name_index(),
+            &names,
+            &mut errors,
+        );
+        errors.into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn subproject_without_source_location_has_no_sources() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "dslib"
+type = "library"
+include_dirs = ["src/dslib/include"]
+"#,
+        );
+        assert!(config.subprojects[0].source_files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn later_subproject_with_invalid_dependency_is_reported() {
+        let config = config(
+            r#"
+[dependencies]
+manual = [{ name = "m", ldflags = "-lm" }]
+
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+dependencies = [{ name = "m" }]
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+dependencies = ["core", "missing"]
+"#,
+        );
+        assert_eq!(dependency_errors(&config), ["Invalid dependency: missing"]);
+    }
+
+    #[test]
+    fn invalid_detailed_dependency_does_not_stop_validation() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+dependencies = [{ name = "gone", imports = ["a"] }]
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+dependencies = [{ name = "core" }, { name = "missing" }]
+"#,
+        );
+        assert_eq!(
+            dependency_errors(&config),
+            ["Invalid dependency: gone", "Invalid dependency: missing"]
+        );
+    }
+
+    fn cycle_error(config: &BuildConfig) -> Error {
+        SubProject::check_circular_dependencies_and_get_build_order(&config.subprojects)
+            .unwrap_err()
+    }
+
+    #[test]
+    fn cycle_path_leaves_out_the_subprojects_leading_into_it() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+dependencies = ["net"]
+
+[[subprojects]]
+name = "net"
+type = "library"
+src_dir = "src/net"
+dependencies = ["a"]
+
+[[subprojects]]
+name = "a"
+type = "library"
+src_dir = "src/a"
+dependencies = ["b"]
+
+[[subprojects]]
+name = "b"
+type = "library"
+src_dir = "src/b"
+dependencies = ["a"]
+"#,
+        );
+        assert_eq!(
+            cycle_error(&config).message,
+            "Dependency cycle: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn self_dependency_points_at_the_dependency_entry() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+dependencies = ["core"]
+"#,
+        );
+        let mut errors = Vec::new();
+        let names = SubProject::check_duplicate_names(&config.subprojects, &mut errors);
+        SubProject::check_subproject_dependencies(
+            &config.subprojects,
+            &config.dependencies,
+            &config.dependencies.name_index(),
+            &names,
+            &mut errors,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].error_type,
+            ErrorType::CircularDependency
+        ));
+        assert_eq!(errors[0].message, "Subproject core depends on itself");
+        let dependency = config.subprojects[0].dependencies.as_ref().unwrap()[0].span();
+        assert_eq!(errors[0].span, Some(dependency));
+    }
+
+    #[test]
+    fn two_subproject_cycle_is_reported_in_full() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "a"
+type = "library"
+src_dir = "src/a"
+dependencies = ["b"]
+
+[[subprojects]]
+name = "b"
+type = "library"
+src_dir = "src/b"
+dependencies = [{ name = "a" }]
+"#,
+        );
+        let error = cycle_error(&config);
+        assert_eq!(error.message, "Dependency cycle: a -> b -> a");
+        // The dependency closing the cycle is the one in b
+        let closing = config.subprojects[1].dependencies.as_ref().unwrap()[0].span();
+        assert_eq!(error.span, Some(closing));
+    }
+
+    #[test]
+    fn three_subproject_cycle_is_reported_in_full() {
+        let config = config(
+            r#"
+[[subprojects]]
+name = "a"
+type = "library"
+src_dir = "src/a"
+dependencies = ["b"]
+
+[[subprojects]]
+name = "b"
+type = "library"
+src_dir = "src/b"
+dependencies = ["c"]
+
+[[subprojects]]
+name = "c"
+type = "library"
+src_dir = "src/c"
+dependencies = ["a"]
+"#,
+        );
+        let error = cycle_error(&config);
+        assert_eq!(error.message, "Dependency cycle: a -> b -> c -> a");
+        let closing = config.subprojects[2].dependencies.as_ref().unwrap()[0].span();
+        assert_eq!(error.span, Some(closing));
+    }
+
+    #[test]
+    fn excluded_files_are_dropped_from_the_source_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        for file in [
+            "main.c",
+            "util.c",
+            "util_test.c",
+            "platform/posix/io.c",
+            "platform/win32/io.c",
+            "platform/win32/console/tty.c",
+        ] {
+            let path = src.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let config = config(&format!(
+            r#"
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "{}"
+exclude = ["**/*_test.c", "platform/win32/**/*"]
+"#,
+            src.display()
+        ));
+        let sources = config.subprojects[0].source_files().unwrap();
+        assert_eq!(
+            sources,
+            ["main.c", "platform/posix/io.c", "util.c"].map(|file| src.join(file))
+        );
     }
 }
@@ -0,0 +1,85 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use toml::Spanned;
+
+use super::build_settings::{check_c_compiler, find_in_path, BuildSettings};
+use crate::{
+    build::target_compiler,
+    error::{Error, ErrorType},
+};
+
+// Compilers, archiver and sysroot to cross compile with instead of the host ones
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Toolchain {
+    pub cc: Option<Spanned<String>>,
+    pub cxx: Option<Spanned<String>>,
+    pub ar: Option<Spanned<String>>,
+    // Passed to the compiler and linker with --sysroot
+    pub sysroot: Option<Spanned<String>>,
+    // Target triple built for unless --target is given
+    pub target: Option<Spanned<String>>,
+}
+
+impl Toolchain {
+    // The compilers of the toolchain replace the ones of [build], so they are probed and
+    // used for every subproject whose override doesn't name another
+    pub fn apply(&self, settings: &mut BuildSettings) {
+        if let Some(cc) = &self.cc {
+            settings.compiler = cc.clone();
+        }
+        if let Some(cxx) = &self.cxx {
+            settings.cxx_compiler = Some(cxx.clone());
+        }
+    }
+
+    pub fn verify(&self, settings: &BuildSettings) -> Vec<Error> {
+        let mut errors = Vec::new();
+        // Without cc the compiler of [build] is run prefixed with the target triple
+        if let (None, Some(target)) = (&self.cc, &self.target) {
+            let compiler = target_compiler(settings.compiler.get_ref(), Some(target.get_ref()));
+            let compiler = Spanned::new(target.span(), compiler);
+            if let Err(e) = check_c_compiler(&compiler, &settings.c_standard) {
+                errors.push(e);
+            }
+        }
+        if let Some(ar) = &self.ar {
+            if find_in_path(ar.get_ref()).is_none() {
+                errors.push(Error {
+                    error_type: ErrorType::InvalidToolchain,
+                    message: format!("Archiver {} not in path", ar.get_ref()),
+                    span: Some(ar.span()),
+                    additional_info: None,
+                });
+            }
+        }
+        if let Some(sysroot) = &self.sysroot {
+            if !Path::new(sysroot.get_ref()).is_dir() {
+                errors.push(Error {
+                    error_type: ErrorType::InvalidToolchain,
+                    message: format!("Sysroot {} is not a directory", sysroot.get_ref()),
+                    span: Some(sysroot.span()),
+                    additional_info: None,
+                });
+            }
+        }
+        errors
+    }
+}
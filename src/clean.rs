@@ -0,0 +1,192 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    build::{self, BuildMode, BUILD_DIR},
+    build_config::{BuildConfig, SubProject, BUILT_IN_PROFILES},
+    error::{Error, ErrorType},
+};
+
+// The built-in modes and every profile of the config
+fn modes(config: &BuildConfig) -> Vec<BuildMode> {
+    let profiles = config.profiles.iter().flat_map(|profiles| profiles.keys());
+    let mut modes = vec![BuildMode::Debug, BuildMode::Release];
+    modes.extend(
+        profiles
+            .filter(|name| !BUILT_IN_PROFILES.contains(&name.get_ref().as_str()))
+            .map(|name| BuildMode::Profile(name.get_ref().clone())),
+    );
+    modes
+}
+
+// Targets with outputs in the build directory, None for the host. Cross builds go to
+// build/<triple>/<mode>, next to the host's build/<mode>.
+fn built_targets(modes: &[BuildMode]) -> Vec<Option<String>> {
+    let mut targets = vec![None];
+    let Ok(entries) = fs::read_dir(BUILD_DIR) else {
+        return targets;
+    };
+    let mut triples: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| modes.iter().all(|mode| mode.as_str() != name))
+        .collect();
+    triples.sort();
+    targets.extend(triples.into_iter().map(Some));
+    targets
+}
+
+// Object directory and artifacts of a subproject for every target and mode built
+fn subproject_outputs(config: &BuildConfig, subproject: &SubProject) -> Vec<PathBuf> {
+    let modes = modes(config);
+    let mut paths = Vec::new();
+    for target in built_targets(&modes) {
+        for mode in &modes {
+            let target = target.as_deref();
+            paths.push(
+                build::output_dir(target, mode)
+                    .join("obj")
+                    .join(subproject.name.get_ref()),
+            );
+            paths.extend(build::artifact_path(subproject, target, mode));
+            paths.extend(build::shared_library_path(subproject, target, mode));
+        }
+    }
+    paths
+}
+
+// What clean removes, only paths that exist: the build directory and the outputs of
+// the custom build rules, or the outputs of a single subproject
+pub fn paths(config: &BuildConfig, subproject: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    let paths = match subproject {
+        Some(name) => {
+            let Some(subproject) = config
+                .subprojects
+                .iter()
+                .find(|subproject| subproject.name.get_ref() == name)
+            else {
+                return Err(Error {
+                    error_type: ErrorType::InvalidSubprojectDependency,
+                    message: format!("No subproject named {}", name),
+                    span: None,
+                    additional_info: None,
+                });
+            };
+            subproject_outputs(config, subproject)
+        }
+        None => {
+            let mut paths = vec![PathBuf::from(BUILD_DIR)];
+            for rule in config.custom_build_rules.iter().flatten() {
+                let outputs = build::custom_rule_outputs(rule).map_err(|e| Error {
+                    error_type: ErrorType::CleanFailed,
+                    message: format!(
+                        "Failed to read sources of custom build rule {}: {}",
+                        rule.name.get_ref(),
+                        e
+                    ),
+                    span: None,
+                    additional_info: None,
+                })?;
+                // Outputs in the build directory go with it
+                paths.extend(
+                    outputs
+                        .into_iter()
+                        .map(|(_, output)| output)
+                        .filter(|output| !output.starts_with(BUILD_DIR)),
+                );
+            }
+            paths
+        }
+    };
+    Ok(paths
+        .into_iter()
+        .filter(|path| path.symlink_metadata().is_ok())
+        .collect())
+}
+
+// Every file and directory under path, path included, with the total size of the files.
+// Symlinks are listed but not followed, as removing them leaves their target alone.
+fn walk(path: &Path, entries: &mut Vec<PathBuf>) -> io::Result<u64> {
+    let meta = path.symlink_metadata()?;
+    entries.push(path.to_path_buf());
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut children: Vec<PathBuf> = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    children.sort();
+    let mut size = 0;
+    for child in children {
+        size += walk(&child, entries)?;
+    }
+    Ok(size)
+}
+
+// Everything removing paths would delete, for --dry-run, and the size of it
+pub fn contents(paths: &[PathBuf]) -> Result<(Vec<PathBuf>, u64), Error> {
+    let mut entries = Vec::new();
+    let mut size = 0;
+    for path in paths {
+        size += walk(path, &mut entries).map_err(|e| Error {
+            error_type: ErrorType::CleanFailed,
+            message: format!("Failed to read {}: {}", path.display(), e),
+            span: None,
+            additional_info: None,
+        })?;
+    }
+    Ok((entries, size))
+}
+
+pub fn remove(paths: &[PathBuf]) -> Result<(), Error> {
+    for path in paths {
+        let removed = if path.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        removed.map_err(|e| Error {
+            error_type: ErrorType::CleanFailed,
+            message: format!("Failed to remove {}: {}", path.display(), e),
+            span: None,
+            additional_info: None,
+        })?;
+    }
+    Ok(())
+}
+
+// Sizes in the largest binary unit below them, e.g. 1.5 MiB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -17,7 +17,29 @@
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use clap::{builder::PossibleValuesParser, ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    build::{self, BuildContext, BuildMode, BuildReport},
+    build_config::{self, BuildConfig, SubProject, SubProjectType, CONFIG_FILE},
+    clean,
+    edit::{self, NewDependency},
+    error::{self, Error, ErrorType, MessageFormat, Warning},
+    format,
+    graph::{self, GraphFormat},
+    ide,
+    init::{self, Template},
+    logd, loge,
+    logger::{self, ColorMode, LogLevel},
+    logi, logv, logw, package, util, watch,
+    workspace::{self, Workspace},
+};
 
 /// Iceforge Build Tool
 #[derive(Parser, Debug)]
@@ -40,6 +62,51 @@ struct IceforgeCLI {
     /// Generate .vscode/c_cpp_properties.json for the project
     #[arg(long)]
     gen_vsc: bool,
+
+    /// Show more output, -v for verbose messages and -vv for debug messages
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Don't print warnings
+    #[arg(long, global = true)]
+    no_warnings: bool,
+
+    /// Treat warnings about the config as errors, for CI
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+
+    /// Don't warn about dependencies no subproject depends on
+    #[arg(long, global = true)]
+    no_unused_warnings: bool,
+
+    /// Prefix log lines with an ISO-8601 timestamp
+    #[arg(long, global = true)]
+    timestamps: bool,
+
+    /// Also write all log output to a file
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Never fetch dependencies, only use the checkouts already in deps/
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Probe the compiler again instead of trusting cached results
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Print the commands build and trigger would run, or what clean would remove, without doing it
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// When to color the output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorMode,
+
+    /// Format of reported config errors
+    #[arg(long, global = true, value_enum, default_value_t)]
+    message_format: MessageFormat,
+
     /// Commands
     #[command(subcommand)]
     command: Option<Commands>,
@@ -50,27 +117,60 @@ enum Commands {
     /// Build the project or a subproject
     Build(BuildOptions),
 
+    /// Build, then rebuild whenever a source, header or the config changes
+    Watch(BuildOptions),
+
     /// Run a binary from the project
     Run(RunOptions),
 
+    /// Build and run the test subprojects
+    Test(TestOptions),
+
     /// Clean the build directory
     Clean(CleanOptions),
 
     /// Refresh and update dependencies
     Refresh,
 
+    /// Run a custom build rule on demand, regardless of its rebuild rule
+    Trigger(TriggerOptions),
+
     /// Install the current project or a remote iceforge repo for system-wide availability
-    Install,
+    Install(InstallOptions),
 
     /// Publish the project by tagging the current version in the config
     Publish(PublishOptions),
 
     /// Initialize a new iceforge project
     Init(InitOptions),
+
+    /// List subprojects, dependencies and the build order as iceforge sees them
+    List(ListOptions),
+
+    /// Verify the config and optionally look for problems that aren't errors
+    Check(CheckOptions),
+
+    /// Rewrite the config with its sections in canonical order
+    Fmt(FmtOptions),
+
+    /// Print the dependency graph of the subprojects
+    Graph(GraphOptions),
+
+    /// Add a dependency to the config, keeping its formatting and comments
+    Add(AddOptions),
+
+    /// Explain an error in detail, given its code such as IF0007
+    Explain(ExplainOptions),
+
+    /// Remove a dependency or subproject from the config, keeping its formatting and comments
+    Remove(RemoveOptions),
+
+    /// Print a completion script for a shell
+    Completions(CompletionsOptions),
 }
 
 #[derive(Parser, Debug)]
-#[command(group(ArgGroup::new("build_mode").args(&["release", "debug"])))]
+#[command(group(ArgGroup::new("build_mode").args(&["release", "debug", "profile"])))]
 struct BuildOptions {
     /// Build in release mode
     #[arg(long, group = "build_mode")]
@@ -80,6 +180,10 @@ struct BuildOptions {
     #[arg(long, group = "build_mode")]
     debug: bool,
 
+    /// Build with a profile from [profiles], into build/<profile>
+    #[arg(long, group = "build_mode")]
+    profile: Option<String>,
+
     /// Build only a specific subproject
     #[arg(long)]
     subproject: Option<String>,
@@ -88,6 +192,37 @@ struct BuildOptions {
     #[arg(long)]
     parallel: Option<u32>,
 
+    /// Cross compile for a target triple, e.g. aarch64-linux-gnu
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Rebuild everything instead of only the sources that changed
+    #[arg(short, long)]
+    force: bool,
+
+    /// Keep building the subprojects that don't depend on a failed one, and report
+    /// every failure at the end
+    #[arg(short, long)]
+    keep_going: bool,
+
+    /// Add the flags of a preset from [presets] on top of the global settings
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Compile the C sources of each subproject as a single translation unit
+    ///
+    /// Cold builds get faster as shared headers are only parsed once, but static names
+    /// and macros of one source are visible in the next. A subproject whose unity build
+    /// fails is compiled source by source instead. Unity builds are only used when no
+    /// object of the subproject can be reused, rebuilding the few sources that changed
+    /// is faster otherwise. `unity = false` on a subproject opts it out.
+    #[arg(long)]
+    unity: bool,
+
+    /// Print the per-subproject build times as JSON
+    #[arg(long)]
+    timings: bool,
+
     /// Generate compile_commands.json for the project
     #[arg(long)]
     generate_compile_commands: bool,
@@ -97,13 +232,33 @@ struct BuildOptions {
     generate_vscode_config: bool,
 }
 
+impl BuildOptions {
+    // A custom profile is checked against each config it is built with
+    fn mode(&self) -> BuildMode {
+        match self.profile.as_deref() {
+            Some("debug") => BuildMode::Debug,
+            Some("release") => BuildMode::Release,
+            Some(name) => BuildMode::Profile(name.to_string()),
+            None if self.release => BuildMode::Release,
+            None => BuildMode::Debug,
+        }
+    }
+}
+
 impl Default for BuildOptions {
     fn default() -> Self {
         Self {
             release: false,
             debug: true,
+            profile: None,
             subproject: None,
             parallel: None,
+            target: None,
+            force: false,
+            keep_going: false,
+            preset: None,
+            unity: false,
+            timings: false,
             generate_compile_commands: false,
             generate_vscode_config: false,
         }
@@ -117,6 +272,29 @@ struct RunOptions {
     binary: Option<String>,
 }
 
+#[derive(Parser, Debug, Default)]
+struct TestOptions {
+    /// Only run the tests whose name contains this
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Only run the test subproject with this name
+    #[arg(long, conflicts_with = "filter")]
+    test: Option<String>,
+
+    /// Specify the number of parallel jobs for the build
+    #[arg(long)]
+    parallel: Option<u32>,
+
+    /// Add the flags of a preset from [presets] on top of the global settings
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Arguments passed to every test binary, after --
+    #[arg(last = true)]
+    args: Vec<String>,
+}
+
 #[derive(Parser, Debug, Default)]
 struct CleanOptions {
     /// Clean only a specific subproject
@@ -124,11 +302,32 @@ struct CleanOptions {
     subproject: Option<String>,
 }
 
+#[derive(Parser, Debug)]
+struct TriggerOptions {
+    /// Name of the custom build rule to run
+    rule: String,
+}
+
+#[derive(Parser, Debug)]
+struct InstallOptions {
+    /// Remote iceforge repository to fetch, build and install instead of the current project
+    source: Option<String>,
+
+    /// Root to install bin/, lib/ and include/ below (defaults to /usr/local for root,
+    /// ~/.local otherwise)
+    #[arg(long)]
+    prefix: Option<String>,
+}
+
 #[derive(Parser, Debug)]
 struct PublishOptions {
     /// Add the git tag to the specified remote repository
     #[arg(long)]
     remote: Option<String>,
+
+    /// Move the tag if the version is already tagged
+    #[arg(long)]
+    force: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -140,108 +339,1283 @@ struct InitOptions {
     /// Create a new directory for the project and initialize it there
     #[arg(long)]
     dir: Option<String>,
+
+    /// Overwrite an existing iceforge.toml
+    #[arg(long)]
+    force: bool,
+
+    /// Kind of project to create
+    #[arg(long, value_enum, default_value_t)]
+    template: Template,
+
+    /// Initialize a git repository and commit the generated files
+    #[arg(long)]
+    git: bool,
+
+    /// Also ignore iceforge.lock in the generated .gitignore
+    #[arg(long)]
+    ignore_lockfile: bool,
+}
+
+#[derive(Parser, Debug, Default)]
+struct ListOptions {
+    /// List the names of the subprojects
+    #[arg(long)]
+    subprojects: bool,
+
+    /// List the names of the dependencies
+    #[arg(long, alias = "dependencies")]
+    deps: bool,
+
+    /// List the names of the binaries, which run accepts
+    #[arg(long)]
+    binaries: bool,
+
+    /// List the names of the custom build rules
+    #[arg(long)]
+    custom_rules: bool,
+
+    /// List the order subprojects are built in
+    #[arg(long)]
+    build_order: bool,
+
+    /// Print the selected lists, or all of them, as a JSON object
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Parser, Debug, Default)]
+struct CheckOptions {
+    /// Warn about dependencies no subproject depends on
+    #[arg(long)]
+    unused: bool,
+
+    /// Treat unused dependencies as errors, implies --unused
+    #[arg(long)]
+    deny_unused: bool,
+
+    /// Also check that the sources of remote dependencies can be reached
+    #[arg(long)]
+    strict: bool,
+
+    /// Also check that the compiler accepts global_cflags, debug_flags and release_flags
+    #[arg(long)]
+    flags: bool,
+}
+
+#[derive(Parser, Debug, Default)]
+struct FmtOptions {
+    /// Only check the formatting, failing instead of writing when it would change
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Parser, Debug, Default)]
+struct GraphOptions {
+    /// Print an ASCII tree, Graphviz DOT to pipe into `dot -Tpng`, or a Mermaid flowchart
+    #[arg(long, value_enum, default_value_t)]
+    format: GraphFormat,
+}
+
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("kind").required(true).args(&["remote", "pkg_config", "manual"])))]
+struct AddOptions {
+    /// Name the subprojects refer to the dependency by
+    #[arg(long)]
+    name: String,
+
+    /// Git URL of a remote dependency
+    #[arg(long)]
+    remote: Option<String>,
+
+    /// pkg-config query of a pkg-config dependency
+    #[arg(long)]
+    pkg_config: Option<String>,
+
+    /// Add a manual dependency
+    #[arg(long)]
+    manual: bool,
+
+    /// Tag, version requirement or git ref of a remote dependency
+    #[arg(long, requires = "remote")]
+    version: Option<String>,
+
+    /// Include directory of a remote or manual dependency, can be given multiple times
+    #[arg(long = "include-dir", conflicts_with = "pkg_config")]
+    include_dirs: Vec<String>,
+
+    /// Library directory of a manual dependency, can be given multiple times
+    #[arg(long = "lib-dir", requires = "manual")]
+    lib_dirs: Vec<String>,
+
+    /// Compiler flags of a manual dependency
+    #[arg(long, requires = "manual", allow_hyphen_values = true)]
+    cflags: Option<String>,
+
+    /// Linker flags of a manual dependency
+    #[arg(long, requires = "manual", allow_hyphen_values = true)]
+    ldflags: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct RemoveOptions {
+    /// Name of the dependency or subproject
+    name: String,
+
+    /// Remove it even when subprojects depend on it, dropping it from their dependencies
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ExplainOptions {
+    /// Code of the error, such as IF0007, or its name as in the JSON output
+    #[arg(required_unless_present = "list")]
+    code: Option<String>,
+
+    /// List every error code with its name
+    #[arg(long, conflicts_with = "code")]
+    list: bool,
+}
+
+#[derive(Parser, Debug)]
+struct CompletionsOptions {
+    /// Shell to complete for
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+// Load and verify the project config, reporting any error and exiting on failure.
+// Warnings are left to the caller.
+fn load_unreported_config() -> BuildConfig {
+    read_config().unwrap_or_else(|code| std::process::exit(code))
+}
+
+// The verified config, or the exit code of its errors once they are reported
+fn read_config() -> Result<BuildConfig, i32> {
+    let source = match BuildConfig::read_source(CONFIG_FILE) {
+        Ok(source) => source,
+        Err(e) => return Err(report_config_errors(&[e], CONFIG_FILE, "")),
+    };
+    if workspace::is_workspace(&source) {
+        loge!(
+            "{} is a workspace, only build, clean and check run on it, run other commands in a member",
+            CONFIG_FILE
+        );
+        return Err(1);
+    }
+    let mut config = match BuildConfig::from_source(&source) {
+        Ok(config) => config,
+        Err(e) => return Err(report_config_errors(&[e], CONFIG_FILE, &source)),
+    };
+    if let Err(errors) = config.verify_config() {
+        return Err(report_config_errors(&errors, CONFIG_FILE, &source));
+    }
+    Ok(config)
+}
+
+// Report the warnings about the config, or fail with them when warnings are denied
+fn report_warnings(config: &BuildConfig) {
+    report_warnings_in(CONFIG_FILE, config);
+}
+
+fn report_warnings_in(config_path: &str, config: &BuildConfig) {
+    if config.warnings.is_empty() {
+        return;
+    }
+    if error::deny_warnings() {
+        let errors: Vec<Error> = config
+            .warnings
+            .iter()
+            .map(|warning| warning.0.clone())
+            .collect();
+        exit_with_config_errors(&errors, config_path, &config.source);
+    }
+    report_config_warnings(&config.warnings, config_path, &config.source);
+}
+
+// The workspace when the config is one, with every member loaded and verified. Libraries
+// of other members are linked from their artifacts for target and mode.
+fn load_workspace(target: Option<&str>, mode: &BuildMode) -> Option<Workspace> {
+    let source = BuildConfig::read_source(CONFIG_FILE).ok()?;
+    if !workspace::is_workspace(&source) {
+        return None;
+    }
+    let workspace = match Workspace::load(&source, target, mode) {
+        Ok(workspace) => workspace,
+        Err(files) => {
+            for file in &files {
+                report_config_errors(&file.errors, &file.path, &file.source);
+            }
+            std::process::exit(files[0].errors[0].error_type.exit_code());
+        }
+    };
+    for member in &workspace.members {
+        let path = Path::new(&member.name).join(CONFIG_FILE);
+        report_warnings_in(&path.display().to_string(), &member.config);
+    }
+    Some(workspace)
+}
+
+fn load_config() -> BuildConfig {
+    let config = load_unreported_config();
+    report_warnings(&config);
+    config
+}
+
+fn exit_with_error(e: Error) -> ! {
+    std::process::exit(report_error(e));
+}
+
+// Report an error and give its exit code
+fn report_error(e: Error) -> i32 {
+    loge!("{} ({})", e.message, e.error_type.explain_hint());
+    e.error_type.exit_code()
 }
 
-fn handle_build(opts: BuildOptions) {
+// Report errors in a config and give the exit code of the first, which still tells about
+// them when the report can't be written
+fn report_config_errors(errors: &[Error], config_path: &str, source: &str) -> i32 {
+    if let Err(e) = error::emit_config_errors(errors, config_path, source) {
+        logd!("Failed to report the errors in {}: {}", config_path, e);
+    }
+    errors[0].error_type.exit_code()
+}
+
+fn exit_with_config_errors(errors: &[Error], config_path: &str, source: &str) -> ! {
+    std::process::exit(report_config_errors(errors, config_path, source));
+}
+
+fn exit_with_config_error(e: Error, source: &str) -> ! {
+    exit_with_config_errors(&[e], CONFIG_FILE, source);
+}
+
+// Warnings that can't be written are no reason to stop the command
+fn report_config_warnings(warnings: &[Warning], config_path: &str, source: &str) {
+    for warning in warnings {
+        if let Err(e) = warning.emit_config_warning(config_path, source) {
+            logd!("Failed to report the warnings in {}: {}", config_path, e);
+            return;
+        }
+    }
+}
+
+fn handle_build(opts: BuildOptions, offline: bool, dry_run: bool) {
     // Handle the build process with the options provided
-    println!("Building project...");
+    let mode = opts.mode();
+    if let Some(workspace) = load_workspace(opts.target.as_deref(), &mode) {
+        build_workspace(&workspace, &opts, &mode, offline, dry_run);
+        return;
+    }
+    let config = load_config();
+    build_config(
+        &config,
+        &opts,
+        &mode,
+        opts.subproject.as_deref(),
+        offline,
+        dry_run,
+    );
+}
+
+// Members are built one after the other, each after the members whose libraries it uses.
+// With --subproject only the members before the one defining it are built in full.
+fn build_workspace(
+    workspace: &Workspace,
+    opts: &BuildOptions,
+    mode: &BuildMode,
+    offline: bool,
+    dry_run: bool,
+) {
+    let last = match &opts.subproject {
+        Some(subproject) => workspace.member_of(subproject).unwrap_or_else(|| {
+            exit_with_error(Error {
+                error_type: ErrorType::InvalidSubprojectDependency,
+                message: format!("No subproject named {} in the workspace", subproject),
+                span: None,
+                additional_info: None,
+            })
+        }),
+        None => workspace.members.len().saturating_sub(1),
+    };
+    for (index, member) in workspace.members.iter().enumerate().take(last + 1) {
+        logi!("Building workspace member {}", member.name);
+        if dry_run {
+            println!(
+                "cd {}",
+                build::shell_quote(&member.dir.display().to_string())
+            );
+        }
+        let only = opts.subproject.as_deref().filter(|_| index == last);
+        workspace::in_dir(&member.dir, || {
+            build_config(&member.config, opts, mode, only, offline, dry_run)
+        })
+        .unwrap_or_else(|e| exit_with_error(e));
+    }
+}
+
+// A preset named on the command line has to exist in every config it is applied to
+fn select_preset(config: &BuildConfig, preset: Option<&str>) -> Option<String> {
+    let name = preset?;
+    if let Err(e) = config.preset(name) {
+        exit_with_error(e);
+    }
+    Some(name.to_string())
+}
+
+fn build_config(
+    config: &BuildConfig,
+    opts: &BuildOptions,
+    mode: &BuildMode,
+    only: Option<&str>,
+    offline: bool,
+    dry_run: bool,
+) {
+    let start = Instant::now();
+    let reports = try_build_config(config, opts, mode, only, offline, dry_run)
+        .unwrap_or_else(|e| exit_with_error(e));
+    build::log_summary(&reports, start.elapsed());
+    if opts.timings && !dry_run {
+        println!("{}", build::timings_json(&reports, start.elapsed()));
+    }
+}
+
+fn try_build_config(
+    config: &BuildConfig,
+    opts: &BuildOptions,
+    mode: &BuildMode,
+    only: Option<&str>,
+    offline: bool,
+    dry_run: bool,
+) -> Result<Vec<BuildReport>, Error> {
+    if let Some(preset) = &opts.preset {
+        config.preset(preset)?;
+    }
+    if let BuildMode::Profile(profile) = mode {
+        config.build_mode(profile)?;
+    }
+    package::sync_dependencies(config, offline, dry_run)?;
+    let mut ctx = BuildContext::new(config, mode.clone());
+    // A single job prints the commands in the order they would run
+    ctx.jobs = if dry_run {
+        1
+    } else {
+        build::job_count(config, opts.parallel)
+    };
+    ctx.force = opts.force;
+    ctx.keep_going = opts.keep_going;
+    ctx.preset = opts.preset.clone();
+    ctx.unity = opts.unity;
+    if opts.target.is_some() {
+        ctx.target = opts.target.clone();
+    }
+    ctx.dry_run = dry_run;
+    if !dry_run {
+        write_editor_files(&ctx, opts)?;
+    }
+    build::build_project(&ctx, only)
+}
+
+// compile_commands.json and .vscode/c_cpp_properties.json, when asked for. They describe
+// the build about to run, failing to write them doesn't stop it.
+fn write_editor_files(ctx: &BuildContext, opts: &BuildOptions) -> Result<(), Error> {
+    if !opts.generate_compile_commands && !opts.generate_vscode_config {
+        return Ok(());
+    }
+    let entries = build::compile_entries(ctx)?;
+    let mut files = Vec::new();
     if opts.generate_compile_commands {
-        println!("Generating compile_commands.json");
+        let directory = std::env::current_dir().unwrap_or_default();
+        let content = ide::compile_commands_json(&entries, &directory);
+        files.push((ide::COMPILE_COMMANDS_FILE, content));
     }
     if opts.generate_vscode_config {
-        println!("Generating .vscode/c_cpp_properties.json");
+        let compile_commands =
+            opts.generate_compile_commands || Path::new(ide::COMPILE_COMMANDS_FILE).is_file();
+        let content = ide::vscode_config_json(ctx.config, &entries, compile_commands);
+        files.push((ide::VSCODE_CONFIG_FILE, content));
     }
-    if opts.release {
-        println!("Building in release mode");
-    }
-    if opts.debug {
-        println!("Building in debug mode");
+    for (file, content) in files {
+        let path = Path::new(file);
+        let written = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| util::write_atomic(path, content));
+        match written {
+            Ok(()) => logi!("Wrote {}", file),
+            Err(e) => logw!("Failed to write {}: {}", file, e),
+        }
     }
-    if let Some(subproject) = opts.subproject {
-        println!("Building subproject: {}", subproject);
+    Ok(())
+}
+
+// Build, then build again whenever a watched file changes. Errors are reported and the
+// next change retried instead of ending the watch, a changed config is verified again.
+fn handle_watch(opts: BuildOptions, offline: bool) {
+    let mode = opts.mode();
+    let mut config = read_config().ok();
+    if let Some(config) = &config {
+        report_warnings(config);
     }
-    if let Some(parallel) = opts.parallel {
-        println!("Using {} parallel jobs", parallel);
+    loop {
+        let watcher = watch::Watcher::new(config.as_ref()).unwrap_or_else(|e| {
+            loge!("Failed to watch for changes: {}", e);
+            std::process::exit(1);
+        });
+        if let Some(config) = &config {
+            let start = Instant::now();
+            match try_build_config(
+                config,
+                &opts,
+                &mode,
+                opts.subproject.as_deref(),
+                offline,
+                false,
+            ) {
+                Ok(reports) => {
+                    let compiled: usize = reports.iter().map(|report| report.compiled).sum();
+                    logi!(
+                        "Rebuilt {} files in {:.2}s",
+                        compiled,
+                        start.elapsed().as_secs_f64()
+                    );
+                }
+                Err(e) => loge!("{} ({})", e.message, e.error_type.explain_hint()),
+            }
+        }
+        logi!("Watching for changes, press Ctrl-C to stop");
+        let changed = watcher.wait_for_change();
+        for path in &changed {
+            logv!("Changed: {}", path.display());
+        }
+        if changed.iter().any(|path| path == Path::new(CONFIG_FILE)) {
+            logi!("{} changed, verifying it again", CONFIG_FILE);
+            config = read_config().ok();
+            if let Some(config) = &config {
+                report_warnings(config);
+            }
+        }
     }
 }
 
 fn handle_run(opts: RunOptions) {
     // Handle running the binary
     if let Some(binary) = opts.binary {
-        println!("Running binary: {}", binary);
+        // A binary can be picked by its subproject name or by its output name
+        let config = load_config();
+        let binaries: Vec<&SubProject> = config
+            .subprojects
+            .iter()
+            .filter(|subproject| subproject.r#type == SubProjectType::Binary)
+            .collect();
+        let Some(subproject) = binaries.iter().find(|subproject| {
+            subproject.name.get_ref() == &binary || subproject.output_name() == binary
+        }) else {
+            let available: Vec<&str> = binaries
+                .iter()
+                .map(|subproject| subproject.name.get_ref().as_str())
+                .collect();
+            loge!(
+                "No binary named {}, available binaries: {}",
+                binary,
+                available.join(", ")
+            );
+            std::process::exit(1);
+        };
+        println!("Running binary: {}", subproject.output_name());
     } else {
         println!("Running default binary");
     }
 }
 
-fn handle_clean(opts: CleanOptions) {
-    // Handle the clean operation
-    if let Some(subproject) = opts.subproject {
-        println!("Cleaning subproject: {}", subproject);
+fn handle_test(opts: TestOptions, offline: bool) {
+    // Tests pass when they exit with status 0
+    let config = load_config();
+    let tests: Vec<&SubProject> = config
+        .subprojects
+        .iter()
+        .filter(|subproject| subproject.r#type == SubProjectType::Test)
+        .filter(|subproject| {
+            opts.filter
+                .as_ref()
+                .is_none_or(|filter| subproject.name.get_ref().contains(filter.as_str()))
+        })
+        .filter(|subproject| {
+            opts.test
+                .as_ref()
+                .is_none_or(|test| subproject.name.get_ref() == test)
+        })
+        .collect();
+    if let (Some(test), true) = (&opts.test, tests.is_empty()) {
+        exit_with_error(Error {
+            error_type: ErrorType::InvalidSubprojectDependency,
+            message: format!("No test subproject named {}", test),
+            span: None,
+            additional_info: None,
+        });
+    }
+    if tests.is_empty() {
+        match &opts.filter {
+            Some(filter) => logw!("No test subprojects matching {}", filter),
+            None => logw!("No test subprojects"),
+        }
+        return;
+    }
+    let preset = select_preset(&config, opts.preset.as_deref());
+    if let Err(e) = package::sync_dependencies(&config, offline, false) {
+        exit_with_error(e);
+    }
+    let mut ctx = BuildContext::new(&config, BuildMode::Debug);
+    ctx.jobs = build::job_count(&config, opts.parallel);
+    ctx.preset = preset;
+    let start = Instant::now();
+    let reports = build::build_tests(&ctx, &tests).unwrap_or_else(|e| exit_with_error(e));
+    build::log_summary(&reports, start.elapsed());
+
+    let mut failed = Vec::new();
+    for test in &tests {
+        let name = test.name.get_ref();
+        let Some(path) = build::artifact_path(test, config.default_target(), &BuildMode::Debug)
+        else {
+            continue;
+        };
+        logi!("Running test {}", name);
+        match std::process::Command::new(&path).args(&opts.args).status() {
+            Ok(status) if status.success() => logi!("test {} ... ok", name),
+            Ok(status) => {
+                loge!("test {} ... FAILED ({})", name, status);
+                failed.push(name.as_str());
+            }
+            Err(e) => {
+                loge!("test {} ... FAILED to run {}: {}", name, path.display(), e);
+                failed.push(name.as_str());
+            }
+        }
+    }
+    logi!(
+        "test result: {} passed, {} failed in {:.2}s",
+        tests.len() - failed.len(),
+        failed.len(),
+        start.elapsed().as_secs_f64()
+    );
+    if !failed.is_empty() {
+        loge!("Failed tests: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+}
+
+fn handle_clean(opts: CleanOptions, dry_run: bool) {
+    // Handle the clean operation, with --dry-run only listing what would be removed
+    let subproject = opts.subproject.as_deref();
+    // Paths to remove under each member directory, with the name to show it by
+    let mut groups: Vec<(PathBuf, PathBuf, Vec<PathBuf>)> = Vec::new();
+    if let Some(workspace) = load_workspace(None, &BuildMode::Debug) {
+        let members = match subproject {
+            Some(name) => match workspace.member_of(name) {
+                Some(index) => &workspace.members[index..=index],
+                None => exit_with_error(Error {
+                    error_type: ErrorType::InvalidSubprojectDependency,
+                    message: format!("No subproject named {} in the workspace", name),
+                    span: None,
+                    additional_info: None,
+                }),
+            },
+            None => &workspace.members[..],
+        };
+        for member in members {
+            let paths = workspace::in_dir(&member.dir, || clean::paths(&member.config, subproject))
+                .and_then(|paths| paths)
+                .unwrap_or_else(|e| exit_with_error(e));
+            let paths = paths.iter().map(|path| member.dir.join(path)).collect();
+            groups.push((member.dir.clone(), PathBuf::from(&member.name), paths));
+        }
     } else {
-        println!("Cleaning the entire project");
+        let config = load_config();
+        let paths = clean::paths(&config, subproject).unwrap_or_else(|e| exit_with_error(e));
+        groups.push((PathBuf::new(), PathBuf::new(), paths));
+    }
+
+    if dry_run {
+        let mut count = 0;
+        let mut size = 0;
+        for (dir, name, paths) in &groups {
+            let (entries, bytes) = clean::contents(paths).unwrap_or_else(|e| exit_with_error(e));
+            for entry in &entries {
+                let relative = entry.strip_prefix(dir).unwrap_or(entry);
+                println!("{}", name.join(relative).display());
+            }
+            count += entries.len();
+            size += bytes;
+        }
+        println!(
+            "Would remove {} files and directories, {}",
+            count,
+            clean::format_size(size)
+        );
+        return;
+    }
+    match subproject {
+        Some(subproject) => println!("Cleaning subproject: {}", subproject),
+        None => println!("Cleaning the entire project"),
+    }
+    for (_, _, paths) in &groups {
+        if let Err(e) = clean::remove(paths) {
+            exit_with_error(e);
+        }
     }
 }
 
-fn handle_refresh() {
+fn handle_refresh(offline: bool) {
     // Handle refreshing dependencies
-    println!("Refreshing dependencies...");
+    let config = load_config();
+    if let Err(e) = package::refresh_dependencies(&config, offline) {
+        match e.span {
+            Some(_) => exit_with_config_error(e, &config.source),
+            None => exit_with_error(e),
+        }
+    }
 }
 
-fn handle_install() {
+fn handle_trigger(opts: TriggerOptions, dry_run: bool) {
+    // Handle running a single custom build rule
+    let config = load_config();
+    let rules = config.custom_build_rules.as_deref().unwrap_or_default();
+    let Some(rule) = rules.iter().find(|rule| rule.name.get_ref() == &opts.rule) else {
+        let available: Vec<&str> = rules
+            .iter()
+            .map(|rule| rule.name.get_ref().as_str())
+            .collect();
+        exit_with_error(Error {
+            error_type: ErrorType::UnknownCustomBuildRule,
+            message: format!(
+                "No custom build rule named {}, available rules: {}",
+                opts.rule,
+                available.join(", ")
+            ),
+            span: None,
+            additional_info: None,
+        });
+    };
+    if let Err(e) = build::run_custom_build_rule(&config, rule, true, dry_run) {
+        exit_with_error(e);
+    }
+}
+
+fn handle_install(opts: InstallOptions, offline: bool) {
     // Handle the installation of the project
-    println!("Installing project...");
+    let prefix = match opts.prefix {
+        Some(prefix) => PathBuf::from(prefix),
+        None => package::default_prefix().unwrap_or_else(|e| exit_with_error(e)),
+    };
+    // Artifacts are installed from a path relative to the invocation directory
+    let prefix = std::path::absolute(&prefix).unwrap_or(prefix);
+
+    let Some(source) = opts.source else {
+        if let Err(code) = install(&prefix, offline) {
+            std::process::exit(code);
+        }
+        return;
+    };
+    if offline {
+        loge!(
+            "Cannot install {} with --offline, it would have to be fetched",
+            source
+        );
+        std::process::exit(1);
+    }
+    let name = source
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or("package")
+        .to_string();
+    // The checkout is named after the package, which names the install manifest entry,
+    // inside a directory no other install shares
+    let temp_dir = tempfile::Builder::new()
+        .prefix("iceforge-install-")
+        .tempdir()
+        .unwrap_or_else(|e| {
+            loge!(
+                "Failed to create a directory to fetch {} into: {}",
+                source,
+                e
+            );
+            std::process::exit(1);
+        });
+    let checkout = temp_dir.path().join(&name);
+    logi!("Fetching {}", source);
+    let installed = fetch_into(&source, &checkout).and_then(|()| install(&prefix, offline));
+    // Removed before exiting, which would skip its destructor
+    let path = temp_dir.path().to_path_buf();
+    if let Err(e) = temp_dir.close() {
+        logw!("Failed to remove {}: {}", path.display(), e);
+    }
+    if let Err(code) = installed {
+        std::process::exit(code);
+    }
+}
+
+// Fetch a package to install and enter its checkout, giving the exit code of a failure
+// once it is reported
+fn fetch_into(source: &str, checkout: &Path) -> Result<(), i32> {
+    package::fetch_remote(source, None, checkout).map_err(report_error)?;
+    std::env::set_current_dir(checkout).map_err(|e| {
+        loge!("Failed to enter {}: {}", checkout.display(), e);
+        1
+    })
+}
+
+// Build the project in the current directory in release mode and install it under
+// prefix, giving the exit code of a failure once it is reported
+fn install(prefix: &Path, offline: bool) -> Result<(), i32> {
+    let name = std::env::current_dir()
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_else(|| "package".to_string());
+    let config = read_config()?;
+    report_warnings(&config);
+    package::sync_dependencies(&config, offline, false).map_err(report_error)?;
+    let ctx = BuildContext::new(&config, BuildMode::Release);
+    build::build_project(&ctx, None).map_err(report_error)?;
+    package::install_artifacts(&config, &name, prefix).map_err(report_error)?;
+    Ok(())
 }
 
 fn handle_publish(opts: PublishOptions) {
     // Handle publishing the project by tagging the current version
-    if let Some(remote) = opts.remote {
-        println!("Tagging version and pushing to remote: {}", remote);
-    } else {
-        println!("Tagging version locally");
+    let config = load_config();
+    if let Err(e) = package::publish(&config, opts.remote.as_deref(), opts.force) {
+        loge!(
+            "{} ({})",
+            e.error.message,
+            e.error.error_type.explain_hint()
+        );
+        std::process::exit(e.exit_code);
     }
 }
 
 fn handle_init(opts: InitOptions) {
     // Handle initializing a new project
-    if let Some(name) = opts.name {
-        println!("Initializing project: {}", name);
+    let dir = PathBuf::from(opts.dir.unwrap_or_else(|| ".".to_string()));
+    let name = opts.name.unwrap_or_else(|| {
+        std::path::absolute(&dir)
+            .ok()
+            .and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "app".to_string())
+    });
+    logi!("Initializing project {} in {}", name, dir.display());
+    let files = init::init_project(&dir, &name, opts.template, opts.force, opts.ignore_lockfile)
+        .unwrap_or_else(|e| exit_with_error(e));
+    if opts.git {
+        // The project is there either way, so a failing git doesn't fail the init
+        if let Err(e) = init::init_git(&dir, &files) {
+            logw!("Failed to set up git for the project: {}", e.message);
+        }
+    }
+}
+
+fn handle_list(opts: ListOptions) {
+    // Print how iceforge interprets the config, everything unless filtered. Filtered
+    // lists are names one per line for scripts, sorted except for the build order.
+    let config = load_config();
+    let all =
+        !(opts.subprojects || opts.deps || opts.binaries || opts.custom_rules || opts.build_order);
+
+    fn sorted(mut names: Vec<&str>) -> Vec<&str> {
+        names.sort_unstable();
+        names
+    }
+    let subproject_names = |filter: fn(&SubProject) -> bool| {
+        config
+            .subprojects
+            .iter()
+            .filter(|subproject| filter(subproject))
+            .map(|subproject| subproject.name.get_ref().as_str())
+            .collect::<Vec<_>>()
+    };
+    // Verification leaves the subprojects in build order
+    let lists = [
+        (
+            "subprojects",
+            opts.subprojects,
+            sorted(subproject_names(|_| true)),
+        ),
+        (
+            "dependencies",
+            opts.deps,
+            sorted(config.dependencies.iter().map(|dep| dep.name()).collect()),
+        ),
+        (
+            "binaries",
+            opts.binaries,
+            sorted(subproject_names(|subproject| {
+                subproject.r#type == SubProjectType::Binary
+            })),
+        ),
+        (
+            "custom_rules",
+            opts.custom_rules,
+            sorted(
+                config
+                    .custom_build_rules
+                    .iter()
+                    .flatten()
+                    .map(|rule| rule.name.get_ref().as_str())
+                    .collect(),
+            ),
+        ),
+        ("build_order", opts.build_order, subproject_names(|_| true)),
+    ];
+    let selected = lists.iter().filter(|(_, wanted, _)| all || *wanted);
+    if opts.json {
+        let fields: Vec<String> = selected
+            .map(|(key, _, names)| {
+                let names: Vec<String> =
+                    names.iter().map(|name| error::json_string(name)).collect();
+                format!("\"{}\": [{}]", key, names.join(", "))
+            })
+            .collect();
+        println!("{{{}}}", fields.join(", "));
+        return;
+    }
+    if !all {
+        for name in selected.flat_map(|(_, _, names)| names) {
+            println!("{}", name);
+        }
+        return;
+    }
+
+    logi!("Subprojects:");
+    for subproject in &config.subprojects {
+        let deps: Vec<&str> = subproject
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|dep| dep.get_ref().name())
+            .collect();
+        let kind = match subproject.r#type {
+            SubProjectType::Binary => "binary",
+            SubProjectType::Library => "library",
+            SubProjectType::HeaderOnly => "header-only",
+            SubProjectType::Test => "test",
+        };
+        if deps.is_empty() {
+            logi!("  {} ({})", subproject.name.get_ref(), kind);
+        } else {
+            logi!(
+                "  {} ({}), depends on: {}",
+                subproject.name.get_ref(),
+                kind,
+                deps.join(", ")
+            );
+        }
+    }
+
+    let deps = &config.dependencies;
+    logi!("Remote dependencies:");
+    for remote in &deps.remote {
+        let remote = remote.get_ref();
+        logi!(
+            "  {} from {} ({})",
+            remote.name.get_ref(),
+            remote.source.get_ref(),
+            remote
+                .version
+                .as_ref()
+                .map_or("default branch", |version| version.get_ref())
+        );
+    }
+    logi!("Pkg-config dependencies:");
+    for pkg_config in &deps.pkg_config {
+        let pkg_config = pkg_config.get_ref();
+        logi!(
+            "  {} ({})",
+            pkg_config.name.get_ref(),
+            pkg_config.pkg_config_query.get_ref()
+        );
+    }
+    logi!("Manual dependencies:");
+    for manual in &deps.manual {
+        logi!("  {}", manual.get_ref().name.get_ref());
+    }
+
+    logi!("Custom build rules:");
+    for rule in config.custom_build_rules.iter().flatten() {
+        logi!("  {}", rule.name.get_ref());
+    }
+
+    logi!("Build order:");
+    for (index, subproject) in config.subprojects.iter().enumerate() {
+        logi!("  {}. {}", index + 1, subproject.name.get_ref());
+    }
+}
+
+fn handle_check(opts: CheckOptions, offline: bool) {
+    // Loading the config already runs every verification
+    if let Some(workspace) = load_workspace(None, &BuildMode::Debug) {
+        logi!(
+            "{} is a valid workspace: {} members",
+            CONFIG_FILE,
+            workspace.members.len()
+        );
+        return;
+    }
+    let mut config = load_unreported_config();
+    if opts.strict {
+        // Reported as errors below
+        config
+            .warnings
+            .retain(|warning| !matches!(warning.0.error_type, ErrorType::ConflictingBuildRules));
+    }
+    if opts.unused || opts.deny_unused {
+        // Reported below, even with --no-unused-warnings
+        config
+            .warnings
+            .retain(|warning| !matches!(warning.0.error_type, ErrorType::UnusedDependency));
+    }
+    report_warnings(&config);
+    if opts.flags {
+        if let Err(e) = config.build.check_flags() {
+            exit_with_config_error(e, &config.source);
+        }
+    }
+    if opts.strict {
+        let conflicts = build_config::CustomBuildRule::conflicting_rules(
+            config.custom_build_rules.as_deref().unwrap_or_default(),
+        );
+        if !conflicts.is_empty() {
+            exit_with_config_errors(&conflicts, CONFIG_FILE, &config.source);
+        }
+        if offline {
+            exit_with_error(Error {
+                error_type: ErrorType::FetchFailed,
+                message: "--strict needs network access, which --offline forbids".to_string(),
+                span: None,
+                additional_info: None,
+            });
+        }
+        let unreachable: Vec<Error> = config
+            .dependencies
+            .remote
+            .iter()
+            .filter_map(|remote| package::check_remote_reachable(remote.get_ref()).err())
+            .collect();
+        if !unreachable.is_empty() {
+            exit_with_config_errors(&unreachable, CONFIG_FILE, &config.source);
+        }
+    }
+    if opts.unused || opts.deny_unused {
+        let deny_unused = opts.deny_unused || error::deny_warnings();
+        let unused = config.unused_dependency_errors();
+        if deny_unused && !unused.is_empty() {
+            exit_with_config_errors(&unused, CONFIG_FILE, &config.source);
+        }
+        let warnings: Vec<Warning> = unused.into_iter().map(Warning).collect();
+        report_config_warnings(&warnings, CONFIG_FILE, &config.source);
+    }
+    logi!(
+        "{} is valid: {} subprojects, {} dependencies, {} custom build rules, {} overrides",
+        CONFIG_FILE,
+        config.subprojects.len(),
+        config.dependencies.len(),
+        config.custom_build_rules.as_ref().map_or(0, Vec::len),
+        config.overrides.as_ref().map_or(0, Vec::len)
+    );
+}
+
+fn handle_fmt(opts: FmtOptions) {
+    // Formatting only needs valid TOML, not a valid config
+    let source = match BuildConfig::read_source(CONFIG_FILE) {
+        Ok(source) => source,
+        Err(e) => exit_with_error(e),
+    };
+    let formatted = match format::format_config(&source) {
+        Ok(formatted) => formatted,
+        Err(e) => exit_with_config_error(e, &source),
+    };
+    if formatted == source {
+        logi!("{} is formatted", CONFIG_FILE);
+    } else if opts.check {
+        loge!("{} is not formatted, run `iceforge fmt`", CONFIG_FILE);
+        std::process::exit(1);
+    } else if let Err(e) = util::write_atomic(Path::new(CONFIG_FILE), formatted) {
+        loge!("Failed to write {}: {}", CONFIG_FILE, e);
+        std::process::exit(1);
+    } else {
+        logi!("Formatted {}", CONFIG_FILE);
+    }
+}
+
+fn handle_graph(opts: GraphOptions) {
+    // The config isn't verified, so the graph of a config with a dependency cycle can be
+    // looked at to find it
+    let source =
+        BuildConfig::read_source(CONFIG_FILE).unwrap_or_else(|e| exit_with_config_error(e, ""));
+    let config =
+        BuildConfig::from_source(&source).unwrap_or_else(|e| exit_with_config_error(e, &source));
+    if let Some((_, cycle)) = SubProject::find_dependency_cycle(&config.subprojects) {
+        logw!("Dependency cycle: {}", cycle.join(" -> "));
+    }
+    match opts.format {
+        GraphFormat::Tree => print!("{}", graph::tree(&config)),
+        GraphFormat::Dot => print!("{}", graph::dot(&config)),
+        GraphFormat::Mermaid => print!("{}", graph::mermaid(&config)),
+    }
+}
+
+fn handle_add(opts: AddOptions) {
+    let dep = if let Some(source) = opts.remote {
+        NewDependency::Remote {
+            name: opts.name,
+            source,
+            version: opts.version,
+            include_dirs: opts.include_dirs,
+        }
+    } else if let Some(query) = opts.pkg_config {
+        NewDependency::PkgConfig {
+            name: opts.name,
+            query,
+        }
     } else {
-        println!("Initializing project in the current directory");
+        NewDependency::Manual {
+            name: opts.name,
+            cflags: opts.cflags,
+            ldflags: opts.ldflags,
+            include_dirs: opts.include_dirs,
+            lib_dirs: opts.lib_dirs,
+        }
+    };
+
+    let source = BuildConfig::read_source(CONFIG_FILE).unwrap_or_else(|e| exit_with_error(e));
+    let edited =
+        edit::add_dependency(&source, &dep).unwrap_or_else(|e| exit_with_config_error(e, &source));
+    // Errors are reported against the edited config, so a conflict points at the new
+    // entry and the one it clashes with, and nothing is written
+    let config =
+        BuildConfig::from_source(&edited).unwrap_or_else(|e| exit_with_config_error(e, &edited));
+    if let Err(errors) = config.check_dependencies() {
+        exit_with_config_errors(&errors, CONFIG_FILE, &edited);
     }
+    if let Err(e) = util::write_atomic(Path::new(CONFIG_FILE), edited) {
+        loge!("Failed to write {}: {}", CONFIG_FILE, e);
+        std::process::exit(1);
+    }
+    logi!("Added {} to {}", dep.name(), CONFIG_FILE);
+}
 
-    if let Some(dir) = opts.dir {
-        println!("Creating and initializing project in directory: {}", dir);
+fn handle_remove(opts: RemoveOptions) {
+    let name = opts.name.as_str();
+    let source = BuildConfig::read_source(CONFIG_FILE).unwrap_or_else(|e| exit_with_error(e));
+    let config =
+        BuildConfig::from_source(&source).unwrap_or_else(|e| exit_with_config_error(e, &source));
+    let kind = if config.dependencies.has_dependency(name)
+        || config.other_platform_dependencies.has_dependency(name)
+    {
+        "dependency"
+    } else {
+        "subproject"
+    };
+
+    let dependency_map = SubProject::dependency_map(&config.subprojects);
+    let dependents: Vec<&str> = config
+        .subprojects
+        .iter()
+        .map(|subproject| subproject.name.get_ref().as_str())
+        .filter(|dependent| {
+            *dependent != name
+                && dependency_map
+                    .get(*dependent)
+                    .is_some_and(|deps| deps.iter().any(|dep| dep == name))
+        })
+        .collect();
+    if !dependents.is_empty() && !opts.force {
+        loge!(
+            "{} is a dependency of {}, use --force to remove it from them too",
+            name,
+            dependents.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let edited = match edit::remove_entry(&source, name, opts.force) {
+        Ok(Some(edited)) => edited,
+        Ok(None) => {
+            loge!("No dependency or subproject named {}", name);
+            std::process::exit(1);
+        }
+        Err(e) => exit_with_config_error(e, &source),
+    };
+    // Removing the last subproject, for one, leaves a config that can't be loaded
+    if let Err(e) = BuildConfig::from_source(&edited) {
+        exit_with_config_error(e, &edited);
+    }
+    if let Err(e) = util::write_atomic(Path::new(CONFIG_FILE), edited) {
+        loge!("Failed to write {}: {}", CONFIG_FILE, e);
+        std::process::exit(1);
+    }
+    if !dependents.is_empty() {
+        logw!(
+            "Removed {} from the dependencies of {}",
+            name,
+            dependents.join(", ")
+        );
     }
+    logi!("Removed {} {} from {}", kind, name, CONFIG_FILE);
+}
+
+fn handle_explain(opts: ExplainOptions) {
+    let Some(code) = opts.code else {
+        for error_type in ErrorType::ALL {
+            println!("{}  {}", error_type.id(), error_type.code());
+        }
+        return;
+    };
+    match ErrorType::from_id(&code) {
+        Some(error_type) => println!(
+            "{} ({})\n\n{}",
+            error_type.id(),
+            error_type.code(),
+            error_type.explanation()
+        ),
+        None => {
+            loge!(
+                "Unknown error code {}, `iceforge explain --list` lists them all",
+                code
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// Subprojects of the config in the current directory, unverified and without reporting
+// anything, as completions shouldn't fail outside of a project
+fn unverified_subprojects() -> Vec<SubProject> {
+    BuildConfig::read_source(CONFIG_FILE)
+        .ok()
+        .filter(|source| !workspace::is_workspace(source))
+        .and_then(|source| BuildConfig::from_source(&source).ok())
+        .map_or_else(Vec::new, |config| config.subprojects)
+}
+
+fn handle_completions(opts: CompletionsOptions) {
+    let mut command = IceforgeCLI::command();
+    // Names of the current project are baked into the script, it is regenerated to pick
+    // up new subprojects
+    let subprojects = unverified_subprojects();
+    // Arguments naming a subproject, of a given type or of any
+    let named_args = [
+        ("build", "subproject", None),
+        ("watch", "subproject", None),
+        ("clean", "subproject", None),
+        ("run", "binary", Some(SubProjectType::Binary)),
+        ("test", "test", Some(SubProjectType::Test)),
+    ];
+    // Changed in place, mut_subcommand would move them after the other subcommands
+    for cmd in command.get_subcommands_mut() {
+        let Some((_, arg, r#type)) = named_args
+            .iter()
+            .find(|(subcommand, _, _)| *subcommand == cmd.get_name())
+        else {
+            continue;
+        };
+        let names: Vec<String> = subprojects
+            .iter()
+            .filter(|subproject| r#type.as_ref().is_none_or(|t| subproject.r#type == *t))
+            .map(|subproject| subproject.name.get_ref().clone())
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+        *cmd = std::mem::take(cmd).mut_arg(*arg, |arg| {
+            arg.value_parser(PossibleValuesParser::new(names))
+        });
+    }
+    let name = command.get_name().to_string();
+    clap_complete::generate(opts.shell, &mut command, name, &mut io::stdout());
 }
 
 pub fn parse() {
     let cli = IceforgeCLI::parse();
+    error::set_message_format(cli.message_format);
+    logger::set_verbosity(cli.verbose);
+    logger::set_color_mode(cli.color);
+    logger::set_warnings_enabled(!cli.no_warnings);
+    error::set_deny_warnings(cli.deny_warnings);
+    build_config::set_unused_warnings_enabled(!cli.no_unused_warnings);
+    logger::set_timestamps(cli.timestamps);
+    build_config::set_probe_cache_enabled(!cli.no_cache);
+    if let Some(log_file) = &cli.log_file {
+        if let Err(e) = logger::set_log_file(log_file) {
+            loge!("Failed to open log file {}: {}", log_file.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    if cli.dry_run {
+        // Commands that would change something but can't print it instead are refused
+        // rather than run for real
+        let supported = match &cli.command {
+            Some(Commands::Build(_) | Commands::Trigger(_) | Commands::Clean(_)) => true,
+            Some(_) => false,
+            None => !cli.run,
+        };
+        if !supported {
+            loge!("--dry-run is only supported by build, trigger and clean");
+            std::process::exit(1);
+        }
+        // Only the commands are printed, so the output can be pasted into a shell,
+        // and nothing may be written, not even the compiler probe cache
+        logger::set_log_level(LogLevel::Warning);
+        build_config::set_probe_cache_enabled(false);
+    }
 
     let mut hit_something = cli.build || cli.clean || cli.run;
 
     if let Some(command) = cli.command {
         hit_something = true;
         match command {
-            Commands::Build(build_opts) => handle_build(build_opts),
+            Commands::Build(build_opts) => handle_build(build_opts, cli.offline, cli.dry_run),
+            Commands::Watch(watch_opts) => handle_watch(watch_opts, cli.offline),
             Commands::Run(run_opts) => handle_run(run_opts),
-            Commands::Clean(clean_opts) => handle_clean(clean_opts),
-            Commands::Refresh => handle_refresh(),
-            Commands::Install => handle_install(),
+            Commands::Test(test_opts) => handle_test(test_opts, cli.offline),
+            Commands::Clean(clean_opts) => handle_clean(clean_opts, cli.dry_run),
+            Commands::Refresh => handle_refresh(cli.offline),
+            Commands::Trigger(trigger_opts) => handle_trigger(trigger_opts, cli.dry_run),
+            Commands::Install(install_opts) => handle_install(install_opts, cli.offline),
             Commands::Publish(publish_opts) => handle_publish(publish_opts),
             Commands::Init(init_opts) => handle_init(init_opts),
+            Commands::List(list_opts) => handle_list(list_opts),
+            Commands::Check(check_opts) => handle_check(check_opts, cli.offline),
+            Commands::Fmt(fmt_opts) => handle_fmt(fmt_opts),
+            Commands::Graph(graph_opts) => handle_graph(graph_opts),
+            Commands::Add(add_opts) => handle_add(add_opts),
+            Commands::Explain(explain_opts) => handle_explain(explain_opts),
+            Commands::Remove(remove_opts) => handle_remove(remove_opts),
+            Commands::Completions(completions_opts) => handle_completions(completions_opts),
         }
     }
 
     if cli.clean {
-        handle_clean(CleanOptions::default());
+        handle_clean(CleanOptions::default(), cli.dry_run);
     }
     if cli.build {
-        handle_build(BuildOptions {
-            generate_compile_commands: cli.gen_cc,
-            generate_vscode_config: cli.gen_vsc,
-            ..Default::default()
-        });
+        handle_build(
+            BuildOptions {
+                generate_compile_commands: cli.gen_cc,
+                generate_vscode_config: cli.gen_vsc,
+                ..Default::default()
+            },
+            cli.offline,
+            cli.dry_run,
+        );
     }
     if cli.run {
         handle_run(RunOptions::default());
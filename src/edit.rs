@@ -0,0 +1,286 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::error::{Error, ErrorType};
+
+// A dependency to add to the config, as given on the command line
+pub enum NewDependency {
+    Remote {
+        name: String,
+        source: String,
+        version: Option<String>,
+        include_dirs: Vec<String>,
+    },
+    PkgConfig {
+        name: String,
+        query: String,
+    },
+    Manual {
+        name: String,
+        cflags: Option<String>,
+        ldflags: Option<String>,
+        include_dirs: Vec<String>,
+        lib_dirs: Vec<String>,
+    },
+}
+
+impl NewDependency {
+    pub fn name(&self) -> &str {
+        match self {
+            NewDependency::Remote { name, .. }
+            | NewDependency::PkgConfig { name, .. }
+            | NewDependency::Manual { name, .. } => name,
+        }
+    }
+
+    // Key of the dependency kind below [dependencies]
+    fn kind(&self) -> &'static str {
+        match self {
+            NewDependency::Remote { .. } => "remote",
+            NewDependency::PkgConfig { .. } => "pkg_config",
+            NewDependency::Manual { .. } => "manual",
+        }
+    }
+
+    // Fields of the entry, without the name when it is the key of a table
+    fn fields(&self, with_name: bool) -> InlineTable {
+        let strings = |values: &[String]| values.iter().collect::<Array>();
+        let mut fields = InlineTable::new();
+        if with_name {
+            fields.insert("name", self.name().into());
+        }
+        match self {
+            NewDependency::Remote {
+                source,
+                version,
+                include_dirs,
+                ..
+            } => {
+                fields.insert("source", source.into());
+                if let Some(version) = version {
+                    fields.insert("version", version.into());
+                }
+                fields.insert("include_dirs", strings(include_dirs).into());
+            }
+            NewDependency::PkgConfig { query, .. } => {
+                fields.insert("pkg_config_query", query.into());
+            }
+            NewDependency::Manual {
+                cflags,
+                ldflags,
+                include_dirs,
+                lib_dirs,
+                ..
+            } => {
+                if let Some(cflags) = cflags {
+                    fields.insert("cflags", cflags.into());
+                }
+                if let Some(ldflags) = ldflags {
+                    fields.insert("ldflags", ldflags.into());
+                }
+                if !include_dirs.is_empty() {
+                    fields.insert("include_dirs", strings(include_dirs).into());
+                }
+                if !lib_dirs.is_empty() {
+                    fields.insert("lib_dirs", strings(lib_dirs).into());
+                }
+            }
+        }
+        fields
+    }
+}
+
+fn edit_error(message: String) -> Error {
+    Error {
+        error_type: ErrorType::TomlParseError,
+        message,
+        span: None,
+        additional_info: None,
+    }
+}
+
+fn parse_document(source: &str) -> Result<DocumentMut, Error> {
+    source.parse().map_err(|e: toml_edit::TomlError| Error {
+        error_type: ErrorType::TomlParseError,
+        message: e.message().to_string(),
+        span: e.span(),
+        additional_info: None,
+    })
+}
+
+// The config with the dependency added in the form its kind is already written in: an
+// inline array, an array of tables or tables keyed by name. Formatting and comments are
+// kept, and a kind that isn't there yet becomes an array of tables.
+pub fn add_dependency(source: &str, dep: &NewDependency) -> Result<String, Error> {
+    let mut doc = parse_document(source)?;
+
+    let dependencies = doc
+        .entry("dependencies")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_like_mut()
+        .ok_or_else(|| edit_error("dependencies is not a table".to_string()))?;
+    let kind = dependencies
+        .entry(dep.kind())
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()));
+
+    match kind {
+        Item::ArrayOfTables(array) => array.push(dep.fields(true).into_table()),
+        Item::Value(Value::Array(array)) => {
+            // Keep the layout of a multi-line array
+            let multiline = array.iter().any(|entry| {
+                entry
+                    .decor()
+                    .prefix()
+                    .and_then(|prefix| prefix.as_str())
+                    .is_some_and(|prefix| prefix.contains('\n'))
+            });
+            array.push(dep.fields(true));
+            if multiline {
+                if let Some(entry) = array.iter_mut().last() {
+                    entry.decor_mut().set_prefix("\n    ");
+                }
+                array.set_trailing(",\n");
+            }
+        }
+        Item::Table(table) => {
+            let mut entry = dep.fields(false).into_table();
+            entry.set_implicit(false);
+            table.insert(dep.name(), Item::Table(entry));
+        }
+        Item::Value(Value::InlineTable(table)) => {
+            table.insert(dep.name(), dep.fields(false).into());
+        }
+        _ => {
+            return Err(edit_error(format!(
+                "dependencies.{} is neither an array nor a table",
+                dep.kind()
+            )))
+        }
+    }
+    Ok(doc.to_string())
+}
+
+fn is_named(value: &Value, name: &str) -> bool {
+    value
+        .as_inline_table()
+        .and_then(|table| table.get("name"))
+        .and_then(Value::as_str)
+        == Some(name)
+}
+
+// Remove the values of an inline array matching, moving the decor of a removed first
+// value to the new first one so `["a", "b"]` doesn't become `[ "b"]`
+fn retain_values(array: &mut Array, keep: impl Fn(&Value) -> bool) -> usize {
+    let before = array.len();
+    let first = array.get(0).map(|value| value.decor().clone());
+    let first_kept = array.get(0).is_some_and(&keep);
+    array.retain(keep);
+    if let (Some(decor), false, Some(value)) = (first, first_kept, array.get_mut(0)) {
+        let suffix = value.decor().suffix().cloned();
+        *value.decor_mut() = decor;
+        if let Some(suffix) = suffix {
+            value.decor_mut().set_suffix(suffix);
+        }
+    }
+    before - array.len()
+}
+
+// Remove the entries of a list of named entries, in any form it can be written in
+fn remove_named(item: &mut Item, name: &str) -> usize {
+    match item {
+        Item::ArrayOfTables(array) => {
+            let before = array.len();
+            array.retain(|table| table.get("name").and_then(Item::as_str) != Some(name));
+            before - array.len()
+        }
+        Item::Value(Value::Array(array)) => retain_values(array, |value| !is_named(value, name)),
+        Item::Table(table) => table.remove(name).map_or(0, |_| 1),
+        Item::Value(Value::InlineTable(table)) => table.remove(name).map_or(0, |_| 1),
+        _ => 0,
+    }
+}
+
+// Subprojects of the config, whether written as an array of tables or an inline array
+fn for_each_subproject(doc: &mut DocumentMut, mut f: impl FnMut(&mut dyn toml_edit::TableLike)) {
+    match doc.get_mut("subprojects") {
+        Some(Item::ArrayOfTables(array)) => {
+            for table in array.iter_mut() {
+                f(table);
+            }
+        }
+        Some(Item::Value(Value::Array(array))) => {
+            for table in array.iter_mut().filter_map(Value::as_inline_table_mut) {
+                f(table);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The config without the dependency or subproject called name, and the overrides of a
+// removed subproject. Subprojects depending on it keep their reference unless
+// remove_references is set. None when nothing is called name.
+pub fn remove_entry(
+    source: &str,
+    name: &str,
+    remove_references: bool,
+) -> Result<Option<String>, Error> {
+    let mut doc = parse_document(source)?;
+
+    let mut removed = 0;
+    if let Some(dependencies) = doc
+        .get_mut("dependencies")
+        .and_then(Item::as_table_like_mut)
+    {
+        for kind in ["remote", "pkg_config", "manual"] {
+            if let Some(item) = dependencies.get_mut(kind) {
+                removed += remove_named(item, name);
+            }
+        }
+    }
+    if let Some(subprojects) = doc.get_mut("subprojects") {
+        let subprojects_removed = remove_named(subprojects, name);
+        if subprojects_removed > 0 {
+            if let Some(overrides) = doc.get_mut("overrides") {
+                remove_named(overrides, name);
+            }
+        }
+        removed += subprojects_removed;
+    }
+    if removed == 0 {
+        return Ok(None);
+    }
+
+    if remove_references {
+        for_each_subproject(&mut doc, |subproject| {
+            let Some(Item::Value(Value::Array(deps))) = subproject.get_mut("dependencies") else {
+                return;
+            };
+            retain_values(deps, |dep| {
+                dep.as_str() != Some(name) && !is_named(dep, name)
+            });
+        });
+    }
+    Ok(Some(doc.to_string()))
+}
@@ -16,10 +16,22 @@
 * You should have received a copy of the GNU General Public License
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::ops::Range;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, IsTerminal, Write},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::logger;
 
 use codespan_reporting::{
-    diagnostic::{Diagnostic, Label},
+    diagnostic::{Diagnostic, Label, Severity},
     files::SimpleFiles,
     term::{
         self,
@@ -42,36 +54,386 @@ pub struct Error {
     pub additional_info: Option<AdditionalInfo>,
 }
 
+// A problem in the config that doesn't stop the build, reported like an error. The error
+// type says what it would fail with when warnings are denied.
 #[derive(Debug, Clone)]
+pub struct Warning(pub Error);
+
+// Variants are serialized in snake_case, these names are part of the JSON output and
+// must not change
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ErrorType {
+    ConfigReadError,
     TomlParseError,
     IncorrectCompiler,
     UnsupportedCStandard,
+    InvalidCompilerFlag,
     DuplicateDependencySource,
     DuplicateDependencyName,
     DuplicateDependencyIncludeName,
     CustomBuildMissing,
     ExtraFieldNonCustomBuild,
     InvalidPkgConfigQuery,
+    MissingManualPath,
     DuplicateSubprojectName,
     InvalidSubprojectDependency,
     CircularDependency,
     OverrideNameConflict,
     DuplicateCustomBuildRuleName,
+    InvalidCustomBuildRule,
+    ConflictingBuildRules,
+    CompilationFailed,
+    LinkingFailed,
+    CustomBuildRuleFailed,
+    UnknownCustomBuildRule,
+    FetchFailed,
+    UnresolvableVersion,
+    LockfileError,
+    InstallFailed,
+    InitFailed,
+    PublishFailed,
+    InvalidImport,
+    NameShadowing,
+    UndefinedVariable,
+    InvalidVersionConstraint,
+    NoSourcesMatched,
+    InvalidDefine,
+    InvalidLibraryKind,
+    UnusedDependency,
+    EntryPointMismatch,
+    EmptyIncludeDirs,
+    UnusedOverride,
+    InvalidPlatform,
+    OtherPlatformDependency,
+    InvalidOutputName,
+    InvalidParallelJobs,
+    InvalidDependencySource,
+    InvalidWorkspaceMember,
+    InvalidToolchain,
+    UnknownPreset,
+    CleanFailed,
+    InvalidPrecompiledHeader,
+    UnknownProfile,
+}
+
+// How errors are reported, set once from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+static MESSAGE_FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+pub fn set_message_format(format: MessageFormat) {
+    let _ = MESSAGE_FORMAT.set(format);
+}
+
+// Whether warnings about the config are errors, for CI
+static DENY_WARNINGS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_deny_warnings(deny: bool) {
+    DENY_WARNINGS.store(deny, Ordering::Relaxed);
+}
+
+pub fn deny_warnings() -> bool {
+    DENY_WARNINGS.load(Ordering::Relaxed)
+}
+
+impl ErrorType {
+    // Stable identifier for tools matching on error kinds, the serde name of the variant
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorType::ConfigReadError => "config_read_error",
+            ErrorType::TomlParseError => "toml_parse_error",
+            ErrorType::IncorrectCompiler => "incorrect_compiler",
+            ErrorType::UnsupportedCStandard => "unsupported_c_standard",
+            ErrorType::InvalidCompilerFlag => "invalid_compiler_flag",
+            ErrorType::DuplicateDependencySource => "duplicate_dependency_source",
+            ErrorType::DuplicateDependencyName => "duplicate_dependency_name",
+            ErrorType::DuplicateDependencyIncludeName => "duplicate_dependency_include_name",
+            ErrorType::CustomBuildMissing => "custom_build_missing",
+            ErrorType::ExtraFieldNonCustomBuild => "extra_field_non_custom_build",
+            ErrorType::InvalidPkgConfigQuery => "invalid_pkg_config_query",
+            ErrorType::MissingManualPath => "missing_manual_path",
+            ErrorType::DuplicateSubprojectName => "duplicate_subproject_name",
+            ErrorType::InvalidSubprojectDependency => "invalid_subproject_dependency",
+            ErrorType::CircularDependency => "circular_dependency",
+            ErrorType::OverrideNameConflict => "override_name_conflict",
+            ErrorType::DuplicateCustomBuildRuleName => "duplicate_custom_build_rule_name",
+            ErrorType::InvalidCustomBuildRule => "invalid_custom_build_rule",
+            ErrorType::ConflictingBuildRules => "conflicting_build_rules",
+            ErrorType::CompilationFailed => "compilation_failed",
+            ErrorType::LinkingFailed => "linking_failed",
+            ErrorType::CustomBuildRuleFailed => "custom_build_rule_failed",
+            ErrorType::UnknownCustomBuildRule => "unknown_custom_build_rule",
+            ErrorType::FetchFailed => "fetch_failed",
+            ErrorType::UnresolvableVersion => "unresolvable_version",
+            ErrorType::LockfileError => "lockfile_error",
+            ErrorType::InstallFailed => "install_failed",
+            ErrorType::InitFailed => "init_failed",
+            ErrorType::PublishFailed => "publish_failed",
+            ErrorType::InvalidImport => "invalid_import",
+            ErrorType::NameShadowing => "name_shadowing",
+            ErrorType::UndefinedVariable => "undefined_variable",
+            ErrorType::InvalidVersionConstraint => "invalid_version_constraint",
+            ErrorType::NoSourcesMatched => "no_sources_matched",
+            ErrorType::InvalidDefine => "invalid_define",
+            ErrorType::InvalidLibraryKind => "invalid_library_kind",
+            ErrorType::UnusedDependency => "unused_dependency",
+            ErrorType::EntryPointMismatch => "entry_point_mismatch",
+            ErrorType::EmptyIncludeDirs => "empty_include_dirs",
+            ErrorType::UnusedOverride => "unused_override",
+            ErrorType::InvalidPlatform => "invalid_platform",
+            ErrorType::OtherPlatformDependency => "other_platform_dependency",
+            ErrorType::InvalidOutputName => "invalid_output_name",
+            ErrorType::InvalidParallelJobs => "invalid_parallel_jobs",
+            ErrorType::InvalidDependencySource => "invalid_dependency_source",
+            ErrorType::InvalidWorkspaceMember => "invalid_workspace_member",
+            ErrorType::InvalidToolchain => "invalid_toolchain",
+            ErrorType::UnknownPreset => "unknown_preset",
+            ErrorType::CleanFailed => "clean_failed",
+            ErrorType::InvalidPrecompiledHeader => "invalid_precompiled_header",
+            ErrorType::UnknownProfile => "unknown_profile",
+        }
+    }
+
+    // Process exit code for the category of the error, part of the command line interface:
+    // 2 for config errors, 3 for toolchain errors, 4 for dependency errors and 5 for build
+    // failures. Anything else exits with 1.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorType::ConfigReadError
+            | ErrorType::TomlParseError
+            | ErrorType::InvalidCompilerFlag
+            | ErrorType::DuplicateDependencySource
+            | ErrorType::DuplicateDependencyName
+            | ErrorType::DuplicateDependencyIncludeName
+            | ErrorType::CustomBuildMissing
+            | ErrorType::ExtraFieldNonCustomBuild
+            | ErrorType::DuplicateSubprojectName
+            | ErrorType::InvalidSubprojectDependency
+            | ErrorType::CircularDependency
+            | ErrorType::OverrideNameConflict
+            | ErrorType::DuplicateCustomBuildRuleName
+            | ErrorType::InvalidCustomBuildRule
+            | ErrorType::ConflictingBuildRules
+            | ErrorType::UnknownCustomBuildRule
+            | ErrorType::InvalidImport
+            | ErrorType::NameShadowing
+            | ErrorType::UndefinedVariable
+            | ErrorType::InvalidVersionConstraint
+            | ErrorType::NoSourcesMatched
+            | ErrorType::InvalidDefine
+            | ErrorType::InvalidLibraryKind
+            | ErrorType::UnusedDependency
+            | ErrorType::EntryPointMismatch
+            | ErrorType::EmptyIncludeDirs
+            | ErrorType::UnusedOverride
+            | ErrorType::InvalidPlatform
+            | ErrorType::OtherPlatformDependency
+            | ErrorType::InvalidOutputName
+            | ErrorType::InvalidParallelJobs
+            | ErrorType::InvalidDependencySource
+            | ErrorType::InvalidWorkspaceMember
+            | ErrorType::UnknownPreset
+            | ErrorType::InvalidPrecompiledHeader
+            | ErrorType::UnknownProfile => 2,
+            ErrorType::IncorrectCompiler
+            | ErrorType::UnsupportedCStandard
+            | ErrorType::InvalidToolchain => 3,
+            ErrorType::InvalidPkgConfigQuery
+            | ErrorType::MissingManualPath
+            | ErrorType::FetchFailed
+            | ErrorType::UnresolvableVersion
+            | ErrorType::LockfileError => 4,
+            ErrorType::CompilationFailed
+            | ErrorType::LinkingFailed
+            | ErrorType::CustomBuildRuleFailed => 5,
+            ErrorType::InstallFailed
+            | ErrorType::InitFailed
+            | ErrorType::PublishFailed
+            | ErrorType::CleanFailed => 1,
+        }
+    }
+}
+
+impl fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+// Only the message, spans need the config's source to mean anything, see with_source
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    // Underlying failures, of git or the file system, are already part of the message
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+// Byte offsets at which the lines of a source start, for locating spans
+struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { source, starts }
+    }
+
+    // 1-based line and column, counted in characters, of a byte offset
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+        let column = self
+            .source
+            .get(start..offset)
+            .map_or(offset - start, |text| text.chars().count());
+        (line, column + 1)
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_span(span: &Option<Range<usize>>) -> String {
+    match span {
+        Some(span) => format!("{{\"start\":{},\"end\":{}}}", span.start, span.end),
+        None => "null".to_string(),
+    }
 }
 
 impl Error {
-    pub fn emit_config_error(&self, config_path: &str) {
-        let config_contents = std::fs::read_to_string(config_path).unwrap();
+    // Single line `path:line:col: message` description of the error, for consumers
+    // without codespan. Errors without a span only get the path.
+    pub fn with_source(&self, path: &str, source: &str) -> String {
+        let Some(span) = &self.span else {
+            return format!("{}: {}", path, self.message);
+        };
+        let index = LineIndex::new(source);
+        let (line, column) = index.line_column(span.start);
+        let mut text = format!("{}:{}:{}: {}", path, line, column, self.message);
+        if let Some(info) = &self.additional_info {
+            let (line, column) = index.line_column(info.span.start);
+            text.push_str(&format!(
+                " ({}:{}:{}: {})",
+                path, line, column, info.message
+            ));
+        }
+        text
+    }
+
+    // Single line JSON object describing the error, for editor integration
+    pub fn to_json(&self, config_path: &str) -> String {
+        self.json(config_path, Severity::Error)
+    }
+
+    fn json(&self, config_path: &str, severity: Severity) -> String {
+        let additional_info = match &self.additional_info {
+            Some(info) => format!(
+                "{{\"message\":{},\"span\":{}}}",
+                json_string(&info.message),
+                json_span(&Some(info.span.clone()))
+            ),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"error_type\":{},\"message\":{},\"span\":{},\"file\":{},\"additional_info\":{},\"severity\":{}}}",
+            json_string(self.error_type.code()),
+            json_string(&self.message),
+            json_span(&self.span),
+            json_string(config_path),
+            additional_info,
+            json_string(severity_name(severity))
+        )
+    }
+
+    // Report an error in the config whose text is source, falling back to a single
+    // line when the diagnostic can't be rendered. Fails only when the report can't be
+    // written.
+    pub fn emit_config_error(&self, config_path: &str, source: &str) -> io::Result<()> {
+        self.emit_config_diagnostic(Severity::Error, config_path, source)
+    }
+
+    fn emit_config_diagnostic(
+        &self,
+        severity: Severity,
+        config_path: &str,
+        source: &str,
+    ) -> io::Result<()> {
+        if MESSAGE_FORMAT.get().copied().unwrap_or_default() == MessageFormat::Json {
+            return writeln!(io::stdout(), "{}", self.json(config_path, severity));
+        }
+        match self.try_emit_config_diagnostic(severity, config_path, source) {
+            Ok(()) => Ok(()),
+            Err(codespan_reporting::files::Error::Io(e)) => Err(e),
+            // Spans outside of source, for one
+            Err(_) => writeln!(
+                io::stderr(),
+                "{}: {} ({})",
+                severity_name(severity),
+                self.with_source(config_path, source),
+                self.error_type.explain_hint()
+            ),
+        }
+    }
+
+    fn try_emit_config_diagnostic(
+        &self,
+        severity: Severity,
+        config_path: &str,
+        source: &str,
+    ) -> Result<(), codespan_reporting::files::Error> {
         let mut files = SimpleFiles::new();
-        let file_id = files.add(config_path, config_contents);
-        let writer = StandardStream::stderr(ColorChoice::Always);
+        let file_id = files.add(config_path, source);
+        let color = if logger::use_color(std::io::stderr().is_terminal()) {
+            ColorChoice::Always
+        } else {
+            ColorChoice::Never
+        };
+        let writer = StandardStream::stderr(color);
         let config = codespan_reporting::term::Config::default();
-        let mut labels_vec = Vec::new();
+        let notes = vec![self.error_type.explain_hint()];
+
+        let Some(span) = self.span.clone() else {
+            let diag = Diagnostic::new(severity)
+                .with_code(self.error_type.id())
+                .with_message(format!("{}: {}", config_path, self.message))
+                .with_notes(notes);
+            let mut writer = writer.lock();
+            return term::emit(&mut writer, &config, &files, &diag);
+        };
 
-        labels_vec.push(
-            Label::primary(file_id, self.span.clone().unwrap()).with_message(self.clone().message),
-        );
+        let mut labels_vec = Vec::new();
+        labels_vec.push(Label::primary(file_id, span).with_message(self.clone().message));
         if let Some(additional_info) = self.additional_info.clone() {
             labels_vec.push(
                 Label::secondary(file_id, additional_info.span)
@@ -79,10 +441,81 @@ impl Error {
             );
         }
 
-        let diag = Diagnostic::error()
-            .with_message("Error parsing config")
-            .with_labels(labels_vec);
+        let message = match severity {
+            Severity::Error => "Error parsing config",
+            _ => "Warning in config",
+        };
+        let diag = Diagnostic::new(severity)
+            .with_code(self.error_type.id())
+            .with_message(message)
+            .with_labels(labels_vec)
+            .with_notes(notes);
+
+        let mut writer = writer.lock();
+        term::emit(&mut writer, &config, &files, &diag)
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error | Severity::Bug => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+impl Warning {
+    // Report the warning like an error, unless warnings are disabled
+    pub fn emit_config_warning(&self, config_path: &str, source: &str) -> io::Result<()> {
+        if !logger::is_enabled(logger::LogLevel::Warning) {
+            return Ok(());
+        }
+        self.0
+            .emit_config_diagnostic(Severity::Warning, config_path, source)
+    }
+}
+
+// Report every error in the config, each as its own diagnostic, followed by a count when
+// there is more than one
+pub fn emit_config_errors(errors: &[Error], config_path: &str, source: &str) -> io::Result<()> {
+    for error in errors {
+        error.emit_config_error(config_path, source)?;
+    }
+    if errors.len() > 1 && MESSAGE_FORMAT.get().copied().unwrap_or_default() != MessageFormat::Json
+    {
+        writeln!(
+            io::stderr(),
+            "error: {} errors in {}",
+            errors.len(),
+            config_path
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_the_serde_names_of_the_variants() {
+        for error_type in ErrorType::ALL {
+            let serialized = toml::Value::try_from(&error_type).unwrap();
+            assert_eq!(serialized.as_str(), Some(error_type.code()));
+        }
+    }
 
-        term::emit(&mut writer.lock(), &config, &files, &diag).unwrap();
+    #[test]
+    fn errors_convert_to_boxed_std_errors() {
+        let error = Error {
+            error_type: ErrorType::InvalidSubprojectDependency,
+            message: "Invalid dependency: zz".to_string(),
+            span: Some(0..2),
+            additional_info: None,
+        };
+        let boxed: Box<dyn std::error::Error> = error.into();
+        assert_eq!(boxed.to_string(), "Invalid dependency: zz");
+        assert!(boxed.source().is_none());
     }
 }
@@ -0,0 +1,741 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::error::ErrorType;
+
+impl ErrorType {
+    // Every error type, in the order of their ids
+    pub const ALL: [ErrorType; 51] = [
+        ErrorType::ConfigReadError,
+        ErrorType::TomlParseError,
+        ErrorType::IncorrectCompiler,
+        ErrorType::UnsupportedCStandard,
+        ErrorType::InvalidCompilerFlag,
+        ErrorType::DuplicateDependencySource,
+        ErrorType::DuplicateDependencyName,
+        ErrorType::DuplicateDependencyIncludeName,
+        ErrorType::CustomBuildMissing,
+        ErrorType::ExtraFieldNonCustomBuild,
+        ErrorType::InvalidPkgConfigQuery,
+        ErrorType::MissingManualPath,
+        ErrorType::DuplicateSubprojectName,
+        ErrorType::InvalidSubprojectDependency,
+        ErrorType::CircularDependency,
+        ErrorType::OverrideNameConflict,
+        ErrorType::DuplicateCustomBuildRuleName,
+        ErrorType::InvalidCustomBuildRule,
+        ErrorType::ConflictingBuildRules,
+        ErrorType::CompilationFailed,
+        ErrorType::LinkingFailed,
+        ErrorType::CustomBuildRuleFailed,
+        ErrorType::UnknownCustomBuildRule,
+        ErrorType::FetchFailed,
+        ErrorType::UnresolvableVersion,
+        ErrorType::LockfileError,
+        ErrorType::InstallFailed,
+        ErrorType::InitFailed,
+        ErrorType::PublishFailed,
+        ErrorType::InvalidImport,
+        ErrorType::NameShadowing,
+        ErrorType::UndefinedVariable,
+        ErrorType::InvalidVersionConstraint,
+        ErrorType::NoSourcesMatched,
+        ErrorType::InvalidDefine,
+        ErrorType::InvalidLibraryKind,
+        ErrorType::UnusedDependency,
+        ErrorType::EntryPointMismatch,
+        ErrorType::EmptyIncludeDirs,
+        ErrorType::UnusedOverride,
+        ErrorType::InvalidPlatform,
+        ErrorType::OtherPlatformDependency,
+        ErrorType::InvalidOutputName,
+        ErrorType::InvalidParallelJobs,
+        ErrorType::InvalidDependencySource,
+        ErrorType::InvalidWorkspaceMember,
+        ErrorType::InvalidToolchain,
+        ErrorType::UnknownPreset,
+        ErrorType::CleanFailed,
+        ErrorType::InvalidPrecompiledHeader,
+        ErrorType::UnknownProfile,
+    ];
+
+    // Id for `iceforge explain`, such as IF0007. Ids are never reused or renumbered, new
+    // error types get the next free one.
+    pub fn id(&self) -> String {
+        let number = match self {
+            ErrorType::ConfigReadError => 1,
+            ErrorType::TomlParseError => 2,
+            ErrorType::IncorrectCompiler => 3,
+            ErrorType::UnsupportedCStandard => 4,
+            ErrorType::InvalidCompilerFlag => 5,
+            ErrorType::DuplicateDependencySource => 6,
+            ErrorType::DuplicateDependencyName => 7,
+            ErrorType::DuplicateDependencyIncludeName => 8,
+            ErrorType::CustomBuildMissing => 9,
+            ErrorType::ExtraFieldNonCustomBuild => 10,
+            ErrorType::InvalidPkgConfigQuery => 11,
+            ErrorType::MissingManualPath => 12,
+            ErrorType::DuplicateSubprojectName => 13,
+            ErrorType::InvalidSubprojectDependency => 14,
+            ErrorType::CircularDependency => 15,
+            ErrorType::OverrideNameConflict => 16,
+            ErrorType::DuplicateCustomBuildRuleName => 17,
+            ErrorType::InvalidCustomBuildRule => 18,
+            ErrorType::ConflictingBuildRules => 19,
+            ErrorType::CompilationFailed => 20,
+            ErrorType::LinkingFailed => 21,
+            ErrorType::CustomBuildRuleFailed => 22,
+            ErrorType::UnknownCustomBuildRule => 23,
+            ErrorType::FetchFailed => 24,
+            ErrorType::UnresolvableVersion => 25,
+            ErrorType::LockfileError => 26,
+            ErrorType::InstallFailed => 27,
+            ErrorType::InitFailed => 28,
+            ErrorType::PublishFailed => 29,
+            ErrorType::InvalidImport => 30,
+            ErrorType::NameShadowing => 31,
+            ErrorType::UndefinedVariable => 32,
+            ErrorType::InvalidVersionConstraint => 33,
+            ErrorType::NoSourcesMatched => 34,
+            ErrorType::InvalidDefine => 35,
+            ErrorType::InvalidLibraryKind => 36,
+            ErrorType::UnusedDependency => 37,
+            ErrorType::EntryPointMismatch => 38,
+            ErrorType::EmptyIncludeDirs => 39,
+            ErrorType::UnusedOverride => 40,
+            ErrorType::InvalidPlatform => 41,
+            ErrorType::OtherPlatformDependency => 42,
+            ErrorType::InvalidOutputName => 43,
+            ErrorType::InvalidParallelJobs => 44,
+            ErrorType::InvalidDependencySource => 45,
+            ErrorType::InvalidWorkspaceMember => 46,
+            ErrorType::InvalidToolchain => 47,
+            ErrorType::UnknownPreset => 48,
+            ErrorType::CleanFailed => 49,
+            ErrorType::InvalidPrecompiledHeader => 50,
+            ErrorType::UnknownProfile => 51,
+        };
+        format!("IF{:04}", number)
+    }
+
+    // The error type with an id, or with a snake_case name as in the JSON output.
+    // Case doesn't matter.
+    pub fn from_id(id: &str) -> Option<ErrorType> {
+        Self::ALL.into_iter().find(|error_type| {
+            error_type.id().eq_ignore_ascii_case(id) || error_type.code().eq_ignore_ascii_case(id)
+        })
+    }
+
+    pub fn explain_hint(&self) -> String {
+        format!("run `iceforge explain {}` for details", self.id())
+    }
+
+    // What the error means, what usually causes it and how a fix looks
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ErrorType::ConfigReadError => {
+                r#"iceforge.toml could not be read.
+
+iceforge looks for iceforge.toml in the current directory. The file is missing
+when iceforge is run outside the project root, or it isn't readable by the
+current user.
+
+Run iceforge from the directory containing iceforge.toml, or create a project
+with:
+
+    iceforge init"#
+            }
+            ErrorType::TomlParseError => {
+                r#"The config is not valid TOML or doesn't have the shape iceforge expects.
+
+Common causes are a missing quote or bracket, a misspelled key, a value of the
+wrong type or a required field that is missing, such as the name of a
+subproject.
+
+    [[subprojects]]
+    name = "app"       # not: name = app
+    type = "binary""#
+            }
+            ErrorType::IncorrectCompiler => {
+                r#"The compiler named in [build] or an override can't be found in PATH.
+
+The compiler isn't installed, or `compiler` is misspelled. When C++ sources are
+built the matching C++ compiler (g++ for gcc, clang++ for clang) has to be
+found as well, and so does the compiler_launcher, or the launcher given in
+CRYO_COMPILER_LAUNCHER.
+
+    [build]
+    compiler = "gcc"
+    compiler_launcher = "ccache""#
+            }
+            ErrorType::UnsupportedCStandard => {
+                r#"The C standard in [build] or an override is unknown or not supported by the compiler.
+
+Either `c_standard` is not one of the standards iceforge knows (c89 to c23 and
+their gnu variants), or the compiler rejects it with -std. Older compilers may
+not know c2x/c23 yet. An override setting only one of compiler and c_standard
+is checked against the global value of the other.
+
+    [build]
+    c_standard = "c11""#
+            }
+            ErrorType::InvalidCompilerFlag => {
+                r#"The compiler rejected global_cflags, debug_flags or release_flags.
+
+Reported by `iceforge check --flags`, which compiles an empty file with each
+set of flags. A typo such as -Walll or an unterminated quote are the usual
+causes.
+
+    [build]
+    global_cflags = "-Wall -Wextra""#
+            }
+            ErrorType::DuplicateDependencySource => {
+                r#"Two remote dependencies fetch the same source at the same version.
+
+The second entry would fetch and build the same code again under another name.
+Usually one of them was copied and not changed. Remove the duplicate, or
+point it at a different version if both are really needed.
+
+    [[dependencies.remote]]
+    name = "zlib"
+    source = "https://github.com/madler/zlib.git"
+    version = "v1.3.1""#
+            }
+            ErrorType::DuplicateDependencyName => {
+                r#"Two dependencies have the same name.
+
+Names of dependencies are shared across remote, pkg_config and manual
+dependencies, as subprojects refer to them by name alone. Rename one of them
+and update the subprojects depending on it.
+
+    [[dependencies.pkg_config]]
+    name = "ssl"
+    pkg_config_query = "openssl"
+
+    [[dependencies.manual]]
+    name = "ssl_static"   # not: name = "ssl"
+    ldflags = "-l:libssl.a""#
+            }
+            ErrorType::DuplicateDependencyIncludeName => {
+                r#"Two remote dependencies have the same include_name.
+
+include_name is the folder prefix the headers of a dependency are included
+with, so two dependencies sharing it would shadow each other's headers. Give
+each a distinct include_name.
+
+    [[dependencies.remote]]
+    name = "mylib"
+    include_name = "mylib""#
+            }
+            ErrorType::CustomBuildMissing => {
+                r#"A remote dependency has build_method = "custom" but no build_command.
+
+A custom build needs the command that builds the dependency, and usually the
+build_output it produces.
+
+    [[dependencies.remote]]
+    name = "lua"
+    build_method = "custom"
+    build_command = "make a"
+    build_output = "src/liblua.a""#
+            }
+            ErrorType::ExtraFieldNonCustomBuild => {
+                r#"A remote dependency sets build_command or build_output without a custom build.
+
+These fields only apply when build_method is "custom". With any other build
+method, or none, they would be silently ignored. Either remove them or make
+the build custom.
+
+    [[dependencies.remote]]
+    name = "lua"
+    build_method = "custom"   # needed for the two fields below
+    build_command = "make a"
+    build_output = "src/liblua.a""#
+            }
+            ErrorType::InvalidPkgConfigQuery => {
+                r#"pkg-config doesn't know the query of a pkg-config dependency.
+
+The library or its development package isn't installed, the query is
+misspelled, or PKG_CONFIG_PATH doesn't include the directory of its .pc file.
+`pkg-config --list-all` shows the available names.
+
+    [[dependencies.pkg_config]]
+    name = "freetype"
+    pkg_config_query = "freetype2""#
+            }
+            ErrorType::MissingManualPath => {
+                r#"An include or library directory of a manual dependency doesn't exist.
+
+The directory was moved, the path is relative to the wrong place (paths are
+relative to the project root), or it depends on an environment variable that
+isn't set.
+
+    [[dependencies.manual]]
+    name = "raylib"
+    include_dirs = ["${HOME}/raylib/include"]
+    lib_dirs = ["${HOME}/raylib/lib"]
+    ldflags = "-lraylib""#
+            }
+            ErrorType::DuplicateSubprojectName => {
+                r#"Two subprojects have the same name.
+
+Subproject names identify build outputs and dependencies, so they have to be
+unique. Rename one of them and update the subprojects depending on it.
+
+    [[subprojects]]
+    name = "core"
+
+    [[subprojects]]
+    name = "core_tests"   # not: name = "core""#
+            }
+            ErrorType::InvalidSubprojectDependency => {
+                r#"A subproject depends on something that isn't a library or a dependency.
+
+Subprojects can depend on library and header-only subprojects and on declared
+dependencies. Binaries can't be depended on. The name is often misspelled, or
+the dependency was never declared.
+
+    [dependencies]
+    manual = [{ name = "m", ldflags = "-lm" }]
+
+    [[subprojects]]
+    name = "app"
+    dependencies = ["core", "m"]"#
+            }
+            ErrorType::CircularDependency => {
+                r#"Subprojects depend on each other in a cycle, so there is no build order.
+
+A subproject may depend on itself directly, or through others, as in
+a -> b -> a. `iceforge graph` shows the cycle. Move the shared code into a
+library both depend on.
+
+    [[subprojects]]
+    name = "a"
+    dependencies = ["common"]   # not: ["b"] while b depends on a"#
+            }
+            ErrorType::OverrideNameConflict => {
+                r#"An override is defined twice or names no subproject.
+
+Each override applies to the subproject with the same name, so there can be at
+most one per subproject and its name has to match one. Merge duplicate
+overrides and fix misspelled names.
+
+    [[overrides]]
+    name = "app"   # an existing subproject
+    cflags = "-O0""#
+            }
+            ErrorType::DuplicateCustomBuildRuleName => {
+                r#"Two custom build rules have the same name.
+
+Rules are triggered by name with `iceforge trigger`, so names have to be
+unique. Rename one of them.
+
+    [[custom_build_rules]]
+    name = "vertex_shaders"
+
+    [[custom_build_rules]]
+    name = "fragment_shaders""#
+            }
+            ErrorType::InvalidCustomBuildRule => {
+                r#"A custom build rule can never produce anything useful.
+
+The rule has no or an invalid trigger extension, an invalid output extension,
+a command that never uses its input ($in or ${in}), a src_dir that doesn't
+exist, or an output_dir that is a file.
+
+    [[custom_build_rules]]
+    name = "shaders"
+    src_dir = "shaders"
+    output_dir = "build/shaders"
+    trigger_extensions = ["vert"]
+    output_extension = "spv"
+    command = "glslc -o $out $in""#
+            }
+            ErrorType::ConflictingBuildRules => {
+                r#"Two custom build rules would process the same files.
+
+Their source directories overlap, one inside the other, and they share a
+trigger extension, so both rules run on the same inputs. This is only an
+error with `iceforge check --strict`. Give the rules separate directories or
+extensions.
+
+    [[custom_build_rules]]
+    src_dir = "shaders/vertex"
+    trigger_extensions = ["vert"]
+
+    [[custom_build_rules]]
+    src_dir = "shaders/fragment"
+    trigger_extensions = ["frag"]"#
+            }
+            ErrorType::CompilationFailed => {
+                r#"The compiler failed on a source file.
+
+The compiler's own messages above this one say why. Missing headers usually
+mean a dependency or include directory is missing from the subproject.
+`iceforge build -v` shows the full command that was run."#
+            }
+            ErrorType::LinkingFailed => {
+                r#"Linking a binary or library failed.
+
+Undefined references usually mean a library is missing from the subproject's
+dependencies, or a manual dependency lacks its -l flag or lib_dirs. Multiple
+definitions of main() mean a library contains a main function.
+
+    [[subprojects]]
+    name = "app"
+    dependencies = ["core", "m"]"#
+            }
+            ErrorType::CustomBuildRuleFailed => {
+                r#"The command of a custom build rule failed.
+
+The command's own output above this one says why. The tool it runs may be
+missing from PATH. `iceforge --dry-run build` prints the expanded command,
+which can be run by hand to debug it."#
+            }
+            ErrorType::UnknownCustomBuildRule => {
+                r#"`iceforge trigger` was given a rule that isn't in the config.
+
+The rule name is misspelled or the rule was removed. The error lists the
+available rules.
+
+    iceforge trigger shaders"#
+            }
+            ErrorType::FetchFailed => {
+                r#"A remote dependency couldn't be fetched.
+
+git failed to clone or update the source: the URL is wrong, the network or
+credentials are missing, or --offline forbids fetching a dependency that isn't
+in deps/ yet. `iceforge check --strict` checks every source can be reached.
+
+    iceforge refresh"#
+            }
+            ErrorType::UnresolvableVersion => {
+                r#"No tag of a remote dependency matches its version requirement.
+
+The requirement is stricter than any published version, or the repository has
+no version tags. `git ls-remote --tags <source>` lists the available tags.
+
+    [[dependencies.remote]]
+    name = "mylib"
+    version = ">=1.2, <2.0""#
+            }
+            ErrorType::LockfileError => {
+                r#"iceforge.lock couldn't be read, parsed or written.
+
+The lockfile was edited by hand or has a merge conflict. It is generated, so
+it can be deleted and recreated.
+
+    rm iceforge.lock
+    iceforge refresh"#
+            }
+            ErrorType::InstallFailed => {
+                r#"Installing the project failed.
+
+The prefix isn't writable (system prefixes need root, or --prefix ~/.local),
+the build of the project failed, or a remote project couldn't be fetched.
+
+    iceforge install --prefix ~/.local"#
+            }
+            ErrorType::InitFailed => {
+                r#"A new project couldn't be created.
+
+The directory already contains an iceforge.toml or template files, or it isn't
+writable. --force overwrites existing files.
+
+    iceforge init --force"#
+            }
+            ErrorType::PublishFailed => {
+                r#"The current version couldn't be tagged.
+
+The working tree has uncommitted changes, the version is already tagged, or
+the tag couldn't be pushed. Commit first, bump the version in [build], or use
+--force to move an existing tag.
+
+    [build]
+    version = "0.2.0""#
+            }
+            ErrorType::InvalidImport => {
+                r#"A subproject imports something its dependency doesn't export.
+
+Only remote dependencies have imports, listed in their `imports` field. The
+error lists what is available.
+
+    [[dependencies.remote]]
+    name = "engine"
+    imports = ["math", "render"]
+
+    [[subprojects]]
+    name = "game"
+    dependencies = [{ name = "engine", imports = ["math"] }]"#
+            }
+            ErrorType::NameShadowing => {
+                r#"A subproject has the same name as a dependency.
+
+Subprojects list their dependencies by name, so the name would be ambiguous.
+Rename the subproject or the dependency.
+
+    [[dependencies.manual]]
+    name = "m"
+
+    [[subprojects]]
+    name = "mathutils"   # not: name = "m""#
+            }
+            ErrorType::UndefinedVariable => {
+                r#"A ${NAME} reference in the config names an undefined variable.
+
+Paths and custom build rule commands expand ${PROJECT_ROOT}, rule variables
+such as ${in} and ${out}, and environment variables. The variable is
+misspelled or not set in the environment. A literal $ is written as $$.
+
+    [[custom_build_rules]]
+    command = "${COMPILER} -c $in -o $out""#
+            }
+            ErrorType::InvalidVersionConstraint => {
+                r#"The version of a remote dependency looks like a requirement but doesn't parse.
+
+Versions starting with ^, ~, >, < or =, or containing a comma or *, are parsed
+as requirements. Anything else is used as a git ref. A malformed requirement is
+an error rather than being tried as a ref.
+
+    [[dependencies.remote]]
+    version = "^1.2"   # not: "^1.x.5""#
+            }
+            ErrorType::NoSourcesMatched => {
+                r#"The sources patterns of a subproject match no file, its exclude
+patterns drop every source file, or one of its source directories is missing.
+
+The sources patterns are relative to the project root and are misspelled, or
+the files were moved. Exclude patterns are relative to src_dir and only need to
+match the files to leave out. The src_dir and every entry of src_dirs have to
+be existing directories.
+
+    [[subprojects]]
+    name = "core"
+    src_dir = "src/core"
+    src_dirs = ["src/platform/linux"]
+    exclude = ["**/*_test.c", "platform/win32/*"]"#
+            }
+            ErrorType::InvalidDefine => {
+                r#"A define isn't a valid preprocessor definition.
+
+Defines are written as NAME or NAME=value, where NAME is a C identifier and
+there are no spaces. Flags such as -DNAME belong in cflags instead.
+
+    [[subprojects]]
+    defines = ["DEBUG_LOG", "VERSION=2"]"#
+            }
+            ErrorType::InvalidLibraryKind => {
+                r#"library_type is set on a subproject that isn't a library.
+
+Only library subprojects can be built static, shared or both. Remove
+library_type or change the type of the subproject.
+
+    [[subprojects]]
+    name = "core"
+    type = "library"
+    library_type = "shared""#
+            }
+            ErrorType::UnusedDependency => {
+                r#"A dependency is declared but no subproject depends on it.
+
+A warning unless --no-unused-warnings is passed, and an error with
+--deny-warnings or `iceforge check --deny-unused`. The dependency is still
+fetched and built, but is left over from removed code, or the subproject that
+needs it forgot to list it. Remove the dependency or add it to a subproject.
+
+    [[subprojects]]
+    name = "app"
+    dependencies = ["zlib"]"#
+            }
+            ErrorType::EntryPointMismatch => {
+                r#"A binary has no main() in its sources, or a library defines one.
+
+A warning, or an error with --deny-warnings. A binary without main() fails to
+link unless main comes from a dependency, and a library defining main() will
+clash with the main of the binaries linking it. The subproject type is usually
+wrong, or main lives in a file outside src_dir.
+
+    [[subprojects]]
+    name = "app"
+    type = "binary"    # the subproject with main()
+    src_dir = "src/app""#
+            }
+            ErrorType::EmptyIncludeDirs => {
+                r#"include_dirs is set but empty.
+
+A warning, or an error with --deny-warnings. An empty list adds no include
+directory, so the headers of the subproject or dependency can't be found by
+others. List the directories or remove the field.
+
+    [[subprojects]]
+    name = "core"
+    include_dirs = ["src/core/include"]   # not: []"#
+            }
+            ErrorType::UnusedOverride => {
+                r#"An override sets nothing besides its name.
+
+A warning, or an error with --deny-warnings. The override has no effect,
+usually because its settings were removed or misspelled. Set what should
+change for the subproject, or remove the override.
+
+    [[overrides]]
+    name = "app"
+    cflags = "-O0""#
+            }
+            ErrorType::InvalidPlatform => {
+                r#"The platforms of a dependency name an unknown operating system.
+
+Platforms are named as Rust names them: linux, macos, windows, freebsd,
+netbsd, openbsd, dragonfly, android, ios, solaris or illumos. A misspelled
+platform would leave the dependency out everywhere, so it is an error.
+
+    [[dependencies.pkg_config]]
+    name = "zlib"
+    pkg_config_query = "zlib"
+    platforms = ["linux", "macos"]   # not: ["linx", "osx"]"#
+            }
+            ErrorType::OtherPlatformDependency => {
+                r#"A subproject depends on a dependency that isn't used on this platform.
+
+A warning, or an error with --deny-warnings. The dependency's platforms leave
+out the current one, so the subproject is built without it. That is fine when
+its code only needs the dependency on those platforms. Otherwise declare a
+dependency with the same name for the other platforms.
+
+    [[dependencies.pkg_config]]
+    name = "compression"
+    pkg_config_query = "zlib"
+    platforms = ["linux"]
+
+    [[dependencies.manual]]
+    name = "compression"
+    ldflags = "-lcompression"
+    platforms = ["macos"]"#
+            }
+            ErrorType::InvalidOutputName => {
+                r#"The output_name of a subproject isn't a safe file name, or is taken.
+
+Output names may only contain letters, digits, '.', '_' and '-', and can't
+start with '.' or '-', so they can't escape the build directory or be taken for
+a flag. Two binaries, or two libraries, can't share an output name either, as
+one would overwrite the other.
+
+    [[subprojects]]
+    name = "cli"
+    type = "binary"
+    output_name = "mytool""#
+            }
+            ErrorType::InvalidParallelJobs => {
+                r#"parallel_jobs in [build] or an override is 0, or more than 1024.
+
+With 0 jobs nothing would ever be compiled, so it is an error. Values above
+1024 only spawn threads that wait for each other, so they are clamped to 1024
+with a warning, an error with --deny-warnings. Leave parallel_jobs out to use
+one job per CPU.
+
+    [build]
+    parallel_jobs = 8"#
+            }
+            ErrorType::InvalidDependencySource => {
+                r#"The source of a remote dependency isn't something git can fetch.
+
+A source is a URL with the https, http, ssh, git or file scheme, an scp-like
+address such as git@github.com:user/repo.git, or the path of a local
+repository. Anything else is taken for a path, so a misspelled URL is reported
+as a path that doesn't exist. A local source of a dependency built with
+build_method = "iceforge" must contain an iceforge.toml.
+
+    [[dependencies.remote]]
+    name = "glfw"
+    source = "https://github.com/glfw/glfw.git"
+    include_dirs = ["include"]"#
+            }
+            ErrorType::InvalidWorkspaceMember => {
+                r#"A member of a [workspace] is listed twice or has no iceforge.toml.
+
+Members are directories relative to the workspace root, each holding an
+iceforge project of its own. Check the path for typos and that the member
+was initialized with `iceforge init`.
+
+    [workspace]
+    members = ["engine", "editor"]"#
+            }
+            ErrorType::InvalidToolchain => {
+                r#"The archiver or sysroot of the [toolchain] can't be found.
+
+The ar of the toolchain has to be in PATH and its sysroot has to be an
+existing directory. Cross toolchains are often installed outside of PATH, in
+which case the full path of ar can be given.
+
+    [toolchain]
+    cc = "arm-none-eabi-gcc"
+    ar = "arm-none-eabi-ar"
+    sysroot = "/usr/lib/arm-none-eabi""#
+            }
+            ErrorType::UnknownPreset => {
+                r#"A preset selected with --preset or by a subproject isn't defined.
+
+Presets are named sets of cflags, ldflags and defines under [presets.<name>].
+The name given to --preset or to the preset field of a subproject has to be
+one of them.
+
+    [presets.asan]
+    cflags = "-fsanitize=address -fno-omit-frame-pointer"
+    ldflags = "-fsanitize=address"
+
+    [[subprojects]]
+    name = "app"
+    preset = "asan""#
+            }
+            ErrorType::CleanFailed => {
+                r#"A build output couldn't be removed by clean.
+
+The file or directory isn't writable, or is in use by another program. Run
+clean with --dry-run to list everything it would remove.
+
+    iceforge clean --dry-run"#
+            }
+            ErrorType::InvalidPrecompiledHeader => {
+                r#"The pch of a subproject isn't a header file that exists.
+
+The path is relative to the project root, like src_dir. Headers ending in .hpp,
+.hh or .hxx are precompiled for the C++ sources of the subproject, any other
+header for its C sources.
+
+    [[subprojects]]
+    name = "core"
+    src_dir = "src/core"
+    pch = "src/core/include/common.h""#
+            }
+            ErrorType::UnknownProfile => {
+                r#"A profile selected with --profile or inherited by a profile isn't defined.
+
+Besides the built-in debug and release profiles, profiles are defined under
+[profiles.<name>]. A profile inherits from debug or release only, and its name
+may only contain letters, digits, - and _ as it's built to build/<name>.
+
+    [profiles.minsize]
+    inherits = "release"
+    opt_level = "s"
+    ldflags = "-Wl,--gc-sections"
+
+    iceforge build --profile minsize"#
+            }
+        }
+    }
+}
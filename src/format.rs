@@ -0,0 +1,137 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::error::{Error, ErrorType};
+
+// Order of the top-level sections in a formatted config, anything else follows them
+const SECTION_ORDER: [&str; 8] = [
+    "build",
+    "toolchain",
+    "dependencies",
+    "subprojects",
+    "overrides",
+    "presets",
+    "profiles",
+    "custom_build_rules",
+];
+
+// Position of a table or array of tables in the document, None for plain values. An
+// implicit table such as `dependencies.remote` in `[dependencies.remote.zlib]` has no
+// header of its own and sits where its first nested table does.
+fn position(item: &Item) -> Option<usize> {
+    match item {
+        Item::Table(table) if table.is_implicit() => table
+            .iter()
+            .filter_map(|(_, item)| position(item))
+            .min()
+            .or(table.position()),
+        Item::Table(table) => table.position(),
+        Item::ArrayOfTables(array) => array.iter().next().and_then(Table::position),
+        _ => None,
+    }
+}
+
+// Headers are separated by a blank line, comments above them stay attached
+fn normalize_header(table: &mut Table, first: &mut bool) {
+    if table.is_implicit() {
+        return;
+    }
+    let decor = table.decor_mut();
+    let prefix = decor
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or_default()
+        .trim_start()
+        .to_string();
+    decor.set_prefix(if *first {
+        prefix
+    } else {
+        format!("\n{}", prefix)
+    });
+    *first = false;
+}
+
+fn reposition_table(table: &mut Table, next: &mut usize, first: &mut bool) {
+    table.set_position(*next);
+    *next += 1;
+    normalize_header(table, first);
+
+    // Nested tables keep the order they were written in
+    let mut keys: Vec<(usize, String)> = table
+        .iter()
+        .filter_map(|(key, item)| position(item).map(|position| (position, key.to_string())))
+        .collect();
+    keys.sort();
+    for (_, key) in keys {
+        if let Some(item) = table.get_mut(&key) {
+            reposition(item, next, first);
+        }
+    }
+}
+
+fn reposition(item: &mut Item, next: &mut usize, first: &mut bool) {
+    match item {
+        Item::Table(table) => reposition_table(table, next, first),
+        Item::ArrayOfTables(array) => {
+            for table in array.iter_mut() {
+                reposition_table(table, next, first);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The config with its sections in canonical order, keys within a table and comments
+// are left as written
+pub fn format_config(source: &str) -> Result<String, Error> {
+    let mut doc: DocumentMut = source.parse().map_err(|e: toml_edit::TomlError| Error {
+        error_type: ErrorType::TomlParseError,
+        message: e.message().to_string(),
+        span: e.span(),
+        additional_info: None,
+    })?;
+
+    let root = doc.as_table_mut();
+    let mut keys: Vec<(usize, usize, String)> = root
+        .iter()
+        .filter_map(|(key, item)| {
+            let rank = SECTION_ORDER
+                .iter()
+                .position(|section| *section == key)
+                .unwrap_or(SECTION_ORDER.len());
+            position(item).map(|position| (rank, position, key.to_string()))
+        })
+        .collect();
+    keys.sort();
+
+    // Top-level values are printed before any table
+    let mut first = root.iter().all(|(_, item)| position(item).is_some());
+    let mut next = 1;
+    for (_, _, key) in keys {
+        if let Some(item) = root.get_mut(&key) {
+            reposition(item, &mut next, &mut first);
+        }
+    }
+
+    let mut formatted = doc.to_string().trim_end().to_string();
+    formatted.push('\n');
+    Ok(formatted)
+}
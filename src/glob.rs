@@ -0,0 +1,121 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use glob::{MatchOptions, Pattern, PatternError};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+// `*` and `?` stay within a directory, `**` spans any number of them, including none
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+fn invalid_pattern(e: PatternError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+// Whether a path matches a glob pattern, an invalid pattern matches nothing
+pub fn matches(pattern: &str, path: &Path) -> bool {
+    Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_path_with(path, OPTIONS))
+}
+
+// Files matched by a list of patterns, relative to the working directory unless they are
+// absolute. Patterns starting with `!` remove the files they match from the ones matched
+// so far.
+pub fn expand(patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut selected = BTreeSet::new();
+    for pattern in patterns {
+        if let Some(excluded) = pattern.strip_prefix('!') {
+            let excluded = Pattern::new(excluded).map_err(invalid_pattern)?;
+            selected.retain(|path: &PathBuf| !excluded.matches_path_with(path, OPTIONS));
+            continue;
+        }
+        for path in glob::glob_with(pattern, OPTIONS).map_err(invalid_pattern)? {
+            let path = path.map_err(io::Error::from)?;
+            if path.is_file() {
+                selected.insert(path);
+            }
+        }
+    }
+    Ok(selected.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn single_and_double_stars() {
+        assert!(matches("*.c", Path::new("main.c")));
+        assert!(!matches("*.c", Path::new("src/main.c")));
+        assert!(matches("src/**/*.c", Path::new("src/main.c")));
+        assert!(matches("src/**/*.c", Path::new("src/a/b/main.c")));
+        assert!(matches("**/*_test.c", Path::new("io_test.c")));
+        assert!(matches("**/*_test.c", Path::new("io/io_test.c")));
+        assert!(!matches("**/*_test.c", Path::new("io/io.c")));
+        assert!(matches(
+            "platform/win32/*",
+            Path::new("platform/win32/io.c")
+        ));
+        assert!(!matches(
+            "platform/win32/*",
+            Path::new("platform/win32/sub/io.c")
+        ));
+    }
+
+    #[test]
+    fn absolute_patterns_expand_and_exclude() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "src/main.c",
+            "src/a/util.c",
+            "src/a/util.h",
+            "src/exp/new.c",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let patterns = [
+            format!("{}/src/**/*.c", root.display()),
+            format!("!{}/src/exp/*", root.display()),
+        ];
+        assert_eq!(
+            expand(&patterns).unwrap(),
+            [root.join("src/a/util.c"), root.join("src/main.c")]
+        );
+        // Header installs take everything below an absolute include directory
+        assert_eq!(
+            expand(&[format!("{}/src/a/**/*", root.display())]).unwrap(),
+            [root.join("src/a/util.c"), root.join("src/a/util.h")]
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert!(expand(&["src/[.c".to_string()]).is_err());
+        assert!(!matches("src/[.c", Path::new("src/[.c")));
+    }
+}
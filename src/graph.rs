@@ -0,0 +1,318 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+
+use crate::build_config::{BuildConfig, DependencyRef, SubProject, SubProjectType};
+
+// How `iceforge graph` prints the dependency graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum GraphFormat {
+    #[default]
+    Tree,
+    Dot,
+    Mermaid,
+}
+
+// What a name in the graph refers to, for labels
+fn node_kind(config: &BuildConfig, name: &str) -> &'static str {
+    if let Some(subproject) = config
+        .subprojects
+        .iter()
+        .find(|subproject| subproject.name.get_ref() == name)
+    {
+        return match subproject.r#type {
+            SubProjectType::Binary => "binary",
+            SubProjectType::Library => "library",
+            SubProjectType::HeaderOnly => "header-only",
+            SubProjectType::Test => "test",
+        };
+    }
+    match config.dependencies.iter().find(|dep| dep.name() == name) {
+        Some(DependencyRef::Remote(_)) => "remote",
+        Some(DependencyRef::PkgConfig(_)) => "pkg-config",
+        Some(DependencyRef::Manual(_)) => "manual",
+        None if config.other_platform_dependencies.has_dependency(name) => "other platform",
+        None => "unknown",
+    }
+}
+
+// Every subproject, then the dependencies they name that aren't subprojects, each once
+fn node_names<'a>(
+    config: &'a BuildConfig,
+    map: &'a HashMap<String, Vec<String>>,
+) -> Vec<&'a String> {
+    let mut declared = HashSet::new();
+    config
+        .subprojects
+        .iter()
+        .map(|subproject| subproject.name.get_ref())
+        .chain(
+            config
+                .subprojects
+                .iter()
+                .flat_map(|subproject| map.get(subproject.name.get_ref()).into_iter().flatten()),
+        )
+        .filter(|name| declared.insert(*name))
+        .collect()
+}
+
+// Edges of the dependency cycle, if any, so they can be highlighted
+fn cycle_edges(config: &BuildConfig) -> HashSet<(String, String)> {
+    let Some((_, cycle)) = SubProject::find_dependency_cycle(&config.subprojects) else {
+        return HashSet::new();
+    };
+    cycle
+        .windows(2)
+        .map(|edge| (edge[0].clone(), edge[1].clone()))
+        .collect()
+}
+
+fn tree_node(
+    config: &BuildConfig,
+    map: &HashMap<String, Vec<String>>,
+    name: &str,
+    prefix: &str,
+    path: &mut Vec<String>,
+    expanded: &mut HashSet<String>,
+    out: &mut String,
+) {
+    let deps = map.get(name).map(Vec::as_slice).unwrap_or_default();
+    for (index, dep) in deps.iter().enumerate() {
+        let last = index + 1 == deps.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let marker = if path.contains(dep) {
+            " (cycle)"
+        } else if expanded.contains(dep) && map.get(dep).is_some_and(|deps| !deps.is_empty()) {
+            // Already printed with its dependencies above
+            " (*)"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "{}{}{} ({}){}\n",
+            prefix,
+            branch,
+            dep,
+            node_kind(config, dep),
+            marker
+        ));
+        if marker.is_empty() {
+            expanded.insert(dep.clone());
+            path.push(dep.clone());
+            let prefix = format!("{}{}", prefix, indent);
+            tree_node(config, map, dep, &prefix, path, expanded, out);
+            path.pop();
+        }
+    }
+}
+
+// A tree per subproject no other subproject depends on. Subprojects only reachable
+// through a cycle get a tree of their own too.
+pub fn tree(config: &BuildConfig) -> String {
+    let map = SubProject::dependency_map(&config.subprojects);
+    let depended_on: HashSet<&String> = map.values().flatten().collect();
+    let mut expanded = HashSet::new();
+    let mut out = String::new();
+    let roots = config
+        .subprojects
+        .iter()
+        .filter(|subproject| !depended_on.contains(subproject.name.get_ref()))
+        .chain(&config.subprojects);
+    for subproject in roots {
+        let name = subproject.name.get_ref();
+        if expanded.contains(name) {
+            continue;
+        }
+        expanded.insert(name.clone());
+        out.push_str(&format!("{} ({})\n", name, node_kind(config, name)));
+        let mut path = vec![name.clone()];
+        tree_node(config, &map, name, "", &mut path, &mut expanded, &mut out);
+    }
+    out
+}
+
+// A quoted DOT identifier
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Graphviz description of the graph, for `dot -Tpng`. Binaries are boxes, libraries
+// rounded boxes, external dependencies dashed grey ellipses and the edges of a
+// dependency cycle are red.
+pub fn dot(config: &BuildConfig) -> String {
+    let map = SubProject::dependency_map(&config.subprojects);
+    let cycle = cycle_edges(config);
+    let mut out = String::from("digraph iceforge {\n");
+    for name in node_names(config, &map) {
+        let kind = node_kind(config, name);
+        let style = match kind {
+            "binary" | "test" => "shape=box",
+            "library" | "header-only" => "shape=box, style=rounded",
+            _ => "shape=ellipse, style=\"dashed,filled\", fillcolor=lightgrey",
+        };
+        out.push_str(&format!(
+            "    {} [label={}, {}];\n",
+            dot_id(name),
+            dot_id(&format!("{} ({})", name, kind)),
+            style
+        ));
+    }
+    for subproject in &config.subprojects {
+        let name = subproject.name.get_ref();
+        for dep in map.get(name).into_iter().flatten() {
+            let color = if cycle.contains(&(name.clone(), dep.clone())) {
+                " [color=red]"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "    {} -> {}{};\n",
+                dot_id(name),
+                dot_id(dep),
+                color
+            ));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// A Mermaid node label, quotes can't be escaped with a backslash
+fn mermaid_label(name: &str, kind: &str) -> String {
+    format!("\"{} ({})\"", name.replace('"', "#quot;"), kind)
+}
+
+// Mermaid flowchart of the graph, for pasting into markdown. Nodes are numbered as names
+// may contain anything. Binaries are rectangles, libraries rounded, external dependencies
+// dashed grey stadiums and the edges of a dependency cycle are red.
+pub fn mermaid(config: &BuildConfig) -> String {
+    let map = SubProject::dependency_map(&config.subprojects);
+    let cycle = cycle_edges(config);
+    let names = node_names(config, &map);
+    let ids: HashMap<&String, String> = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (*name, format!("n{}", index)))
+        .collect();
+    let mut out = String::from("graph TD\n");
+    let mut leaves = Vec::new();
+    for name in &names {
+        let kind = node_kind(config, name);
+        let label = mermaid_label(name, kind);
+        let id = &ids[name];
+        let node = match kind {
+            "binary" | "test" => format!("{}[{}]", id, label),
+            "library" | "header-only" => format!("{}({})", id, label),
+            _ => {
+                leaves.push(id.as_str());
+                format!("{}([{}])", id, label)
+            }
+        };
+        out.push_str(&format!("    {}\n", node));
+    }
+    let mut cycle_links = Vec::new();
+    let edges = config.subprojects.iter().flat_map(|subproject| {
+        let name = subproject.name.get_ref();
+        map.get(name)
+            .into_iter()
+            .flatten()
+            .map(move |dep| (name, dep))
+    });
+    for (index, (name, dep)) in edges.enumerate() {
+        if cycle.contains(&(name.clone(), dep.clone())) {
+            cycle_links.push(index.to_string());
+        }
+        out.push_str(&format!("    {} --> {}\n", ids[name], ids[dep]));
+    }
+    if !leaves.is_empty() {
+        out.push_str("    classDef external fill:#eee,stroke-dasharray:5 5\n");
+        out.push_str(&format!("    class {} external\n", leaves.join(",")));
+    }
+    if !cycle_links.is_empty() {
+        out.push_str(&format!(
+            "    linkStyle {} stroke:red\n",
+            cycle_links.join(",")
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BuildConfig {
+        BuildConfig::from_source(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[dependencies]
+manual = [{ name = "m", ldflags = "-lm" }]
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "src/app"
+dependencies = ["core"]
+
+[[subprojects]]
+name = "core"
+type = "library"
+src_dir = "src/core"
+dependencies = ["m"]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn mermaid_styles_subprojects_and_leaves_apart() {
+        assert_eq!(
+            mermaid(&config()),
+            r#"graph TD
+    n0["app (binary)"]
+    n1("core (library)")
+    n2(["m (manual)"])
+    n0 --> n1
+    n1 --> n2
+    classDef external fill:#eee,stroke-dasharray:5 5
+    class n2 external
+"#
+        );
+    }
+
+    #[test]
+    fn dot_styles_subprojects_and_leaves_apart() {
+        let dot = dot(&config());
+        assert!(dot.contains("\"app\" [label=\"app (binary)\", shape=box];"));
+        assert!(dot.contains("\"core\" [label=\"core (library)\", shape=box, style=rounded];"));
+        assert!(dot.contains(
+            "\"m\" [label=\"m (manual)\", shape=ellipse, style=\"dashed,filled\", fillcolor=lightgrey];"
+        ));
+        assert!(dot.contains("\"core\" -> \"m\";"));
+    }
+}
@@ -0,0 +1,181 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::path::Path;
+
+use crate::{
+    build::CompileEntry,
+    build_config::{find_in_path, BuildConfig},
+    error::json_string,
+};
+
+pub const COMPILE_COMMANDS_FILE: &str = "compile_commands.json";
+pub const VSCODE_CONFIG_FILE: &str = ".vscode/c_cpp_properties.json";
+
+fn json_list(items: &[String], indent: &str) -> String {
+    if items.is_empty() {
+        return "[]".to_string();
+    }
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| format!("{}  {}", indent, json_string(item)))
+        .collect();
+    format!("[\n{}\n{}]", items.join(",\n"), indent)
+}
+
+// Compilation database read by clangd and most other C tooling, commands run from directory
+pub fn compile_commands_json(entries: &[CompileEntry], directory: &Path) -> String {
+    let directory = directory.to_string_lossy();
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "  {{\n    \"directory\": {},\n    \"file\": {},\n    \"output\": {},\n    \"arguments\": {}\n  }}",
+                json_string(&directory),
+                json_string(&entry.source.to_string_lossy()),
+                json_string(&entry.object.to_string_lossy()),
+                json_list(&entry.arguments, "    ")
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+// Settings for the VS Code C/C++ extension. Include paths and defines are those of the
+// compile commands, relative include paths resolved against the workspace folder.
+pub fn vscode_config_json(
+    config: &BuildConfig,
+    entries: &[CompileEntry],
+    compile_commands: bool,
+) -> String {
+    let mut include_paths: Vec<String> = Vec::new();
+    for dir in entries
+        .iter()
+        .flat_map(|entry| &entry.arguments)
+        .filter_map(|arg| arg.strip_prefix("-I"))
+    {
+        let dir = if Path::new(dir).is_absolute() {
+            dir.to_string()
+        } else {
+            format!("${{workspaceFolder}}/{}", dir)
+        };
+        if !include_paths.contains(&dir) {
+            include_paths.push(dir);
+        }
+    }
+    let mut defines: Vec<String> = Vec::new();
+    for define in entries
+        .iter()
+        .flat_map(|entry| &entry.arguments)
+        .filter_map(|arg| arg.strip_prefix("-D"))
+    {
+        if !defines.iter().any(|known| known == define) {
+            defines.push(define.to_string());
+        }
+    }
+    let compiler = config.build.compiler.get_ref();
+    let compiler_path =
+        find_in_path(compiler).map_or(compiler.clone(), |path| path.display().to_string());
+
+    let mut fields = vec![
+        format!("      \"name\": {}", json_string("iceforge")),
+        format!(
+            "      \"includePath\": {}",
+            json_list(&include_paths, "      ")
+        ),
+        format!("      \"defines\": {}", json_list(&defines, "      ")),
+        format!("      \"compilerPath\": {}", json_string(&compiler_path)),
+        format!(
+            "      \"cStandard\": {}",
+            json_string(config.build.c_standard.get_ref())
+        ),
+    ];
+    if let Some(standard) = &config.build.cpp_standard {
+        fields.push(format!(
+            "      \"cppStandard\": {}",
+            json_string(standard.get_ref())
+        ));
+    }
+    if compile_commands {
+        fields.push(format!(
+            "      \"compileCommands\": {}",
+            json_string(&format!("${{workspaceFolder}}/{}", COMPILE_COMMANDS_FILE))
+        ));
+    }
+    format!(
+        "{{\n  \"configurations\": [\n    {{\n{}\n    }}\n  ],\n  \"version\": 4\n}}\n",
+        fields.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(source: &str, arguments: &[&str]) -> CompileEntry {
+        CompileEntry {
+            source: PathBuf::from(source),
+            object: PathBuf::from(source).with_extension("o"),
+            arguments: arguments.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn compile_commands_list_every_entry() {
+        let entries = [
+            entry("src/a.c", &["gcc", "-DNAME=\"a\"", "-c", "src/a.c"]),
+            entry("src/b.c", &["gcc", "-c", "src/b.c"]),
+        ];
+        let json = compile_commands_json(&entries, Path::new("/project"));
+        assert_eq!(json.matches("\"directory\": \"/project\"").count(), 2);
+        assert!(json.contains("\"file\": \"src/a.c\""));
+        assert!(json.contains("\"output\": \"src/b.o\""));
+        assert!(json.contains("\"-DNAME=\\\"a\\\"\""));
+        assert_eq!(
+            compile_commands_json(&[], Path::new("/project")),
+            "[\n\n]\n"
+        );
+    }
+
+    #[test]
+    fn vscode_include_paths_come_from_the_compile_commands() {
+        let config = BuildConfig::from_source(
+            "subprojects = []\n[build]\nversion = \"0.1.0\"\nc_standard = \"c17\"\ncompiler = \"cc\"\n",
+        );
+        let config = config.unwrap();
+        let entries = [
+            entry(
+                "a.c",
+                &["cc", "-Iinclude", "-I/usr/include/foo", "-c", "a.c"],
+            ),
+            entry("b.c", &["cc", "-Iinclude", "-DFOO", "-DBAR=1", "-c", "b.c"]),
+            entry("c.c", &["cc", "-DFOO", "-c", "c.c"]),
+        ];
+        let json = vscode_config_json(&config, &entries, false);
+        assert!(json.contains(
+            "\"includePath\": [\n        \"${workspaceFolder}/include\",\n        \"/usr/include/foo\"\n      ]"
+        ));
+        assert!(json.contains("\"defines\": [\n        \"FOO\",\n        \"BAR=1\"\n      ]"));
+        assert!(json.contains("\"cStandard\": \"c17\""));
+        assert!(!json.contains("compileCommands"));
+        assert!(!json.contains("cppStandard"));
+        assert!(vscode_config_json(&config, &entries, true)
+            .contains("\"compileCommands\": \"${workspaceFolder}/compile_commands.json\""));
+    }
+}
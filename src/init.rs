@@ -0,0 +1,214 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use clap::ValueEnum;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    build_config::{find_in_path, CONFIG_FILE},
+    error::{Error, ErrorType},
+    logi, logw,
+};
+
+// Kind of project created by `iceforge init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Template {
+    #[default]
+    Binary,
+    Library,
+}
+
+fn init_error(message: String) -> Error {
+    Error {
+        error_type: ErrorType::InitFailed,
+        message,
+        span: None,
+        additional_info: None,
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| init_error(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    fs::write(path, contents)
+        .map_err(|e| init_error(format!("Failed to write {}: {}", path.display(), e)))?;
+    logi!("Created {}", path.display());
+    Ok(())
+}
+
+// First C compiler found on the PATH, falling back to cc
+fn detect_compiler() -> &'static str {
+    ["cc", "gcc", "clang"]
+        .into_iter()
+        .find(|compiler| find_in_path(compiler).is_some())
+        .unwrap_or("cc")
+}
+
+fn config_contents(name: &str, template: Template) -> String {
+    let subproject = match template {
+        Template::Binary => format!(
+            r#"[[subprojects]]
+name = "{name}"
+type = "binary"
+src_dir = "src"
+"#
+        ),
+        Template::Library => format!(
+            r#"[[subprojects]]
+name = "{name}"
+type = "library"
+src_dir = "src"
+include_dirs = ["include"]
+"#
+        ),
+    };
+    format!(
+        r#"[build]
+version = "0.1.0"
+c_standard = "c17"
+compiler = "{}"
+global_cflags = "-Wall -Wextra"
+debug_flags = "-g"
+release_flags = "-O2"
+
+{}"#,
+        detect_compiler(),
+        subproject
+    )
+}
+
+// Create a new project in dir, refusing to overwrite an existing config unless forced.
+// Returns the files that were written.
+pub fn init_project(
+    dir: &Path,
+    name: &str,
+    template: Template,
+    force: bool,
+    ignore_lockfile: bool,
+) -> Result<Vec<PathBuf>, Error> {
+    let config_path = dir.join(CONFIG_FILE);
+    if config_path.exists() && !force {
+        return Err(init_error(format!(
+            "{} already exists, use --force to overwrite it",
+            config_path.display()
+        )));
+    }
+
+    write_file(&config_path, &config_contents(name, template))?;
+    let mut written = vec![config_path];
+
+    // Never clobber sources the user already has
+    let ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let files = match template {
+        Template::Binary => vec![(
+            dir.join("src").join("main.c"),
+            "#include <stdio.h>\n\nint main(void) {\n\tprintf(\"Hello, world!\\n\");\n\treturn 0;\n}\n"
+                .to_string(),
+        )],
+        Template::Library => vec![
+            (
+                dir.join("include").join(format!("{}.h", name)),
+                format!("#pragma once\n\nint {}_answer(void);\n", ident),
+            ),
+            (
+                dir.join("src").join(format!("{}.c", name)),
+                format!(
+                    "#include \"{}.h\"\n\nint {}_answer(void) {{\n\treturn 42;\n}}\n",
+                    name, ident
+                ),
+            ),
+        ],
+    };
+    let mut gitignore = "build/\n".to_string();
+    if ignore_lockfile {
+        gitignore.push_str("iceforge.lock\n");
+    }
+    for (path, contents) in files
+        .into_iter()
+        .chain([(dir.join(".gitignore"), gitignore)])
+    {
+        if !path.exists() {
+            write_file(&path, &contents)?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| init_error(format!("Failed to run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(init_error(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+// Make dir a git repository whose first commit holds the files init wrote. A missing
+// git only warns, the project is usable without it.
+pub fn init_git(dir: &Path, files: &[PathBuf]) -> Result<(), Error> {
+    if find_in_path("git").is_none() {
+        logw!("git not found in PATH, not initializing a repository");
+        return Ok(());
+    }
+    // Committing into an enclosing repository would be a surprise
+    if git(dir, &["rev-parse", "--is-inside-work-tree"]).is_ok() {
+        logw!(
+            "{} is already in a git repository, not initializing one",
+            dir.display()
+        );
+        return Ok(());
+    }
+    git(dir, &["init", "--quiet"])?;
+    let relative: Vec<String> = files
+        .iter()
+        .map(|file| {
+            file.strip_prefix(dir)
+                .unwrap_or(file)
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    let mut add = vec!["add", "--"];
+    add.extend(relative.iter().map(String::as_str));
+    git(dir, &add)?;
+    git(
+        dir,
+        &["commit", "--quiet", "--message", "Initial iceforge project"],
+    )?;
+    logi!("Initialized a git repository with an initial commit");
+    Ok(())
+}
@@ -17,9 +17,21 @@
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use clap::ValueEnum;
 use colored::Colorize;
+use std::{
+    fs::File,
+    io::{IsTerminal, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[repr(u8)]
 pub enum LogLevel {
     Debug,
     Verbose,
@@ -28,13 +40,155 @@ pub enum LogLevel {
     Error,
 }
 
+// Messages below this level are dropped
+static LOG_THRESHOLD: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+static WARNINGS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_warnings_enabled(enabled: bool) {
+    WARNINGS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_THRESHOLD.store(level as u8, Ordering::Relaxed);
+}
+
+// Map the number of -v flags to a threshold, -v shows verbose and -vv debug messages
+pub fn set_verbosity(verbosity: u8) {
+    set_log_level(match verbosity {
+        0 => LogLevel::Info,
+        1 => LogLevel::Verbose,
+        _ => LogLevel::Debug,
+    });
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, ValueEnum)]
+#[repr(u8)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static COLOR_MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+
+pub fn set_color_mode(mode: ColorMode) {
+    COLOR_MODE.store(mode as u8, Ordering::Relaxed);
+    match mode {
+        ColorMode::Always => colored::control::set_override(true),
+        ColorMode::Never => colored::control::set_override(false),
+        ColorMode::Auto => colored::control::unset_override(),
+    }
+}
+
+// Whether output to a stream should be colored. In auto mode colors are only used
+// on terminals and never when NO_COLOR or CRYO_NO_COLOR is set.
+pub fn use_color(is_terminal: bool) -> bool {
+    match COLOR_MODE.load(Ordering::Relaxed) {
+        m if m == ColorMode::Always as u8 => true,
+        m if m == ColorMode::Never as u8 => false,
+        _ => {
+            is_terminal
+                && ["NO_COLOR", "CRYO_NO_COLOR"]
+                    .iter()
+                    .all(|var| std::env::var_os(var).is_none_or(|value| value.is_empty()))
+        }
+    }
+}
+
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+// Every logged line is also written here, without colors
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+pub fn set_timestamps(enabled: bool) {
+    TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn set_log_file(path: &Path) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+// Current UTC time in ISO-8601 format, e.g. 2024-10-05T13:37:00Z
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+// Whether messages of a level are shown at all
+pub fn is_enabled(level: LogLevel) -> bool {
+    (level as u8) >= LOG_THRESHOLD.load(Ordering::Relaxed)
+        && (level != LogLevel::Warning || WARNINGS_ENABLED.load(Ordering::Relaxed))
+}
+
 pub fn log(level: LogLevel, msg: &str) {
-    match level {
-        LogLevel::Debug => println!("{} {}", "DEBUG: ".blue(), msg),
-        LogLevel::Verbose => println!("{} {}", "VERBOSE: ".cyan(), msg),
-        LogLevel::Info => println!("{} {}", "INFO: ".green(), msg),
-        LogLevel::Warning => eprintln!("{} {}", "WARNING: ".yellow(), msg),
-        LogLevel::Error => eprintln!("{} {}", "ERROR: ".red(), msg),
+    if !is_enabled(level) {
+        return;
+    }
+    let to_stderr = level >= LogLevel::Warning;
+    let color = if to_stderr {
+        use_color(std::io::stderr().is_terminal())
+    } else {
+        use_color(std::io::stdout().is_terminal())
+    };
+    let label = match level {
+        LogLevel::Debug => "DEBUG: ",
+        LogLevel::Verbose => "VERBOSE: ",
+        LogLevel::Info => "INFO: ",
+        LogLevel::Warning => "WARNING: ",
+        LogLevel::Error => "ERROR: ",
+    };
+    let prefix = if TIMESTAMPS.load(Ordering::Relaxed) {
+        format!("{} ", timestamp())
+    } else {
+        String::new()
+    };
+    if let Some(file) = LOG_FILE.get() {
+        let mut file = file.lock().unwrap();
+        let _ = writeln!(file, "{}{} {}", prefix, label, msg);
+    }
+
+    let label = if color {
+        match level {
+            LogLevel::Debug => label.blue(),
+            LogLevel::Verbose => label.cyan(),
+            LogLevel::Info => label.green(),
+            LogLevel::Warning => label.yellow(),
+            LogLevel::Error => label.red(),
+        }
+    } else {
+        label.normal()
+    };
+    if to_stderr {
+        eprintln!("{}{} {}", prefix, label, msg);
+    } else {
+        println!("{}{} {}", prefix, label, msg);
     }
 }
 
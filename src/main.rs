@@ -17,25 +17,26 @@
 * along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+pub mod build;
 pub mod build_config;
+pub mod clean;
 pub mod cli;
+pub mod edit;
 pub mod error;
+pub mod explain;
+pub mod format;
+pub mod glob;
+pub mod graph;
+pub mod ide;
+pub mod init;
 pub mod logger;
 pub mod package;
+pub mod util;
+pub mod version;
+pub mod watch;
+pub mod workspace;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config_path = "sample.toml";
-    let mut config = match build_config::BuildConfig::load_config(config_path) {
-        Ok(config) => config,
-        Err(e) => {
-            e.emit_config_error(config_path);
-            std::process::exit(1);
-        }
-    };
-    if let Err(e) = config.verify_config() {
-        e.emit_config_error(config_path);
-        std::process::exit(1);
-    }
     cli::parse();
     Ok(())
 }
@@ -0,0 +1,816 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    build::{artifact_path, command_line, job_count, shared_library_path, BuildMode},
+    build_config::{BuildConfig, RemoteDependency, SubProjectType},
+    error::{Error, ErrorType},
+    glob, logi, logv, logw, util,
+    version::{best_match, parse_requirement},
+};
+
+// Directory where remote dependencies are checked out
+pub const DEPS_DIR: &str = "deps";
+
+// File recording the concrete versions remote dependencies resolved to
+pub const LOCK_FILE: &str = "iceforge.lock";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub dependencies: Vec<LockedDependency>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LockedDependency {
+    pub name: String,
+    pub source: String,
+    // Version requirement from the config that produced this entry
+    pub requirement: Option<String>,
+    // Tag or ref that was checked out, None for the default branch
+    pub version: Option<String>,
+    // Commit the ref pointed to, builds check this commit out
+    pub commit: Option<String>,
+}
+
+// Record of every file installed by iceforge, used to uninstall packages later
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct InstallManifest {
+    #[serde(default)]
+    pub packages: Vec<InstalledPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    pub files: Vec<PathBuf>,
+}
+
+fn install_error(message: String) -> Error {
+    Error {
+        error_type: ErrorType::InstallFailed,
+        message,
+        span: None,
+        additional_info: None,
+    }
+}
+
+fn home_dir() -> Result<PathBuf, Error> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| install_error("HOME is not set".to_string()))
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+// Default install prefix, /usr/local for root and ~/.local for everyone else
+pub fn default_prefix() -> Result<PathBuf, Error> {
+    if is_root() {
+        Ok(PathBuf::from("/usr/local"))
+    } else {
+        Ok(home_dir()?.join(".local"))
+    }
+}
+
+// Where files for prefix are written, DESTDIR stages an install for packaging
+fn staged_prefix(prefix: &Path) -> PathBuf {
+    match std::env::var_os("DESTDIR").filter(|destdir| !destdir.is_empty()) {
+        Some(destdir) => PathBuf::from(destdir).join(prefix.strip_prefix("/").unwrap_or(prefix)),
+        None => prefix.to_path_buf(),
+    }
+}
+
+impl InstallManifest {
+    pub fn path() -> Result<PathBuf, Error> {
+        Ok(home_dir()?
+            .join(".local")
+            .join("share")
+            .join("iceforge")
+            .join("installed.toml"))
+    }
+
+    pub fn load() -> Result<Self, Error> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| install_error(format!("Failed to read {}: {}", path.display(), e)))?;
+        toml::from_str(&content)
+            .map_err(|e| install_error(format!("Failed to parse {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                install_error(format!("Failed to create {}: {}", parent.display(), e))
+            })?;
+        }
+        let content = toml::to_string(self)
+            .map_err(|e| install_error(format!("Failed to serialize install manifest: {}", e)))?;
+        util::write_atomic(&path, content)
+            .map_err(|e| install_error(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    // Replace any previous record of the package so reinstalls don't accumulate entries
+    pub fn record(&mut self, package: InstalledPackage) {
+        self.packages
+            .retain(|installed| installed.name != package.name);
+        self.packages.push(package);
+    }
+}
+
+fn lockfile_error(message: String) -> Error {
+    Error {
+        error_type: ErrorType::LockfileError,
+        message,
+        span: None,
+        additional_info: None,
+    }
+}
+
+impl Lockfile {
+    pub fn load() -> Result<Self, Error> {
+        if !Path::new(LOCK_FILE).exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(LOCK_FILE)
+            .map_err(|e| lockfile_error(format!("Failed to read {}: {}", LOCK_FILE, e)))?;
+        toml::from_str(&content)
+            .map_err(|e| lockfile_error(format!("Failed to parse {}: {}", LOCK_FILE, e)))
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let content = toml::to_string(self)
+            .map_err(|e| lockfile_error(format!("Failed to serialize lockfile: {}", e)))?;
+        util::write_atomic(Path::new(LOCK_FILE), content)
+            .map_err(|e| lockfile_error(format!("Failed to write {}: {}", LOCK_FILE, e)))
+    }
+
+    pub fn record(&mut self, dependency: LockedDependency) {
+        self.dependencies
+            .retain(|locked| locked.name != dependency.name);
+        self.dependencies.push(dependency);
+    }
+
+    pub fn resolved_commit(&self, name: &str) -> Option<&str> {
+        self.dependencies
+            .iter()
+            .find(|locked| locked.name == name)
+            .and_then(|locked| locked.commit.as_deref())
+    }
+}
+
+// Check that the source of a remote dependency can be reached, without fetching it
+pub fn check_remote_reachable(remote: &RemoteDependency) -> Result<(), Error> {
+    let source = &remote.source;
+    let status = Command::new("git")
+        .arg("ls-remote")
+        .arg("--exit-code")
+        .arg(source.get_ref())
+        .arg("HEAD")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if status.is_ok_and(|status| status.success()) {
+        return Ok(());
+    }
+    Err(Error {
+        error_type: ErrorType::FetchFailed,
+        message: format!(
+            "Source of dependency {} is not reachable",
+            remote.name.get_ref()
+        ),
+        span: Some(source.span()),
+        additional_info: None,
+    })
+}
+
+// Tags published by a remote git repository
+pub fn list_remote_tags(source: &str) -> Result<Vec<String>, Error> {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--tags")
+        .arg("--refs")
+        .arg(source)
+        .output()
+        .map_err(|e| Error {
+            error_type: ErrorType::FetchFailed,
+            message: format!("Failed to run git: {}", e),
+            span: None,
+            additional_info: None,
+        })?;
+    if !output.status.success() {
+        return Err(Error {
+            error_type: ErrorType::FetchFailed,
+            message: format!("Failed to list tags of {}", source),
+            span: None,
+            additional_info: None,
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once("refs/tags/"))
+        .map(|(_, tag)| tag.to_string())
+        .collect())
+}
+
+fn offline_error(name: &str, dest: &Path) -> Error {
+    Error {
+        error_type: ErrorType::FetchFailed,
+        message: format!(
+            "{} is not checked out in {} and --offline forbids fetching it",
+            name,
+            dest.display()
+        ),
+        span: None,
+        additional_info: None,
+    }
+}
+
+// Resolve the configured version of a remote dependency to the ref to check out.
+// Version requirements pick the highest matching tag, anything else is used as a git ref.
+// Offline, only the tags of the commit already checked out in deps/ are considered.
+pub fn resolve_version(remote: &RemoteDependency, offline: bool) -> Result<Option<String>, Error> {
+    let Some(version) = &remote.version else {
+        return Ok(None);
+    };
+    let Some(requirement) = parse_requirement(version.get_ref()) else {
+        return Ok(Some(version.get_ref().clone()));
+    };
+    let tags = if offline {
+        let dest = Path::new(DEPS_DIR).join(remote.name.get_ref());
+        if !dest.exists() {
+            return Err(offline_error(remote.name.get_ref(), &dest));
+        }
+        run_git(
+            Command::new("git")
+                .arg("-C")
+                .arg(&dest)
+                .arg("tag")
+                .arg("--points-at")
+                .arg("HEAD"),
+        )?
+        .lines()
+        .map(str::to_string)
+        .collect()
+    } else {
+        list_remote_tags(remote.source.get_ref())?
+    };
+    match best_match(&requirement, tags.iter().map(String::as_str)) {
+        Some(tag) => Ok(Some(tag.to_string())),
+        None => Err(Error {
+            error_type: ErrorType::UnresolvableVersion,
+            message: match offline {
+                true => format!(
+                    "The checkout of {} in {} does not satisfy version {}",
+                    remote.name.get_ref(),
+                    DEPS_DIR,
+                    version.get_ref()
+                ),
+                false => format!(
+                    "No tag of {} satisfies version {}",
+                    remote.source.get_ref(),
+                    version.get_ref()
+                ),
+            },
+            span: Some(version.span()),
+            additional_info: None,
+        }),
+    }
+}
+
+fn replace_error(dest: &Path, e: std::io::Error) -> Error {
+    Error {
+        error_type: ErrorType::FetchFailed,
+        message: format!("Failed to replace {}: {}", dest.display(), e),
+        span: None,
+        additional_info: None,
+    }
+}
+
+// Fetch a remote repository next to dest and move it over dest once the fetch succeeded,
+// so a failed fetch leaves the previous checkout in place
+fn replace_checkout(source: &str, version: Option<&str>, dest: &Path) -> Result<(), Error> {
+    let parent = dest.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent).map_err(|e| replace_error(dest, e))?;
+    let staging = tempfile::Builder::new()
+        .prefix(".fetch-")
+        .tempdir_in(parent)
+        .map_err(|e| replace_error(dest, e))?;
+    let checkout = staging.path().join("checkout");
+    fetch_remote(source, version, &checkout)?;
+    // The previous checkout is removed with the staging directory
+    let previous = staging.path().join("previous");
+    if dest.exists() {
+        fs::rename(dest, &previous).map_err(|e| replace_error(dest, e))?;
+    }
+    if let Err(e) = fs::rename(&checkout, dest) {
+        let _ = fs::rename(&previous, dest);
+        return Err(replace_error(dest, e));
+    }
+    Ok(())
+}
+
+// Fetch a remote dependency at its resolved version, or lock its checkout offline
+fn refresh_dependency(remote: &RemoteDependency, offline: bool) -> Result<LockedDependency, Error> {
+    let name = remote.name.get_ref();
+    let version = resolve_version(remote, offline)?;
+    let dest = Path::new(DEPS_DIR).join(name);
+    if offline {
+        if !dest.exists() {
+            return Err(offline_error(name, &dest));
+        }
+    } else {
+        replace_checkout(remote.source.get_ref(), version.as_deref(), &dest)?;
+    }
+    let commit = head_commit(&dest)?;
+    // A single message, other dependencies are reported concurrently
+    logi!(
+        "{} {} from {}\n    resolved to {} at {}",
+        if offline { "Locked" } else { "Fetched" },
+        name,
+        remote.source.get_ref(),
+        version.as_deref().unwrap_or("the default branch"),
+        commit
+    );
+    Ok(LockedDependency {
+        name: name.clone(),
+        source: remote.source.get_ref().clone(),
+        requirement: remote
+            .version
+            .as_ref()
+            .map(|version| version.get_ref().clone()),
+        version,
+        commit: Some(commit),
+    })
+}
+
+// Fetch every remote dependency at its resolved version and record the result in the lockfile.
+// Offline, the existing checkouts in deps/ are locked as they are. Each dependency has its
+// own checkout and the sub-dependencies of iceforge projects are fetched by their own
+// builds, so remote dependencies never wait on each other and are fetched by a pool of
+// workers sized like the build's. git's output is captured and each dependency is reported
+// in one message once it is done, so concurrent fetches don't interleave.
+pub fn refresh_dependencies(config: &BuildConfig, offline: bool) -> Result<(), Error> {
+    let mut lockfile = Lockfile::load()?;
+    let remotes: Vec<&RemoteDependency> = config
+        .dependencies
+        .remote
+        .iter()
+        .map(|remote| remote.get_ref())
+        .collect();
+    let workers = job_count(config, None).clamp(1, remotes.len().max(1));
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(vec![None; remotes.len()]);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= remotes.len() || failed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let result = refresh_dependency(remotes[index], offline);
+                failed.fetch_or(result.is_err(), Ordering::SeqCst);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    // Record in config order so the lockfile doesn't depend on which fetch finished first
+    for result in results.into_inner().unwrap().into_iter().flatten() {
+        lockfile.record(result?);
+    }
+    lockfile.save()
+}
+
+// Check out the locked commit of every remote dependency, refetching checkouts that are
+// missing or at another commit. Dependencies missing from the lockfile are left alone.
+// Offline, the locked commit can only be checked out from an existing checkout.
+pub fn sync_dependencies(config: &BuildConfig, offline: bool, dry_run: bool) -> Result<(), Error> {
+    let lockfile = Lockfile::load()?;
+    for remote in &config.dependencies.remote {
+        let remote = remote.get_ref();
+        let name = remote.name.get_ref();
+        let Some(commit) = lockfile.resolved_commit(name) else {
+            logw!(
+                "{} is not locked in {}, run `iceforge refresh` to lock it",
+                name,
+                LOCK_FILE
+            );
+            continue;
+        };
+        let dest = Path::new(DEPS_DIR).join(name);
+        if offline {
+            if !dest.exists() {
+                return Err(offline_error(name, &dest));
+            }
+            if head_commit(&dest).is_ok_and(|head| head == commit) {
+                continue;
+            }
+            run_git_unless_dry(
+                Command::new("git")
+                    .arg("-C")
+                    .arg(&dest)
+                    .arg("checkout")
+                    .arg("--quiet")
+                    .arg(commit),
+                dry_run,
+            )?;
+            continue;
+        }
+        if dest.exists() {
+            if head_commit(&dest).is_ok_and(|head| head == commit) {
+                continue;
+            }
+            if dry_run {
+                println!("{}", command_line(Command::new("rm").arg("-rf").arg(&dest)));
+            } else {
+                fs::remove_dir_all(&dest).map_err(|e| Error {
+                    error_type: ErrorType::FetchFailed,
+                    message: format!("Failed to remove {}: {}", dest.display(), e),
+                    span: None,
+                    additional_info: None,
+                })?;
+            }
+        }
+        fetch_commit(remote.source.get_ref(), commit, &dest, dry_run)?;
+    }
+    Ok(())
+}
+
+// Run git, or only print the command in a dry run
+fn run_git_unless_dry(cmd: &mut Command, dry_run: bool) -> Result<String, Error> {
+    if dry_run {
+        println!("{}", command_line(cmd));
+        return Ok(String::new());
+    }
+    run_git(cmd)
+}
+
+fn run_git(cmd: &mut Command) -> Result<String, Error> {
+    match cmd.output() {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(output) => Err(Error {
+            error_type: ErrorType::FetchFailed,
+            message: format!(
+                "git failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            span: None,
+            additional_info: None,
+        }),
+        Err(e) => Err(Error {
+            error_type: ErrorType::FetchFailed,
+            message: format!("Failed to run git: {}", e),
+            span: None,
+            additional_info: None,
+        }),
+    }
+}
+
+// Commit checked out in a repository
+fn head_commit(repo: &Path) -> Result<String, Error> {
+    run_git(
+        Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .arg("rev-parse")
+            .arg("HEAD"),
+    )
+}
+
+// Clone a remote git repository and check out an exact commit
+fn fetch_commit(source: &str, commit: &str, dest: &Path, dry_run: bool) -> Result<(), Error> {
+    logi!("Fetching {} at {}", source, commit);
+    run_git_unless_dry(
+        Command::new("git")
+            .arg("clone")
+            .arg("--quiet")
+            .arg("--no-checkout")
+            .arg(source)
+            .arg(dest),
+        dry_run,
+    )?;
+    run_git_unless_dry(
+        Command::new("git")
+            .arg("-C")
+            .arg(dest)
+            .arg("checkout")
+            .arg("--quiet")
+            .arg(commit),
+        dry_run,
+    )?;
+    Ok(())
+}
+
+// Clone a remote git repository, checking out the given ref if any. The ref is fetched by
+// name, so commit hashes work like branches and tags. Abbreviated hashes can't be fetched
+// by name and are looked up in a full fetch instead. git's output is captured, a failure
+// reports it in the error.
+pub fn fetch_remote(source: &str, version: Option<&str>, dest: &Path) -> Result<(), Error> {
+    let Some(version) = version else {
+        run_git(
+            Command::new("git")
+                .args(["clone", "--quiet", "--depth", "1"])
+                .arg(source)
+                .arg(dest),
+        )?;
+        return Ok(());
+    };
+    let git = || {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(dest);
+        cmd
+    };
+    run_git(Command::new("git").args(["init", "--quiet"]).arg(dest))?;
+    run_git(git().args(["remote", "add", "origin", source]))?;
+    let fetched = run_git(git().args(["fetch", "--quiet", "--depth", "1", "origin", version]));
+    let checkout = match fetched {
+        Ok(_) => "FETCH_HEAD",
+        Err(_) => {
+            run_git(git().args(["fetch", "--quiet", "--tags", "origin"]))?;
+            version
+        }
+    };
+    run_git(git().args(["checkout", "--quiet", checkout]))?;
+    Ok(())
+}
+
+fn install_file(from: &Path, dest: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| install_error(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    fs::copy(from, dest).map_err(|e| {
+        install_error(format!(
+            "Failed to copy {} to {}: {}",
+            from.display(),
+            dest.display(),
+            e
+        ))
+    })?;
+    logi!("Installed {}", dest.display());
+    files.push(dest.to_path_buf());
+    Ok(())
+}
+
+// Copy the release artifacts of a built project below prefix, binaries to bin/,
+// libraries to lib/ and the headers of libraries to include/, then record them in the
+// manifest. Installs staged with DESTDIR aren't recorded.
+pub fn install_artifacts(config: &BuildConfig, name: &str, prefix: &Path) -> Result<(), Error> {
+    let root = staged_prefix(prefix);
+    let mut files = Vec::new();
+    for subproject in &config.subprojects {
+        let dir = match subproject.r#type {
+            SubProjectType::Binary => "bin",
+            SubProjectType::Test => continue,
+            _ => "lib",
+        };
+        let target = config.default_target();
+        let artifacts = artifact_path(subproject, target, &BuildMode::Release)
+            .into_iter()
+            .chain(shared_library_path(subproject, target, &BuildMode::Release));
+        for artifact in artifacts {
+            let file_name = artifact.file_name().unwrap_or_default();
+            install_file(&artifact, &root.join(dir).join(file_name), &mut files)?;
+        }
+        if subproject.r#type == SubProjectType::Binary {
+            continue;
+        }
+        for include_dir in subproject.include_dirs.iter().flatten() {
+            let include_dir = Path::new(include_dir.get_ref());
+            let headers =
+                glob::expand(&[format!("{}/**/*", include_dir.display())]).map_err(|e| {
+                    install_error(format!("Failed to read {}: {}", include_dir.display(), e))
+                })?;
+            for header in headers {
+                let relative = header.strip_prefix(include_dir).unwrap_or(&header);
+                install_file(&header, &root.join("include").join(relative), &mut files)?;
+            }
+        }
+    }
+
+    if root != prefix {
+        return Ok(());
+    }
+    let mut manifest = InstallManifest::load()?;
+    manifest.record(InstalledPackage {
+        name: name.to_string(),
+        version: config.build.version.clone(),
+        files,
+    });
+    manifest.save()
+}
+
+// A failed publish, with the exit code to leave with, git's own when git failed
+pub struct PublishError {
+    pub error: Error,
+    pub exit_code: i32,
+}
+
+fn publish_error(message: String) -> PublishError {
+    PublishError {
+        error: Error {
+            error_type: ErrorType::PublishFailed,
+            message,
+            span: None,
+            additional_info: None,
+        },
+        exit_code: 1,
+    }
+}
+
+// Run a git step of a publish
+fn publish_step(args: &[&str]) -> Result<String, PublishError> {
+    logv!("git {}", args.join(" "));
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| publish_error(format!("Failed to run git: {}", e)))?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
+    }
+    let mut failure = publish_error(format!(
+        "git {} failed with {}: {}",
+        args[0],
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ));
+    failure.exit_code = output.status.code().unwrap_or(1);
+    Err(failure)
+}
+
+// Tag the current commit with the version in the config, v<version>, and push the
+// tag to remote if given. The working tree must be clean and an existing tag is only
+// moved with force.
+pub fn publish(
+    config: &BuildConfig,
+    remote: Option<&str>,
+    force: bool,
+) -> Result<(), PublishError> {
+    let tag = format!("v{}", config.build.version);
+    if !publish_step(&["status", "--porcelain"])?.is_empty() {
+        return Err(publish_error(
+            "The working tree has uncommitted changes, commit or stash them first".to_string(),
+        ));
+    }
+
+    let tag_ref = format!("refs/tags/{}", tag);
+    let exists = Command::new("git")
+        .args(["rev-parse", "--quiet", "--verify", &tag_ref])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if exists && !force {
+        return Err(publish_error(format!(
+            "Version {} is already tagged as {}, pass --force to move the tag",
+            config.build.version, tag
+        )));
+    }
+    let message = format!("Release {}", tag);
+    let mut args = vec!["tag", "--annotate", &tag, "--message", &message];
+    if force {
+        args.push("--force");
+    }
+    publish_step(&args)?;
+    logi!("Tagged the current commit as {}", tag);
+
+    if let Some(remote) = remote {
+        let mut args = vec!["push", remote, &tag_ref];
+        if force {
+            args.push("--force");
+        }
+        publish_step(&args)?;
+        logi!("Pushed {} to {}", tag, remote);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A repository with two commits, the first tagged v1.0
+    fn repository(dir: &Path) -> (String, String) {
+        let git = |args: &[&str]| {
+            run_git(
+                Command::new("git")
+                    .arg("-C")
+                    .arg(dir)
+                    .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                    .args(args),
+            )
+            .unwrap()
+        };
+        git(&["init", "--quiet"]);
+        git(&["commit", "--quiet", "--allow-empty", "--message", "first"]);
+        git(&["tag", "v1.0"]);
+        let first = git(&["rev-parse", "HEAD"]);
+        git(&["commit", "--quiet", "--allow-empty", "--message", "second"]);
+        (first, git(&["rev-parse", "HEAD"]))
+    }
+
+    #[test]
+    fn exact_refs_are_fetched_and_checked_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("origin");
+        fs::create_dir(&origin).unwrap();
+        let (first, second) = repository(&origin);
+        let source = origin.to_str().unwrap();
+        let cases = [
+            ("hash", Some(first.as_str()), &first),
+            ("short", Some(&first[..7]), &first),
+            ("tag", Some("v1.0"), &first),
+            ("default", None, &second),
+        ];
+        for (name, version, expected) in cases {
+            let dest = dir.path().join(name);
+            fetch_remote(source, version, &dest).unwrap();
+            assert_eq!(&head_commit(&dest).unwrap(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn failed_fetches_keep_the_previous_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("origin");
+        fs::create_dir(&origin).unwrap();
+        let (first, second) = repository(&origin);
+        let source = origin.to_str().unwrap();
+        let dest = dir.path().join("deps/lib");
+        replace_checkout(source, Some(&first), &dest).unwrap();
+        assert!(replace_checkout(source, Some("no-such-ref"), &dest).is_err());
+        assert_eq!(head_commit(&dest).unwrap(), first);
+        replace_checkout(source, None, &dest).unwrap();
+        assert_eq!(head_commit(&dest).unwrap(), second);
+        // Nothing is left behind next to the checkout
+        assert_eq!(fs::read_dir(dir.path().join("deps")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn versions_that_are_not_requirements_resolve_to_themselves() {
+        let config = BuildConfig::from_source(
+            r#"
+subprojects = []
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[dependencies]
+remote = [{ name = "lib", source = "https://example.com/lib.git", version = "1234567", include_dirs = [] }]
+"#,
+        )
+        .unwrap();
+        let remote = config.dependencies.remote[0].get_ref();
+        // Resolving a requirement would list the tags of the unreachable source
+        assert_eq!(
+            resolve_version(remote, false).unwrap().as_deref(),
+            Some("1234567")
+        );
+    }
+}
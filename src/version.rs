@@ -0,0 +1,115 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use semver::{Version, VersionReq};
+
+// A version requirement such as `^1.2` or `>=1.2, <2.0`, with Cargo's semantics, so a
+// bare version like `1.2` is a caret requirement. A bare number is left to be a git ref,
+// it may well be an abbreviated commit hash.
+pub fn parse_requirement(text: &str) -> Option<VersionReq> {
+    let text = text.trim();
+    if !is_requirement_like(text) && !text.contains('.') {
+        return None;
+    }
+    VersionReq::parse(text).ok()
+}
+
+// Whether text was meant as a requirement rather than a git ref, even if it fails to parse
+pub fn is_requirement_like(text: &str) -> bool {
+    let text = text.trim();
+    text.contains(',') || text.contains('*') || text.starts_with(['^', '~', '>', '<', '='])
+}
+
+// The version a git tag like `v1.2.3` names. Tags may leave out the minor and patch
+// numbers, `v1.0` is version 1.0.0.
+pub fn tag_version(tag: &str) -> Option<Version> {
+    let tag = tag.strip_prefix('v').unwrap_or(tag);
+    let core_end = tag.find(['-', '+']).unwrap_or(tag.len());
+    let (core, rest) = tag.split_at(core_end);
+    let padding = match core.matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => "",
+    };
+    Version::parse(&format!("{}{}{}", core, padding, rest)).ok()
+}
+
+// The highest of the tags matching the requirement. Pre-releases only match requirements
+// naming a pre-release of the same version.
+pub fn best_match<'a>(
+    requirement: &VersionReq,
+    tags: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    tags.into_iter()
+        .filter_map(|tag| tag_version(tag).map(|version| (version, tag)))
+        .filter(|(version, _)| requirement.matches(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(requirement: &str, tags: &[&'static str]) -> Option<&'static str> {
+        best_match(
+            &parse_requirement(requirement).unwrap(),
+            tags.iter().copied(),
+        )
+    }
+
+    #[test]
+    fn refs_are_not_requirements() {
+        for git_ref in ["1234567", "main", "v1.2.3", "a1b2c3d", "release/1.x"] {
+            assert!(parse_requirement(git_ref).is_none(), "{}", git_ref);
+        }
+        assert!(parse_requirement("1.2").is_some());
+        assert!(parse_requirement("=3").is_some());
+    }
+
+    #[test]
+    fn highest_matching_tag_is_selected() {
+        let tags = ["v1.1.0", "v1.2.0", "v1.10.0", "v2.0.0", "not-a-version"];
+        assert_eq!(best(">=1.2, <2.0", &tags), Some("v1.10.0"));
+        assert_eq!(best("^1.1", &tags), Some("v1.10.0"));
+        assert_eq!(best("~1.1", &tags), Some("v1.1.0"));
+        assert_eq!(best("*", &tags), Some("v2.0.0"));
+        assert_eq!(best(">=3", &tags), None);
+    }
+
+    #[test]
+    fn tags_without_minor_or_patch_match() {
+        assert_eq!(best("^1", &["1.0", "v0.9"]), Some("1.0"));
+        assert_eq!(best("=2.0.0", &["v2"]), Some("v2"));
+        assert_eq!(
+            tag_version("v1.2-rc.1"),
+            Some(Version::parse("1.2.0-rc.1").unwrap())
+        );
+    }
+
+    #[test]
+    fn pre_releases_order_numerically_and_only_match_when_asked_for() {
+        let tags = ["v1.0.0-rc.2", "v1.0.0-rc.10", "v0.9.0"];
+        assert_eq!(best(">=1.0.0-rc.1", &tags), Some("v1.0.0-rc.10"));
+        assert_eq!(best("<2", &tags), Some("v0.9.0"));
+        assert_eq!(
+            best(">=1.0.0-rc.1", &["v1.0.0-rc.10", "v1.0.0"]),
+            Some("v1.0.0")
+        );
+    }
+}
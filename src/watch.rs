@@ -0,0 +1,209 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use crate::build_config::{BuildConfig, CONFIG_FILE};
+
+// How long the watched files have to stay unchanged after a change before a build
+// starts, so saving several files builds once
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// Whatever a build reads: the config, the source and include directories of the
+// subprojects and the source directories of the custom build rules. Without a valid
+// config only the config itself is watched.
+pub fn watched_paths(config: Option<&BuildConfig>) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+    let Some(config) = config else {
+        return paths;
+    };
+    for subproject in &config.subprojects {
+        paths.extend(
+            subproject
+                .source_dirs()
+                .into_iter()
+                .map(|dir| dir.get_ref().into()),
+        );
+        paths.extend(
+            subproject
+                .include_dirs
+                .iter()
+                .flatten()
+                .map(|dir| dir.get_ref().into()),
+        );
+        // Files picked by sources patterns may live anywhere
+        if subproject.sources.is_some() {
+            paths.extend(subproject.source_files().into_iter().flatten());
+        }
+    }
+    for rule in config.custom_build_rules.iter().flatten() {
+        paths.push(rule.src_dir.get_ref().into());
+    }
+    paths
+}
+
+// Outputs of custom build rules are written by the build, watching them would start
+// another one
+fn ignored_dirs(config: Option<&BuildConfig>) -> Vec<PathBuf> {
+    config
+        .into_iter()
+        .flat_map(|config| config.custom_build_rules.iter().flatten())
+        .map(|rule| PathBuf::from(rule.output_dir.get_ref()))
+        .collect()
+}
+
+// Watches the paths a build reads, created before the build so changes made while it
+// runs start the next one
+pub struct Watcher {
+    // Kept alive for its events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    // Single files are watched through their directory, which survives editors replacing
+    // them, so events in it are filtered by these
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    ignored: Vec<PathBuf>,
+    root: PathBuf,
+}
+
+impl Watcher {
+    pub fn new(config: Option<&BuildConfig>) -> notify::Result<Self> {
+        let root = env::current_dir()?;
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for path in watched_paths(config) {
+            let path = root.join(path);
+            if path.is_dir() {
+                watcher.watch(&path, RecursiveMode::Recursive)?;
+                dirs.push(path);
+            } else if let Some(parent) = path.parent().filter(|parent| parent.is_dir()) {
+                // Missing files are watched too, creating one is a change
+                watcher.watch(parent, RecursiveMode::NonRecursive)?;
+                files.push(path);
+            }
+        }
+        let ignored = ignored_dirs(config)
+            .into_iter()
+            .map(|dir| root.join(dir))
+            .collect();
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            files,
+            dirs,
+            ignored,
+            root,
+        })
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        !self.ignored.iter().any(|dir| path.starts_with(dir))
+            && (self.files.iter().any(|file| file == path)
+                || self.dirs.iter().any(|dir| path.starts_with(dir)))
+    }
+
+    // Watched files changed by an event, relative to the project root when inside it
+    fn changed_files(&self, event: notify::Result<notify::Event>) -> Vec<PathBuf> {
+        let Ok(event) = event else {
+            return Vec::new();
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return Vec::new();
+        }
+        event
+            .paths
+            .into_iter()
+            .filter(|path| self.is_watched(path))
+            .map(|path| match path.strip_prefix(&self.root) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => path,
+            })
+            .collect()
+    }
+
+    // The files changed by the first change within `timeout`, or forever without one,
+    // once they settle
+    fn next_change(&self, timeout: Option<Duration>) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        while changed.is_empty() {
+            let event = match timeout {
+                Some(timeout) => self.events.recv_timeout(timeout).ok(),
+                None => self.events.recv().ok(),
+            };
+            let Some(event) = event else {
+                return changed;
+            };
+            changed = self.changed_files(event);
+        }
+        while let Ok(event) = self.events.recv_timeout(SETTLE_TIME) {
+            changed.extend(self.changed_files(event));
+        }
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+
+    // Block until a watched file changes and the files settle, returning the changed files
+    pub fn wait_for_change(&self) -> Vec<PathBuf> {
+        self.next_change(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn writing_a_source_is_one_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().canonicalize().unwrap().join("src");
+        fs::create_dir(&src).unwrap();
+        let main = src.join("main.c");
+        fs::write(&main, "int main(void) { return 0; }\n").unwrap();
+        let config = BuildConfig::from_source(&format!(
+            r#"
+[build]
+version = "0.1.0"
+c_standard = "c11"
+compiler = "cc"
+
+[[subprojects]]
+name = "app"
+type = "binary"
+src_dir = "{}"
+"#,
+            src.display()
+        ))
+        .unwrap();
+        let watcher = Watcher::new(Some(&config)).unwrap();
+        // An editor saving may write a file more than once
+        fs::write(&main, "int main(void) { return 1; }\n").unwrap();
+        fs::write(&main, "int main(void) { return 2; }\n").unwrap();
+        assert_eq!(watcher.next_change(Some(Duration::from_secs(5))), [main]);
+        assert!(watcher.next_change(Some(SETTLE_TIME * 2)).is_empty());
+    }
+}
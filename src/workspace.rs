@@ -0,0 +1,409 @@
+/*
+* Copyright (c) 2024, Dr. Spandan Roy
+*
+* This file is part of iceforge.
+*
+* iceforge is free software: you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* iceforge is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with iceforge.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use toml::Spanned;
+
+use crate::{
+    build::{artifact_path, shell_quote, BuildMode},
+    build_config::{BuildConfig, ManualDependency, SubProject, SubProjectType, CONFIG_FILE},
+    error::{AdditionalInfo, Error, ErrorType},
+};
+
+// A top-level iceforge.toml holding `[workspace] members = [...]` instead of a project
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkspaceFile {
+    workspace: WorkspaceSection,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkspaceSection {
+    members: Vec<Spanned<String>>,
+}
+
+// A project of a workspace, its config verified in its own directory
+pub struct Member {
+    pub name: String,
+    pub dir: PathBuf,
+    pub config: BuildConfig,
+}
+
+pub struct Workspace {
+    // Members in build order, after the members whose libraries they use
+    pub members: Vec<Member>,
+}
+
+// Errors found in one of the config files of a workspace
+pub struct ConfigErrors {
+    pub path: String,
+    pub source: String,
+    pub errors: Vec<Error>,
+}
+
+pub fn is_workspace(source: &str) -> bool {
+    source
+        .parse::<toml::Table>()
+        .is_ok_and(|table| table.contains_key("workspace"))
+}
+
+// Run f with dir as the current directory, config paths are relative to the project root
+pub fn in_dir<T>(dir: &Path, f: impl FnOnce() -> T) -> Result<T, Error> {
+    let dir_error = |dir: &Path, e: std::io::Error| Error {
+        error_type: ErrorType::ConfigReadError,
+        message: format!("Failed to enter {}: {}", dir.display(), e),
+        span: None,
+        additional_info: None,
+    };
+    let previous = env::current_dir().map_err(|e| dir_error(Path::new("."), e))?;
+    env::set_current_dir(dir).map_err(|e| dir_error(dir, e))?;
+    let result = f();
+    env::set_current_dir(&previous).map_err(|e| dir_error(&previous, e))?;
+    Ok(result)
+}
+
+fn member_error(message: String, span: Range<usize>) -> Error {
+    Error {
+        error_type: ErrorType::InvalidWorkspaceMember,
+        message,
+        span: Some(span),
+        additional_info: None,
+    }
+}
+
+// Libraries of a member a subproject of another member can depend on
+fn is_library(subproject: &SubProject) -> bool {
+    matches!(
+        subproject.r#type,
+        SubProjectType::Library | SubProjectType::HeaderOnly
+    )
+}
+
+// Names a subproject depends on that the member itself doesn't define
+fn external_names(config: &BuildConfig) -> Vec<&str> {
+    let mut names = Vec::new();
+    for dep in config
+        .subprojects
+        .iter()
+        .flat_map(|subproject| subproject.dependencies.iter().flatten())
+    {
+        let name = dep.get_ref().name();
+        let local = config
+            .subprojects
+            .iter()
+            .any(|subproject| subproject.name.get_ref() == name)
+            || config.dependencies.has_dependency(name)
+            || config.other_platform_dependencies.has_dependency(name);
+        if !local && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// A library of another member as a manual dependency: the include_dirs of the library
+// and of the subprojects and manual dependencies it uses in its member, and their
+// artifacts and ldflags. Remote and pkg-config dependencies of the library aren't
+// carried over.
+fn library_dependency(
+    member: &Member,
+    library: &SubProject,
+    target: Option<&str>,
+    mode: &BuildMode,
+) -> ManualDependency {
+    let config = &member.config;
+    let mut include_dirs: Vec<String> = Vec::new();
+    let mut ldflags = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![library];
+    while let Some(subproject) = pending.pop() {
+        if !visited.insert(subproject.name.get_ref().as_str()) {
+            continue;
+        }
+        for dir in subproject.include_dirs.iter().flatten() {
+            include_dirs.push(member.dir.join(dir.get_ref()).display().to_string());
+        }
+        let target = target.or(config.default_target());
+        if let Some(artifact) = artifact_path(subproject, target, mode) {
+            ldflags.push(shell_quote(
+                &member.dir.join(artifact).display().to_string(),
+            ));
+        }
+        for dep in subproject.dependencies.iter().flatten() {
+            let name = dep.get_ref().name();
+            if let Some(dep) = config
+                .subprojects
+                .iter()
+                .find(|subproject| subproject.name.get_ref() == name)
+            {
+                pending.push(dep);
+            } else if let Some(manual) = config
+                .dependencies
+                .manual
+                .iter()
+                .find(|manual| manual.get_ref().name.get_ref() == name)
+            {
+                let manual = manual.get_ref();
+                if visited.insert(manual.name.get_ref().as_str()) {
+                    for dir in manual.include_dirs.iter().flatten() {
+                        include_dirs.push(member.dir.join(dir.get_ref()).display().to_string());
+                    }
+                    ldflags.extend(manual.ldflags.clone());
+                }
+            }
+        }
+    }
+    include_dirs.dedup();
+    ManualDependency {
+        name: Spanned::new(0..0, library.name.get_ref().clone()),
+        cflags: None,
+        ldflags: Some(ldflags.join(" ")),
+        include_dirs: Some(
+            include_dirs
+                .into_iter()
+                .map(|dir| Spanned::new(0..0, dir))
+                .collect(),
+        ),
+        lib_dirs: None,
+        platforms: None,
+    }
+}
+
+impl Workspace {
+    // Load and verify every member from the current directory, the workspace root. The
+    // libraries of other members a member uses are added to it as manual dependencies
+    // on their artifacts for the given target and mode.
+    pub fn load(
+        source: &str,
+        target: Option<&str>,
+        mode: &BuildMode,
+    ) -> Result<Self, Vec<ConfigErrors>> {
+        let errors_in = |path: &str, source: &str, errors: Vec<Error>| ConfigErrors {
+            path: path.to_string(),
+            source: source.to_string(),
+            errors,
+        };
+        let root_errors = |errors| vec![errors_in(CONFIG_FILE, source, errors)];
+        let file: WorkspaceFile = toml::from_str(source).map_err(|e| {
+            root_errors(vec![Error {
+                error_type: ErrorType::TomlParseError,
+                message: e.to_string(),
+                span: e.span(),
+                additional_info: None,
+            }])
+        })?;
+        let root = env::current_dir().map_err(|e| {
+            root_errors(vec![Error {
+                error_type: ErrorType::ConfigReadError,
+                message: format!("Failed to read the current directory: {}", e),
+                span: None,
+                additional_info: None,
+            }])
+        })?;
+
+        let mut errors = Vec::new();
+        let mut seen: HashMap<&str, Range<usize>> = HashMap::new();
+        for member in &file.workspace.members {
+            if let Some(previous) = seen.get(member.get_ref().as_str()) {
+                errors.push(Error {
+                    additional_info: Some(AdditionalInfo {
+                        span: previous.clone(),
+                        message: "Previously listed here".to_string(),
+                    }),
+                    ..member_error(
+                        format!("Duplicate workspace member {}", member.get_ref()),
+                        member.span(),
+                    )
+                });
+            } else if !root.join(member.get_ref()).join(CONFIG_FILE).is_file() {
+                errors.push(member_error(
+                    format!(
+                        "Workspace member {} has no {}",
+                        member.get_ref(),
+                        CONFIG_FILE
+                    ),
+                    member.span(),
+                ));
+            }
+            seen.entry(member.get_ref()).or_insert(member.span());
+        }
+        if !errors.is_empty() {
+            return Err(root_errors(errors));
+        }
+
+        // Parsed in their own directory, which ${PROJECT_ROOT} refers to
+        let mut file_errors = Vec::new();
+        let mut members = Vec::new();
+        let mut sources = Vec::new();
+        for member in &file.workspace.members {
+            let dir = root.join(member.get_ref());
+            let path = Path::new(member.get_ref())
+                .join(CONFIG_FILE)
+                .display()
+                .to_string();
+            let source = match BuildConfig::read_source(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    file_errors.push(errors_in(&path, "", vec![e]));
+                    continue;
+                }
+            };
+            match in_dir(&dir, || BuildConfig::from_source(&source)).and_then(|result| result) {
+                Ok(config) => members.push(Member {
+                    name: member.get_ref().clone(),
+                    dir,
+                    config,
+                }),
+                Err(e) => file_errors.push(errors_in(&path, &source, vec![e])),
+            }
+            sources.push((path, source));
+        }
+        if !file_errors.is_empty() {
+            return Err(file_errors);
+        }
+
+        // Subproject names are shared by the whole workspace, like in a single project
+        let mut owners: HashMap<&str, usize> = HashMap::new();
+        for (index, member) in members.iter().enumerate() {
+            let mut errors = Vec::new();
+            for subproject in &member.config.subprojects {
+                let name = subproject.name.get_ref().as_str();
+                match owners.get(name) {
+                    Some(&owner) if owner != index => errors.push(Error {
+                        error_type: ErrorType::DuplicateSubprojectName,
+                        message: format!(
+                            "Subproject {} is also defined in workspace member {}",
+                            name, members[owner].name
+                        ),
+                        span: Some(subproject.name.span()),
+                        additional_info: None,
+                    }),
+                    _ => {
+                        owners.insert(name, index);
+                    }
+                }
+            }
+            if !errors.is_empty() {
+                let (path, source) = &sources[index];
+                file_errors.push(errors_in(path, source, errors));
+            }
+        }
+        if !file_errors.is_empty() {
+            return Err(file_errors);
+        }
+
+        // A member is built after the members owning the libraries it depends on
+        let uses: Vec<Vec<(String, usize)>> = members
+            .iter()
+            .map(|member| {
+                external_names(&member.config)
+                    .into_iter()
+                    .filter_map(|name| {
+                        let owner = *owners.get(name)?;
+                        let library = members[owner].config.subprojects.iter().any(|subproject| {
+                            subproject.name.get_ref() == name && is_library(subproject)
+                        });
+                        library.then(|| (name.to_string(), owner))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut order: Vec<usize> = Vec::new();
+        while order.len() < members.len() {
+            let next = (0..members.len()).find(|index| {
+                !order.contains(index)
+                    && uses[*index].iter().all(|(_, owner)| order.contains(owner))
+            });
+            let Some(next) = next else {
+                let cycle: Vec<&str> = (0..members.len())
+                    .filter(|index| !order.contains(index))
+                    .map(|index| members[index].name.as_str())
+                    .collect();
+                let first = (0..members.len())
+                    .find(|index| !order.contains(index))
+                    .unwrap_or_default();
+                return Err(root_errors(vec![Error {
+                    error_type: ErrorType::CircularDependency,
+                    message: format!(
+                        "Workspace members {} depend on each other's libraries",
+                        cycle.join(", ")
+                    ),
+                    span: Some(file.workspace.members[first].span()),
+                    additional_info: None,
+                }]));
+            };
+            order.push(next);
+        }
+
+        let mut slots: Vec<Option<Member>> = members.into_iter().map(Some).collect();
+        let mut ordered: Vec<Member> = Vec::new();
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+        for index in order {
+            let Some(mut member) = slots[index].take() else {
+                continue;
+            };
+            for (name, owner) in &uses[index] {
+                let owner = &ordered[positions[owner]];
+                let library = owner
+                    .config
+                    .subprojects
+                    .iter()
+                    .find(|subproject| subproject.name.get_ref() == name);
+                if let Some(library) = library {
+                    let dep = library_dependency(owner, library, target, mode);
+                    member
+                        .config
+                        .dependencies
+                        .manual
+                        .push(Spanned::new(0..0, dep));
+                }
+            }
+            let verified = in_dir(&member.dir, || member.config.verify_config())
+                .map_err(|e| vec![e])
+                .and_then(|result| result);
+            if let Err(errors) = verified {
+                let (path, source) = &sources[index];
+                file_errors.push(errors_in(path, source, errors));
+            }
+            positions.insert(index, ordered.len());
+            ordered.push(member);
+        }
+        if !file_errors.is_empty() {
+            return Err(file_errors);
+        }
+        Ok(Workspace { members: ordered })
+    }
+
+    // The member defining a subproject
+    pub fn member_of(&self, subproject: &str) -> Option<usize> {
+        self.members.iter().position(|member| {
+            member
+                .config
+                .subprojects
+                .iter()
+                .any(|candidate| candidate.name.get_ref() == subproject)
+        })
+    }
+}